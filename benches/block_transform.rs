@@ -0,0 +1,73 @@
+use bitvec::prelude::*;
+use blind_watermark::prelude::*;
+use blind_watermark::transform::block4::{Mat4, Svd4, dct4};
+use blind_watermark::transform::dct::dct2_2d;
+use criterion::{Criterion, criterion_group, criterion_main};
+use image::{DynamicImage, RgbImage};
+use std::hint::black_box;
+
+/// Textured 4000×3000 (12 MP) image
+fn image_12mp() -> DynamicImage {
+    DynamicImage::from(RgbImage::from_fn(4000, 3000, |x, y| {
+        let noise = (x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503)) >> 24;
+        image::Rgb([
+            ((x / 7 + noise) % 256) as u8,
+            ((y / 5 + noise / 2) % 256) as u8,
+            ((x + y) / 11 % 256) as u8,
+        ])
+    }))
+}
+
+/// DCT and SVD of a single block, general `faer` path against the fixed-size one
+fn bench_block(c: &mut Criterion) {
+    let block: Mat4 = [
+        [0.52, 0.48, 0.61, 0.7],
+        [0.33, 0.9, 0.12, 0.41],
+        [0.05, 0.27, 0.77, 0.66],
+        [0.95, 0.14, 0.38, 0.2],
+    ];
+    let mat = faer::Mat::from_fn(4, 4, |r, c| block[r][c]);
+
+    let mut group = c.benchmark_group("block");
+    group.bench_function("faer", |b| {
+        b.iter(|| dct2_2d(black_box(mat.as_ref())).svd().unwrap())
+    });
+    group.bench_function("fixed", |b| b.iter(|| Svd4::new(&dct4(black_box(&block)))));
+    group.finish();
+}
+
+fn bench_12mp(c: &mut Criterion) {
+    let img = image_12mp();
+    let watermark = b"benchmark".view_bits::<Lsb0>();
+    let config = config_from_seed(Some(1)).unwrap();
+    let watermarked = embed_watermark_image(&img, watermark, &config);
+
+    let mut group = c.benchmark_group("12mp");
+    group.sample_size(10);
+    group.bench_function("embed", |b| {
+        b.iter(|| embed_watermark_image(black_box(&img), watermark, &config))
+    });
+    group.bench_function("extract", |b| {
+        b.iter(|| extract_watermark_image(black_box(&watermarked), watermark.len(), &config))
+    });
+    group.finish();
+}
+
+/// Extraction of a 2 KB payload, where voting dominates for long watermarks
+fn bench_long_payload(c: &mut Criterion) {
+    let img = image_12mp();
+    let payload: Vec<u8> = (0..2048u32).map(|i| (i * 37 % 251) as u8).collect();
+    let watermark = payload.view_bits::<Lsb0>();
+    let config = config_from_seed(Some(1)).unwrap();
+    let watermarked = embed_watermark_image(&img, watermark, &config);
+
+    let mut group = c.benchmark_group("12mp_2kb");
+    group.sample_size(10);
+    group.bench_function("extract", |b| {
+        b.iter(|| extract_watermark_image(black_box(&watermarked), watermark.len(), &config))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_block, bench_12mp, bench_long_payload);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+use blind_watermark::YCrBrAMat;
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use image::{DynamicImage, RgbImage};
+
+/// Textured image of the given size
+fn image(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::from(RgbImage::from_fn(width, height, |x, y| {
+        let noise = (x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503)) >> 24;
+        image::Rgb([
+            ((x / 7 + noise) % 256) as u8,
+            ((y / 5 + noise / 2) % 256) as u8,
+            ((x + y) / 11 % 256) as u8,
+        ])
+    }))
+}
+
+fn bench_dwt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dwt");
+    group.sample_size(20);
+    for (name, width, height) in [("4k", 3840, 2160), ("8k", 7680, 4320)] {
+        let ycbcr: YCrBrAMat = image(width, height).to_rgba32f().into();
+        let padded = ycbcr.add_padding();
+        let dwted = padded.clone().dwt();
+
+        group.bench_function(format!("{name}/forward"), |b| {
+            b.iter_batched(|| padded.clone(), |p| p.dwt(), BatchSize::LargeInput)
+        });
+        group.bench_function(format!("{name}/inverse"), |b| {
+            b.iter_batched(|| dwted.clone(), |d| d.idwt(), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dwt);
+criterion_main!(benches);
@@ -0,0 +1,367 @@
+use anyhow::{Context, Result, bail};
+use bitvec::prelude::*;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    config::WatermarkConfig,
+    report::ExtractionReport,
+    utils::{embed_watermark_image, verify_watermark_image},
+};
+
+/// Seed of the random number generator used by the noise attacks.
+const ATTACK_SEED: u64 = 0;
+
+/// A distortion applied to a watermarked image to measure robustness.
+///
+/// Every attack returns an image with the same dimensions as its input, since extraction
+/// expects the original geometry. Geometric attacks therefore undo the geometry change
+/// after applying it, which leaves only the interpolation and cropping losses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attack {
+    /// JPEG re-encoding at the given quality (1-100).
+    Jpeg(u8),
+    /// Resize by the given factor, then back to the original size.
+    Resize(f32),
+    /// Keep a centered region covering the given fraction of each side, black out the rest.
+    Crop(f32),
+    /// Rotate by the given angle in degrees, then back.
+    Rotate(f32),
+    /// Additive Gaussian noise with the given standard deviation, in 8-bit levels.
+    GaussianNoise(f32),
+    /// Gaussian blur with the given standard deviation, in pixels.
+    Blur(f32),
+    /// Add the given offset to every channel, in 8-bit levels.
+    Brightness(i32),
+    /// Adjust contrast by the given percentage.
+    Contrast(f32),
+    /// Multiply every channel by the given factor, e.g. an exposure change.
+    Gain(f32),
+    /// Replace the given fraction of pixels with black or white.
+    SaltAndPepper(f32),
+    /// Median filter with the given radius.
+    Median(u32),
+    /// Nearest-neighbour downscale by the given factor and smooth upscale back,
+    /// similar to taking a screenshot of a resized view.
+    Screenshot(f32),
+}
+
+impl Attack {
+    /// A default set of attacks covering every kind at moderate strength.
+    pub fn default_suite() -> Vec<Attack> {
+        vec![
+            Attack::Jpeg(90),
+            Attack::Jpeg(75),
+            Attack::Jpeg(50),
+            Attack::Resize(0.5),
+            Attack::Resize(1.5),
+            Attack::Crop(0.75),
+            Attack::Rotate(5.0),
+            Attack::GaussianNoise(5.0),
+            Attack::Blur(1.0),
+            Attack::Brightness(20),
+            Attack::Contrast(20.0),
+            Attack::Gain(1.2),
+            Attack::SaltAndPepper(0.01),
+            Attack::Median(1),
+            Attack::Screenshot(0.8),
+        ]
+    }
+
+    /// Applies the attack to an image, returning an 8-bit RGB image of the same size.
+    pub fn apply(&self, img: &DynamicImage) -> Result<DynamicImage> {
+        let (width, height) = img.dimensions();
+        let rgb = DynamicImage::from(img.to_rgb8());
+        let attacked = match *self {
+            Attack::Jpeg(quality) => {
+                let mut buffer = Vec::new();
+                rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, quality))?;
+                image::load_from_memory(&buffer)?
+            }
+            Attack::Resize(scale) => {
+                let (w, h) = scaled_dimensions(width, height, scale)?;
+                rgb.resize_exact(w, h, FilterType::Lanczos3).resize_exact(
+                    width,
+                    height,
+                    FilterType::Lanczos3,
+                )
+            }
+            Attack::Crop(ratio) => {
+                if !(0.0..=1.0).contains(&ratio) {
+                    bail!("crop ratio must be within [0, 1]");
+                }
+                let (w, h) = scaled_dimensions(width, height, ratio)?;
+                let (x0, y0) = ((width - w) / 2, (height - h) / 2);
+                let mut out = RgbImage::new(width, height);
+                for (x, y, pixel) in rgb.to_rgb8().enumerate_pixels() {
+                    if (x0..x0 + w).contains(&x) && (y0..y0 + h).contains(&y) {
+                        out.put_pixel(x, y, *pixel);
+                    }
+                }
+                out.into()
+            }
+            Attack::Rotate(degrees) => {
+                let rotated = rotate(&rgb.to_rgb8(), degrees);
+                rotate(&rotated, -degrees).into()
+            }
+            Attack::GaussianNoise(sigma) => {
+                let mut rng = Pcg64::seed_from_u64(ATTACK_SEED);
+                let mut out = rgb.to_rgb8();
+                for value in out.iter_mut() {
+                    let noise = sigma * standard_normal(&mut rng);
+                    *value = (*value as f32 + noise).round().clamp(0.0, 255.0) as u8;
+                }
+                out.into()
+            }
+            Attack::Blur(sigma) => rgb.blur(sigma),
+            Attack::Brightness(offset) => rgb.brighten(offset),
+            Attack::Contrast(percent) => rgb.adjust_contrast(percent),
+            Attack::Gain(gain) => {
+                if gain < 0.0 {
+                    bail!("gain must be non-negative");
+                }
+                let mut out = rgb.to_rgb8();
+                for value in out.iter_mut() {
+                    *value = (*value as f32 * gain).round().clamp(0.0, 255.0) as u8;
+                }
+                out.into()
+            }
+            Attack::SaltAndPepper(density) => {
+                let mut rng = Pcg64::seed_from_u64(ATTACK_SEED);
+                let mut out = rgb.to_rgb8();
+                for pixel in out.pixels_mut() {
+                    if rng.random::<f32>() < density {
+                        *pixel = if rng.random::<bool>() {
+                            Rgb([255, 255, 255])
+                        } else {
+                            Rgb([0, 0, 0])
+                        };
+                    }
+                }
+                out.into()
+            }
+            Attack::Median(radius) => median_filter(&rgb.to_rgb8(), radius).into(),
+            Attack::Screenshot(scale) => {
+                let (w, h) = scaled_dimensions(width, height, scale)?;
+                rgb.resize_exact(w, h, FilterType::Nearest).resize_exact(
+                    width,
+                    height,
+                    FilterType::Triangle,
+                )
+            }
+        };
+        Ok(attacked.to_rgb8().into())
+    }
+}
+
+impl fmt::Display for Attack {
+    /// Formats the attack in the `name:parameter` form accepted by [`Attack::from_str`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Attack::Jpeg(quality) => write!(f, "jpeg:{quality}"),
+            Attack::Resize(scale) => write!(f, "resize:{scale}"),
+            Attack::Crop(ratio) => write!(f, "crop:{ratio}"),
+            Attack::Rotate(degrees) => write!(f, "rotate:{degrees}"),
+            Attack::GaussianNoise(sigma) => write!(f, "noise:{sigma}"),
+            Attack::Blur(sigma) => write!(f, "blur:{sigma}"),
+            Attack::Brightness(offset) => write!(f, "brightness:{offset}"),
+            Attack::Contrast(percent) => write!(f, "contrast:{percent}"),
+            Attack::Gain(gain) => write!(f, "gain:{gain}"),
+            Attack::SaltAndPepper(density) => write!(f, "salt-pepper:{density}"),
+            Attack::Median(radius) => write!(f, "median:{radius}"),
+            Attack::Screenshot(scale) => write!(f, "screenshot:{scale}"),
+        }
+    }
+}
+
+impl FromStr for Attack {
+    type Err = anyhow::Error;
+
+    /// Parses an attack written as `name:parameter`, e.g. `jpeg:75` or `rotate:5`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, param) = s
+            .split_once(':')
+            .with_context(|| format!("expected `name:parameter`, got `{s}`"))?;
+        let invalid = || format!("invalid parameter for attack `{name}`: {param}");
+        Ok(match name {
+            "jpeg" => Attack::Jpeg(param.parse().with_context(invalid)?),
+            "resize" => Attack::Resize(param.parse().with_context(invalid)?),
+            "crop" => Attack::Crop(param.parse().with_context(invalid)?),
+            "rotate" => Attack::Rotate(param.parse().with_context(invalid)?),
+            "noise" => Attack::GaussianNoise(param.parse().with_context(invalid)?),
+            "blur" => Attack::Blur(param.parse().with_context(invalid)?),
+            "brightness" => Attack::Brightness(param.parse().with_context(invalid)?),
+            "contrast" => Attack::Contrast(param.parse().with_context(invalid)?),
+            "gain" => Attack::Gain(param.parse().with_context(invalid)?),
+            "salt-pepper" => Attack::SaltAndPepper(param.parse().with_context(invalid)?),
+            "median" => Attack::Median(param.parse().with_context(invalid)?),
+            "screenshot" => Attack::Screenshot(param.parse().with_context(invalid)?),
+            _ => bail!("unknown attack `{name}`"),
+        })
+    }
+}
+
+/// Robustness of a watermark against a single attack.
+#[derive(Debug, Clone)]
+pub struct AttackOutcome {
+    /// The attack applied to the watermarked image
+    pub attack: Attack,
+    /// Extraction result on the attacked image
+    pub report: ExtractionReport,
+}
+
+/// Embeds a watermark, applies each attack and measures how well the watermark survives.
+///
+/// Attacks are evaluated in parallel; outcomes are returned in the order of `attacks`.
+pub fn benchmark(
+    img: &DynamicImage,
+    watermark: &BitSlice<u8>,
+    config: &WatermarkConfig,
+    attacks: &[Attack],
+) -> Result<Vec<AttackOutcome>> {
+    let watermarked = embed_watermark_image(img, watermark, config);
+    attacks
+        .par_iter()
+        .map(|&attack| {
+            let attacked = attack.apply(&watermarked)?;
+            Ok(AttackOutcome {
+                attack,
+                report: verify_watermark_image(&attacked, watermark, config),
+            })
+        })
+        .collect()
+}
+
+fn scaled_dimensions(width: u32, height: u32, scale: f32) -> Result<(u32, u32)> {
+    if scale.is_nan() || scale <= 0.0 {
+        bail!("scale must be positive");
+    }
+    let w = ((width as f32 * scale).round() as u32).max(1);
+    let h = ((height as f32 * scale).round() as u32).max(1);
+    Ok((w, h))
+}
+
+/// Standard normal sample using the Box-Muller transform.
+fn standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = 1.0 - rng.random::<f32>();
+    let u2: f32 = rng.random::<f32>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+/// Rotates an image around its center with bilinear sampling, keeping its size.
+///
+/// Areas outside the source image are filled with black.
+fn rotate(img: &RgbImage, degrees: f32) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = ((width as f32 - 1.0) / 2.0, (height as f32 - 1.0) / 2.0);
+
+    RgbImage::from_fn(width, height, |x, y| {
+        // Inverse mapping: find where this output pixel comes from in the source
+        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+        let sx = cos * dx + sin * dy + cx;
+        let sy = -sin * dx + cos * dy + cy;
+        sample_bilinear(img, sx, sy)
+    })
+}
+
+fn sample_bilinear(img: &RgbImage, x: f32, y: f32) -> Rgb<u8> {
+    let (width, height) = img.dimensions();
+    if x < 0.0 || y < 0.0 || x > (width - 1) as f32 || y > (height - 1) as f32 {
+        return Rgb([0, 0, 0]);
+    }
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let p00 = img.get_pixel(x0, y0);
+    let p10 = img.get_pixel(x1, y0);
+    let p01 = img.get_pixel(x0, y1);
+    let p11 = img.get_pixel(x1, y1);
+    Rgb(std::array::from_fn(|c| {
+        let top = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+        let bottom = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+        (top * (1.0 - fy) + bottom * fy).round() as u8
+    }))
+}
+
+/// Per-channel median filter over a `(2 * radius + 1)²` window, clamped at the borders.
+fn median_filter(img: &RgbImage, radius: u32) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let r = radius as i64;
+    let mut window = Vec::with_capacity(((2 * r + 1) * (2 * r + 1)) as usize);
+
+    let mut out = RgbImage::new(width, height);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        for c in 0..3 {
+            window.clear();
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let sx = (x as i64 + dx).clamp(0, width as i64 - 1) as u32;
+                    let sy = (y as i64 + dy).clamp(0, height as i64 - 1) as u32;
+                    window.push(img.get_pixel(sx, sy)[c]);
+                }
+            }
+            window.sort_unstable();
+            pixel[c] = window[window.len() / 2];
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> DynamicImage {
+        RgbImage::from_fn(32, 24, |x, y| Rgb([(x * 8) as u8, (y * 10) as u8, 128])).into()
+    }
+
+    #[test]
+    fn test_attacks_keep_dimensions() {
+        let img = test_image();
+        for attack in Attack::default_suite() {
+            let attacked = attack.apply(&img).unwrap();
+            assert_eq!(attacked.dimensions(), img.dimensions(), "{attack}");
+        }
+    }
+
+    #[test]
+    fn test_attack_round_trips_through_string() {
+        for attack in Attack::default_suite() {
+            let parsed: Attack = attack.to_string().parse().unwrap();
+            assert_eq!(parsed, attack);
+        }
+        assert!("jpeg".parse::<Attack>().is_err());
+        assert!("unknown:1".parse::<Attack>().is_err());
+        assert!("jpeg:high".parse::<Attack>().is_err());
+    }
+
+    #[test]
+    fn test_gain_scales_and_clamps() {
+        let img = DynamicImage::from(RgbImage::from_pixel(2, 2, Rgb([10, 100, 240])));
+        let attacked = Attack::Gain(1.2).apply(&img).unwrap().to_rgb8();
+        assert_eq!(*attacked.get_pixel(0, 0), Rgb([12, 120, 255]));
+        assert!(Attack::Gain(-1.0).apply(&img).is_err());
+    }
+
+    #[test]
+    fn test_median_removes_isolated_pixel() {
+        let mut img = RgbImage::from_pixel(5, 5, Rgb([10, 10, 10]));
+        img.put_pixel(2, 2, Rgb([255, 255, 255]));
+        let filtered = median_filter(&img, 1);
+        assert_eq!(*filtered.get_pixel(2, 2), Rgb([10, 10, 10]));
+    }
+
+    #[test]
+    fn test_rotate_by_zero_is_identity() {
+        let img = test_image().to_rgb8();
+        assert_eq!(rotate(&img, 0.0), img);
+    }
+}
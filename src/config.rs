@@ -1,9 +1,19 @@
+use anyhow::{Result, bail};
 use derive_builder::Builder;
 
 /// Configuration for the watermarking process.
 ///
 /// This struct allows customizing the strength of the watermark embedding and the strategy used.
+///
+/// With the `serde` feature, it is (de)serialized through a versioned representation, e.g.
+/// `{"version":"1","strength_1":36,"strength_2":20,"mode":{"kind":"strategy","seed":42}}`,
+/// so that stored configurations remain readable by later releases.
 #[derive(Debug, Clone, Builder)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "repr::VersionedConfig", from = "repr::VersionedConfig")
+)]
 pub struct WatermarkConfig {
     /// Embedding strength (corresponds to first singular value).
     ///
@@ -21,10 +31,20 @@ pub struct WatermarkConfig {
     /// Determines how the watermark bits are distributed across the image blocks.
     #[builder(default = "WatermarkMode::Normal")]
     pub mode: WatermarkMode,
+    /// Rule hiding a bit in each block.
+    ///
+    /// Default is [`EmbeddingScheme::SvdQuantization`].
+    #[builder(default)]
+    pub scheme: EmbeddingScheme,
 }
 
 /// Defines the strategy for distributing watermark bits.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "repr::ModeV1", from = "repr::ModeV1")
+)]
 pub enum WatermarkMode {
     /// Normal mode: Watermark bits are embedded sequentially in the blocks.
     ///
@@ -37,12 +57,520 @@ pub enum WatermarkMode {
     Strategy(u64),
 }
 
+/// Defines the rule hiding a bit in each block, see [`crate::schemes`].
+///
+/// Every scheme works on the DCT of the block; `strength_1` sets its quantization step or
+/// amplitude in 8-bit levels. The key of the dithered and spread schemes is secret, like the
+/// seed of [`WatermarkMode::Strategy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmbeddingScheme {
+    /// Quantization of the first (and, with `strength_2`, second) singular value.
+    #[default]
+    SvdQuantization,
+    /// Additive spread spectrum: a keyed ±1 pattern over the mid-frequency coefficients,
+    /// added or subtracted and detected by correlation.
+    SpreadSpectrum { key: u64 },
+    /// Dither modulation: each mid-frequency coefficient is quantized on one of two lattices
+    /// shifted by a keyed dither.
+    DitherModulation { key: u64 },
+    /// Spread-transform dither modulation: the projection of the mid-frequency coefficients
+    /// on a keyed direction is dither-modulated.
+    SpreadTransformDither { key: u64 },
+    /// Rational dither modulation: as [`EmbeddingScheme::SpreadTransformDither`], with the
+    /// projection divided by the amplitude of the rest of the block, so that extraction
+    /// survives global gain changes.
+    RationalDither { key: u64 },
+}
+
+impl EmbeddingScheme {
+    /// Name of the scheme, as in the serialized configuration, e.g. `svd_quantization`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EmbeddingScheme::SvdQuantization => "svd_quantization",
+            EmbeddingScheme::SpreadSpectrum { .. } => "spread_spectrum",
+            EmbeddingScheme::DitherModulation { .. } => "dither_modulation",
+            EmbeddingScheme::SpreadTransformDither { .. } => "spread_transform_dither",
+            EmbeddingScheme::RationalDither { .. } => "rational_dither",
+        }
+    }
+
+    /// Secret key of the scheme, if it uses one.
+    pub fn key(&self) -> Option<u64> {
+        match *self {
+            EmbeddingScheme::SvdQuantization => None,
+            EmbeddingScheme::SpreadSpectrum { key }
+            | EmbeddingScheme::DitherModulation { key }
+            | EmbeddingScheme::SpreadTransformDither { key }
+            | EmbeddingScheme::RationalDither { key } => Some(key),
+        }
+    }
+
+    /// The scheme called `kind` (see [`EmbeddingScheme::kind`]), with `key` if it uses one.
+    ///
+    /// Fails for an unknown name, or when the scheme needs a key and none is given.
+    pub fn from_kind(kind: &str, key: Option<u64>) -> Result<Self> {
+        let keyed = |scheme: fn(u64) -> EmbeddingScheme| match key {
+            Some(key) => Ok(scheme(key)),
+            None => bail!("a key is required, the watermark uses the {kind} scheme"),
+        };
+        match kind {
+            "svd_quantization" => Ok(EmbeddingScheme::SvdQuantization),
+            "spread_spectrum" => keyed(|key| EmbeddingScheme::SpreadSpectrum { key }),
+            "dither_modulation" => keyed(|key| EmbeddingScheme::DitherModulation { key }),
+            "spread_transform_dither" => {
+                keyed(|key| EmbeddingScheme::SpreadTransformDither { key })
+            }
+            "rational_dither" => keyed(|key| EmbeddingScheme::RationalDither { key }),
+            other => bail!("unknown embedding scheme: {other}"),
+        }
+    }
+}
+
 impl Default for WatermarkConfig {
     fn default() -> Self {
         Self {
             strength_1: 36,
             strength_2: None,
             mode: WatermarkMode::Normal,
+            scheme: EmbeddingScheme::SvdQuantization,
+        }
+    }
+}
+
+/// Configuration of the fragile watermark, see [`crate::fragile`].
+///
+/// The builder, and deserialization with the `serde` feature, reject a strength that is not
+/// positive and a tolerance outside `[0, 1]`.
+#[derive(Debug, Clone, Copy, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "repr::VersionedFragileConfig",
+        try_from = "repr::VersionedFragileConfig"
+    )
+)]
+pub struct FragileConfig {
+    /// Secret key of the check pattern.
+    pub key: u64,
+    /// Quantization step of the HH coefficients, in 8-bit levels.
+    ///
+    /// Coefficients move by up to half a step, and changes below a quarter step, such as
+    /// rounding to 8 bits, go unnoticed. Default is 4.
+    #[builder(default = "4")]
+    pub strength: i32,
+    /// Fraction of mismatching coefficients above which a block is reported as tampered.
+    ///
+    /// Unedited blocks have almost none and edited ones about half. Default is 0.25.
+    #[builder(default = "0.25")]
+    pub tolerance: f64,
+}
+
+impl FragileConfig {
+    fn check(strength: i32, tolerance: f64) -> std::result::Result<(), String> {
+        if strength <= 0 {
+            return Err(format!("fragile strength must be positive, got {strength}"));
+        }
+        if !(0.0..=1.0).contains(&tolerance) {
+            return Err(format!(
+                "fragile tolerance must be within [0, 1], got {tolerance}"
+            ));
         }
+        Ok(())
+    }
+}
+
+impl FragileConfigBuilder {
+    fn validate(&self) -> std::result::Result<(), String> {
+        FragileConfig::check(self.strength.unwrap_or(4), self.tolerance.unwrap_or(0.25))
+    }
+}
+
+/// Minimum image quality to preserve when choosing embedding strengths automatically.
+///
+/// See [`crate::tuning::embed_with_quality_target`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "repr::VersionedQualityTarget",
+        from = "repr::VersionedQualityTarget"
+    )
+)]
+pub enum QualityTarget {
+    /// Lower bound on the RGB PSNR between original and watermarked image, in dB.
+    Psnr(f64),
+    /// Lower bound on the SSIM between original and watermarked image, on the Y channel.
+    Ssim(f64),
+}
+
+/// Serialized forms of the configuration types.
+///
+/// Each released layout is kept as its own variant so that older data keeps deserializing;
+/// a new layout gets a new version tag and a conversion from the previous ones.
+#[cfg(feature = "serde")]
+pub(crate) mod repr {
+    use serde::{Deserialize, Serialize};
+
+    use super::{EmbeddingScheme, FragileConfig, QualityTarget, WatermarkConfig, WatermarkMode};
+    use crate::profile::PayloadEncoding;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "version")]
+    pub(super) enum VersionedConfig {
+        #[serde(rename = "1")]
+        V1(ConfigV1),
+        #[serde(rename = "2")]
+        V2(ConfigV2),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct ConfigV1 {
+        strength_1: i32,
+        #[serde(default)]
+        strength_2: Option<i32>,
+        mode: ModeV1,
+    }
+
+    /// Version 1 with the embedding scheme
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct ConfigV2 {
+        strength_1: i32,
+        #[serde(default)]
+        strength_2: Option<i32>,
+        mode: ModeV1,
+        scheme: SchemeV2,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    pub(super) enum SchemeV2 {
+        SvdQuantization,
+        SpreadSpectrum { key: u64 },
+        DitherModulation { key: u64 },
+        SpreadTransformDither { key: u64 },
+        RationalDither { key: u64 },
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    pub(super) enum ModeV1 {
+        Normal,
+        Strategy { seed: u64 },
+    }
+
+    /// Configurations with the default scheme keep the version 1 layout, which earlier
+    /// releases can read.
+    impl From<WatermarkConfig> for VersionedConfig {
+        fn from(config: WatermarkConfig) -> Self {
+            match config.scheme {
+                EmbeddingScheme::SvdQuantization => VersionedConfig::V1(ConfigV1 {
+                    strength_1: config.strength_1,
+                    strength_2: config.strength_2,
+                    mode: config.mode.into(),
+                }),
+                scheme => VersionedConfig::V2(ConfigV2 {
+                    strength_1: config.strength_1,
+                    strength_2: config.strength_2,
+                    mode: config.mode.into(),
+                    scheme: scheme.into(),
+                }),
+            }
+        }
+    }
+
+    impl From<VersionedConfig> for WatermarkConfig {
+        fn from(config: VersionedConfig) -> Self {
+            match config {
+                VersionedConfig::V1(config) => WatermarkConfig {
+                    strength_1: config.strength_1,
+                    strength_2: config.strength_2,
+                    mode: config.mode.into(),
+                    scheme: EmbeddingScheme::SvdQuantization,
+                },
+                VersionedConfig::V2(config) => WatermarkConfig {
+                    strength_1: config.strength_1,
+                    strength_2: config.strength_2,
+                    mode: config.mode.into(),
+                    scheme: config.scheme.into(),
+                },
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "version")]
+    pub(super) enum VersionedFragileConfig {
+        #[serde(rename = "1")]
+        V1(FragileConfigV1),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct FragileConfigV1 {
+        key: u64,
+        strength: i32,
+        tolerance: f64,
+    }
+
+    impl From<FragileConfig> for VersionedFragileConfig {
+        fn from(config: FragileConfig) -> Self {
+            VersionedFragileConfig::V1(FragileConfigV1 {
+                key: config.key,
+                strength: config.strength,
+                tolerance: config.tolerance,
+            })
+        }
+    }
+
+    impl TryFrom<VersionedFragileConfig> for FragileConfig {
+        type Error = String;
+
+        fn try_from(config: VersionedFragileConfig) -> Result<Self, String> {
+            match config {
+                VersionedFragileConfig::V1(config) => {
+                    FragileConfig::check(config.strength, config.tolerance)?;
+                    Ok(FragileConfig {
+                        key: config.key,
+                        strength: config.strength,
+                        tolerance: config.tolerance,
+                    })
+                }
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "version")]
+    pub(crate) enum VersionedQualityTarget {
+        #[serde(rename = "1")]
+        V1(QualityTargetV1),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    pub(crate) enum QualityTargetV1 {
+        Psnr { min: f64 },
+        Ssim { min: f64 },
+    }
+
+    impl From<QualityTarget> for VersionedQualityTarget {
+        fn from(target: QualityTarget) -> Self {
+            VersionedQualityTarget::V1(match target {
+                QualityTarget::Psnr(min) => QualityTargetV1::Psnr { min },
+                QualityTarget::Ssim(min) => QualityTargetV1::Ssim { min },
+            })
+        }
+    }
+
+    impl From<VersionedQualityTarget> for QualityTarget {
+        fn from(target: VersionedQualityTarget) -> Self {
+            match target {
+                VersionedQualityTarget::V1(QualityTargetV1::Psnr { min }) => {
+                    QualityTarget::Psnr(min)
+                }
+                VersionedQualityTarget::V1(QualityTargetV1::Ssim { min }) => {
+                    QualityTarget::Ssim(min)
+                }
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "version")]
+    pub(crate) enum VersionedPayloadEncoding {
+        #[serde(rename = "1")]
+        V1(PayloadEncodingV1),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    pub(crate) enum PayloadEncodingV1 {
+        Utf8,
+        Hex,
+    }
+
+    impl From<PayloadEncoding> for VersionedPayloadEncoding {
+        fn from(encoding: PayloadEncoding) -> Self {
+            VersionedPayloadEncoding::V1(match encoding {
+                PayloadEncoding::Utf8 => PayloadEncodingV1::Utf8,
+                PayloadEncoding::Hex => PayloadEncodingV1::Hex,
+            })
+        }
+    }
+
+    impl From<VersionedPayloadEncoding> for PayloadEncoding {
+        fn from(encoding: VersionedPayloadEncoding) -> Self {
+            match encoding {
+                VersionedPayloadEncoding::V1(PayloadEncodingV1::Utf8) => PayloadEncoding::Utf8,
+                VersionedPayloadEncoding::V1(PayloadEncodingV1::Hex) => PayloadEncoding::Hex,
+            }
+        }
+    }
+
+    impl From<EmbeddingScheme> for SchemeV2 {
+        fn from(scheme: EmbeddingScheme) -> Self {
+            match scheme {
+                EmbeddingScheme::SvdQuantization => SchemeV2::SvdQuantization,
+                EmbeddingScheme::SpreadSpectrum { key } => SchemeV2::SpreadSpectrum { key },
+                EmbeddingScheme::DitherModulation { key } => SchemeV2::DitherModulation { key },
+                EmbeddingScheme::SpreadTransformDither { key } => {
+                    SchemeV2::SpreadTransformDither { key }
+                }
+                EmbeddingScheme::RationalDither { key } => SchemeV2::RationalDither { key },
+            }
+        }
+    }
+
+    impl From<SchemeV2> for EmbeddingScheme {
+        fn from(scheme: SchemeV2) -> Self {
+            match scheme {
+                SchemeV2::SvdQuantization => EmbeddingScheme::SvdQuantization,
+                SchemeV2::SpreadSpectrum { key } => EmbeddingScheme::SpreadSpectrum { key },
+                SchemeV2::DitherModulation { key } => EmbeddingScheme::DitherModulation { key },
+                SchemeV2::SpreadTransformDither { key } => {
+                    EmbeddingScheme::SpreadTransformDither { key }
+                }
+                SchemeV2::RationalDither { key } => EmbeddingScheme::RationalDither { key },
+            }
+        }
+    }
+
+    impl From<WatermarkMode> for ModeV1 {
+        fn from(mode: WatermarkMode) -> Self {
+            match mode {
+                WatermarkMode::Normal => ModeV1::Normal,
+                WatermarkMode::Strategy(seed) => ModeV1::Strategy { seed },
+            }
+        }
+    }
+
+    impl From<ModeV1> for WatermarkMode {
+        fn from(mode: ModeV1) -> Self {
+            match mode {
+                ModeV1::Normal => WatermarkMode::Normal,
+                ModeV1::Strategy { seed } => WatermarkMode::Strategy(seed),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fragile_config_validation() {
+        let config = FragileConfigBuilder::default().key(1).build().unwrap();
+        assert_eq!((config.strength, config.tolerance), (4, 0.25));
+        for strength in [0, -4] {
+            let built = FragileConfigBuilder::default()
+                .key(1)
+                .strength(strength)
+                .build();
+            assert!(built.is_err(), "strength {strength} accepted");
+        }
+        let built = FragileConfigBuilder::default()
+            .key(1)
+            .tolerance(1.5)
+            .build();
+        assert!(built.is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_config_serde_round_trip() {
+        let config = WatermarkConfigBuilder::default()
+            .strength_2(20)
+            .mode(WatermarkMode::Strategy(42))
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":"1","strength_1":36,"strength_2":20,"mode":{"kind":"strategy","seed":42}}"#
+        );
+
+        let decoded: WatermarkConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.strength_1, 36);
+        assert_eq!(decoded.strength_2, Some(20));
+        assert!(matches!(decoded.mode, WatermarkMode::Strategy(42)));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_config_serde_versions() {
+        // Stored version 1 data must keep deserializing
+        let decoded: WatermarkConfig =
+            serde_json::from_str(r#"{"version":"1","strength_1":24,"mode":{"kind":"normal"}}"#)
+                .unwrap();
+        assert_eq!(decoded.strength_1, 24);
+        assert_eq!(decoded.strength_2, None);
+        assert!(matches!(decoded.mode, WatermarkMode::Normal));
+
+        assert!(serde_json::from_str::<WatermarkConfig>(r#"{"strength_1":24}"#).is_err());
+        assert!(
+            serde_json::from_str::<WatermarkConfig>(
+                r#"{"version":"0","strength_1":24,"mode":{"kind":"normal"}}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_config_serde_scheme() {
+        let config = WatermarkConfigBuilder::default()
+            .scheme(EmbeddingScheme::DitherModulation { key: 7 })
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":"2","strength_1":36,"strength_2":null,"mode":{"kind":"normal"},"scheme":{"kind":"dither_modulation","key":7}}"#
+        );
+        let decoded: WatermarkConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.scheme, EmbeddingScheme::DitherModulation { key: 7 });
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_fragile_config_serde() {
+        let config = FragileConfigBuilder::default().key(7).build().unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":"1","key":7,"strength":4,"tolerance":0.25}"#
+        );
+        let decoded: FragileConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            (decoded.key, decoded.strength, decoded.tolerance),
+            (7, 4, 0.25)
+        );
+
+        assert!(
+            serde_json::from_str::<FragileConfig>(
+                r#"{"version":"1","key":7,"strength":0,"tolerance":0.25}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_quality_target_serde() {
+        let json = serde_json::to_string(&QualityTarget::Psnr(42.0)).unwrap();
+        assert_eq!(json, r#"{"version":"1","kind":"psnr","min":42.0}"#);
+        assert_eq!(
+            serde_json::from_str::<QualityTarget>(&json).unwrap(),
+            QualityTarget::Psnr(42.0)
+        );
+        assert_eq!(
+            serde_json::from_str::<QualityTarget>(r#"{"version":"1","kind":"ssim","min":0.98}"#)
+                .unwrap(),
+            QualityTarget::Ssim(0.98)
+        );
+        assert!(serde_json::from_str::<QualityTarget>(r#"{"ssim":0.98}"#).is_err());
     }
 }
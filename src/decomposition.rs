@@ -0,0 +1,129 @@
+use bitvec::prelude::*;
+use image::DynamicImage;
+use rayon::prelude::*;
+
+use crate::{
+    BlockCutted, Imbedded, YCrBrAMat,
+    config::{EmbeddingScheme, WatermarkConfig},
+    scalar::Scalar,
+    transform::{block4::Svd4, embed::imbed_bit_into_svd},
+    utils::render_imbedded,
+};
+
+/// An image decomposed once for embedding many watermarks.
+///
+/// Decoding, color conversion, padding, DWT and cutting happen once, and the SVD of every
+/// block is cached: each embedding only quantizes the singular values and rebuilds the
+/// blocks. Results are identical to [`BlockCutted::embed_watermark_bits`].
+#[derive(Clone, Debug)]
+pub struct Decomposition<T: Scalar = f32> {
+    cutted: BlockCutted<T>,
+    /// SVD of the DCT of each (Y, Cb, Cr) block
+    svds: Vec<[Svd4<T>; 3]>,
+}
+
+impl Decomposition {
+    /// Decomposes a decoded image in `f32`.
+    pub fn new(img: &DynamicImage) -> Self {
+        Self::from_image(img)
+    }
+}
+
+impl<T: Scalar> Decomposition<T> {
+    /// Decomposes a decoded image in the scalar type `T`.
+    pub fn from_image(img: &DynamicImage) -> Self {
+        let ycbcr: YCrBrAMat<T> = img.to_rgba32f().into();
+        Self::from_cutted(ycbcr.add_padding().dwt().cut())
+    }
+
+    /// Caches the block SVDs of an already cut image.
+    pub fn from_cutted(cutted: BlockCutted<T>) -> Self {
+        let svds = (0..cutted.y_ll_blocks.len())
+            .into_par_iter()
+            .map(|i| {
+                [
+                    cutted.y_ll_blocks[i].dct_svd(),
+                    cutted.cb_ll_blocks[i].dct_svd(),
+                    cutted.cr_ll_blocks[i].dct_svd(),
+                ]
+            })
+            .collect();
+        Decomposition { cutted, svds }
+    }
+
+    /// The cut image, e.g. for extraction.
+    pub fn cutted(&self) -> &BlockCutted<T> {
+        &self.cutted
+    }
+
+    /// Maximum number of watermark bits an embedding can carry.
+    pub fn capacity(&self) -> usize {
+        self.cutted.blocks_dimensions.0 * self.cutted.blocks_dimensions.1
+    }
+
+    /// Embed watermark bits into blocks (Y, Cb, Cr) from the cached SVDs
+    ///
+    /// # Panics
+    ///
+    /// If the watermark is longer than [`Decomposition::capacity`].
+    pub fn embed_watermark_bits(
+        &self,
+        watermark_bits: &BitSlice<u8>,
+        config: &WatermarkConfig,
+    ) -> Imbedded<T> {
+        let cutted = &self.cutted;
+        // Only the singular value quantization benefits from the cached SVDs
+        if config.scheme != EmbeddingScheme::SvdQuantization {
+            return cutted.embed_watermark_bits_ref(watermark_bits, config);
+        }
+        let blocks = cutted.imbed_blocks(watermark_bits, config, |i, bit| {
+            let [y, cb, cr] = &self.svds[i];
+            (
+                imbed_bit_into_svd(y, bit, config),
+                imbed_bit_into_svd(cb, bit, config),
+                imbed_bit_into_svd(cr, bit, config),
+            )
+        });
+        cutted.with_blocks(blocks)
+    }
+
+    /// Returns a copy of the image carrying `watermark_bits`, as 8-bit RGB.
+    ///
+    /// # Panics
+    ///
+    /// If the watermark is longer than [`Decomposition::capacity`].
+    pub fn render(&self, watermark_bits: &BitSlice<u8>, config: &WatermarkConfig) -> DynamicImage {
+        render_imbedded(self.embed_watermark_bits(watermark_bits, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{config_from_seed, embed_watermark_image};
+    use image::RgbImage;
+
+    #[test]
+    fn test_matches_single_embedding() {
+        let img = DynamicImage::from(RgbImage::from_fn(64, 48, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8])
+        }));
+        let decomposition = Decomposition::new(&img);
+        assert_eq!(decomposition.capacity(), 8 * 6);
+
+        let config = config_from_seed(Some(4)).unwrap();
+        for watermark in [bits![u8, Lsb0; 1, 0, 1], bits![u8, Lsb0; 0, 0, 1, 1, 0]] {
+            let rendered = decomposition.render(watermark, &config);
+            assert_eq!(rendered, embed_watermark_image(&img, watermark, &config));
+
+            // The borrowed, uncached embedding agrees too
+            let borrowed = decomposition
+                .cutted()
+                .embed_watermark_bits_ref(watermark, &config);
+            let cached = decomposition.embed_watermark_bits(watermark, &config);
+            for (a, b) in borrowed.y_ll_blocks.iter().zip(&cached.y_ll_blocks) {
+                assert_eq!(a.mat_data, b.mat_data);
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::{Result, bail};
+use bitvec::prelude::*;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+use crate::{
+    BlockCutted,
+    config::{EmbeddingScheme, WatermarkConfig},
+    scalar::Scalar,
+    strategy::Permutation,
+};
+
+/// Outcome of a watermark presence test.
+///
+/// Every sample contributes one statistic with zero mean when the image carries no
+/// watermark, and a known variance: the quantization phase of a singular value for
+/// [`EmbeddingScheme::SvdQuantization`], the correlation of a block with its keyed pattern for
+/// [`EmbeddingScheme::SpreadSpectrum`]. The normalized sum of the statistics is then
+/// approximately standard normal, and the watermark is declared present when it exceeds the
+/// quantile matching the false-positive probability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// Detection statistic (z-score)
+    pub score: f64,
+    /// Score above which the watermark is declared present
+    pub threshold: f64,
+    /// Whether the watermark is considered present
+    pub present: bool,
+    /// Number of singular values or block correlations that contributed to the score
+    pub samples: usize,
+}
+
+impl<T: Scalar> BlockCutted<T> {
+    /// Tests whether the blocks carry a watermark embedded with `config`.
+    ///
+    /// For [`EmbeddingScheme::SvdQuantization`], any payload is detected without `expected`:
+    /// the test only looks for singular values sitting at the centers of their quantization
+    /// cells. With `expected`, each block is checked against the bit it should carry, which
+    /// is a much stronger test.
+    ///
+    /// For [`EmbeddingScheme::SpreadSpectrum`], each block is correlated with its keyed
+    /// pattern, signed by the bit it should carry, so `expected` is required.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The embedded watermark, if known.
+    /// * `config` - Configuration used during embedding.
+    /// * `false_positive` - Probability of declaring an unmarked image as marked.
+    ///
+    /// # Errors
+    ///
+    /// If `false_positive` is not within (0, 1), if `expected` is empty or longer than the
+    /// number of blocks, or if the scheme of `config` cannot be scored this way.
+    pub fn detect_watermark(
+        &self,
+        expected: Option<&BitSlice<u8>>,
+        config: &WatermarkConfig,
+        false_positive: f64,
+    ) -> Result<Detection> {
+        if !(false_positive > 0.0 && false_positive < 1.0) {
+            bail!("false-positive probability must be within (0, 1), got {false_positive}");
+        }
+        let nblocks = self.blocks_dimensions.0 * self.blocks_dimensions.1;
+        if let Some(wm) = expected
+            && (wm.is_empty() || wm.len() > nblocks)
+        {
+            bail!(
+                "watermark of {} bits does not fit, the image holds at most {nblocks}",
+                wm.len()
+            );
+        }
+        let perm = Permutation::from_mode(config.mode, nblocks);
+        // Bit expected at a block, if known
+        let bit_at =
+            |i: usize| expected.map(|wm| wm[perm.corresponding_wmbits_position(i, wm.len())]);
+        let channels = |i: usize| {
+            [
+                &self.y_ll_blocks[i],
+                &self.cb_ll_blocks[i],
+                &self.cr_ll_blocks[i],
+            ]
+        };
+
+        // Sum of the statistics, sum of their variances and number of samples
+        let statistics: Vec<(f64, f64, usize)> = match config.scheme {
+            EmbeddingScheme::SvdQuantization => (0..nblocks)
+                .into_par_iter()
+                .map(|i| {
+                    // Phase of the quantization cell center expected at this block, if known
+                    let center = bit_at(i).map(|bit| if bit { 0.75 } else { 0.25 });
+                    channels(i)
+                        .iter()
+                        .flat_map(|block| block.quantization_phases(config))
+                        .map(|phase| phase_statistic(phase as f64, center))
+                        .fold((0.0, 0.0, 0), |(sum, var, n), t| {
+                            (sum + t, var + 0.5, n + 1)
+                        })
+                })
+                .collect(),
+            EmbeddingScheme::SpreadSpectrum { key } => {
+                if expected.is_none() {
+                    bail!("spread-spectrum detection needs the expected watermark");
+                }
+                (0..nblocks)
+                    .into_par_iter()
+                    .map(|i| {
+                        let sign = if bit_at(i) == Some(true) { 1.0 } else { -1.0 };
+                        channels(i)
+                            .iter()
+                            .map(|block| block.spread_correlation(key, i))
+                            .fold((0.0, 0.0, 0), |(sum, var, n), (correlation, variance)| {
+                                (sum + sign * correlation, var + variance, n + 1)
+                            })
+                    })
+                    .collect()
+            }
+            scheme => bail!(
+                "detection is not supported for the {} scheme",
+                scheme.kind()
+            ),
+        };
+        let (total, variance, samples) = statistics
+            .into_iter()
+            .fold((0.0, 0.0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+
+        let score = if variance > 0.0 {
+            total / variance.sqrt()
+        } else {
+            0.0
+        };
+        let threshold = standard_normal_quantile(1.0 - false_positive);
+        Ok(Detection {
+            score,
+            threshold,
+            present: score > threshold,
+            samples,
+        })
+    }
+}
+
+/// Statistic of a single quantization phase.
+///
+/// It is 1 at the expected cell center (or at either center when the bit is unknown) and has
+/// zero mean and variance 1/2 for a uniformly distributed phase.
+fn phase_statistic(phase: f64, center: Option<f64>) -> f64 {
+    match center {
+        Some(center) => (2.0 * PI * (phase - center)).cos(),
+        None => -(4.0 * PI * phase).cos(),
+    }
+}
+
+/// Quantile function of the standard normal distribution.
+///
+/// Uses Acklam's rational approximation, accurate to about 1e-9.
+fn standard_normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_standard_normal_quantile() {
+        assert_relative_eq!(standard_normal_quantile(0.5), 0.0, epsilon = 1e-9);
+        assert_relative_eq!(standard_normal_quantile(0.975), 1.959964, epsilon = 1e-6);
+        assert_relative_eq!(standard_normal_quantile(0.01), -2.326348, epsilon = 1e-6);
+        assert_relative_eq!(
+            standard_normal_quantile(1.0 - 1e-6),
+            4.753424,
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn test_phase_statistic() {
+        assert_relative_eq!(phase_statistic(0.25, None), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(phase_statistic(0.75, None), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(phase_statistic(0.75, Some(0.75)), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(phase_statistic(0.25, Some(0.75)), -1.0, epsilon = 1e-12);
+
+        // Zero mean over uniformly spread phases
+        let mean = (0..1000)
+            .map(|i| phase_statistic(i as f64 / 1000.0, None))
+            .sum::<f64>()
+            / 1000.0;
+        assert_relative_eq!(mean, 0.0, epsilon = 1e-9);
+    }
+}
@@ -0,0 +1,156 @@
+//! Robust and fragile watermarks in one image.
+//!
+//! A [`DualWatermark`] embeds an ownership payload in the LL subbands, as
+//! [`crate::utils::embed_watermark_image`] does, and the check pattern of [`crate::fragile`]
+//! in the HH subbands of the same wavelet transform. The subbands are independent, so that
+//! neither watermark disturbs the other, and [`DualWatermark::verify`] reads both back:
+//!
+//! ```
+//! use bitvec::prelude::*;
+//! use blind_watermark::dual::DualWatermark;
+//! use blind_watermark::prelude::*;
+//! use image::{DynamicImage, Rgb, RgbImage};
+//!
+//! let img = DynamicImage::from(RgbImage::from_fn(128, 128, |x, y| {
+//!     Rgb([x as u8 + 40, y as u8 + 40, ((x ^ y) + 20) as u8])
+//! }));
+//! let dual = DualWatermark::new(
+//!     WatermarkConfig::default(),
+//!     FragileConfigBuilder::default().key(7).build().unwrap(),
+//! );
+//! let mut marked = dual.embed(&img, b"AP".view_bits::<Lsb0>())?.to_rgb8();
+//! for (x, y) in (64..80).flat_map(|x| (32..40).map(move |y| (x, y))) {
+//!     marked.put_pixel(x, y, Rgb([250, 250, 250]));
+//! }
+//!
+//! let verification = dual.verify(&marked.into(), 16)?;
+//! assert_eq!(
+//!     verification.to_string(),
+//!     r#"owner = "AP", tampered regions = 16x8+64+32"#
+//! );
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::Result;
+use bitvec::prelude::*;
+use image::DynamicImage;
+use std::fmt;
+
+use crate::{
+    YCrBrAMat,
+    config::{FragileConfig, WatermarkConfig},
+    fragile::{Region, TamperMap},
+    transform::embed::{extraction_confidence, soft_to_hard_bits},
+    utils::{FRAGILE_ROUNDS, check_capacity, fragile_round, render_imbedded},
+};
+
+/// Configurations of a robust ownership watermark and a fragile integrity watermark.
+#[derive(Debug, Clone)]
+pub struct DualWatermark {
+    /// Configuration of the ownership payload, in the LL subbands
+    pub robust: WatermarkConfig,
+    /// Configuration of the check pattern, in the HH subbands
+    pub fragile: FragileConfig,
+}
+
+/// Outcome of [`DualWatermark::verify`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    /// Ownership payload
+    pub owner: BitVec<u8>,
+    /// Agreement of the votes behind the payload, see
+    /// [`crate::transform::embed::extraction_confidence`]
+    pub confidence: f64,
+    /// Per-block result of the integrity check
+    pub tamper: TamperMap,
+    /// Bounding boxes of the tampered areas, see [`TamperMap::tampered_regions`]
+    pub tampered_regions: Vec<Region>,
+}
+
+impl DualWatermark {
+    /// Combines the configurations of the two watermarks.
+    pub fn new(robust: WatermarkConfig, fragile: FragileConfig) -> Self {
+        DualWatermark { robust, fragile }
+    }
+
+    /// Embeds the ownership payload and the check pattern into a decoded image.
+    ///
+    /// # Returns
+    ///
+    /// The watermarked image as 8-bit RGB, which must be saved in a lossless format for the
+    /// integrity check to pass.
+    ///
+    /// Fails if the watermark is empty or the image has fewer blocks than it has bits.
+    pub fn embed(&self, img: &DynamicImage, watermark: &BitSlice<u8>) -> Result<DynamicImage> {
+        check_capacity(img, watermark.len())?;
+        let ycbcr: YCrBrAMat = img.to_rgba32f().into();
+        let mut dwted = ycbcr.add_padding().dwt();
+        dwted.embed_check_pattern(&self.fragile);
+        let marked = render_imbedded(dwted.cut().embed_watermark_bits(watermark, &self.robust));
+        // The later rounds restore the check pattern where the first one clipped pixels
+        Ok((1..FRAGILE_ROUNDS).fold(marked, |marked, _| fragile_round(&marked, &self.fragile)))
+    }
+
+    /// Extracts the ownership payload of `wm_len` bits and locates the tampered regions of a
+    /// decoded image, from a single wavelet transform.
+    ///
+    /// Fails if `wm_len` is zero or the image, e.g. once cropped, has fewer blocks than
+    /// `wm_len`.
+    pub fn verify(&self, img: &DynamicImage, wm_len: usize) -> Result<Verification> {
+        check_capacity(img, wm_len)?;
+        let ycbcr: YCrBrAMat = img.to_rgba32f().into();
+        let dwted = ycbcr.add_padding().dwt();
+        let tamper = dwted.check_pattern(&self.fragile);
+        let soft_bits = dwted.cut().extract_soft_bits(wm_len, &self.robust);
+        Ok(Verification {
+            owner: soft_to_hard_bits(&soft_bits),
+            confidence: extraction_confidence(&soft_bits),
+            tampered_regions: tamper.tampered_regions(),
+            tamper,
+        })
+    }
+}
+
+impl Verification {
+    /// The ownership payload as text, if it is valid UTF-8.
+    pub fn owner_string(&self) -> Option<String> {
+        if self.owner.len() % 8 != 0 {
+            return None;
+        }
+        String::from_utf8(self.owner.clone().into_vec()).ok()
+    }
+
+    /// Whether no region was found tampered.
+    pub fn is_intact(&self) -> bool {
+        self.tampered_regions.is_empty()
+    }
+}
+
+impl fmt::Display for Verification {
+    /// `owner = "<text>"` (or the bits when not text), then the tampered regions as
+    /// `<width>x<height>+<x>+<y>`, or `none`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.owner_string() {
+            Some(owner) => write!(f, "owner = {owner:?}")?,
+            None => {
+                let bits: String = self
+                    .owner
+                    .iter()
+                    .map(|b| if *b { '1' } else { '0' })
+                    .collect();
+                write!(f, "owner = {bits}")?
+            }
+        }
+        write!(f, ", tampered regions = ")?;
+        if self.is_intact() {
+            return write!(f, "none");
+        }
+        for (i, region) in self.tampered_regions.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{region}")?;
+        }
+        Ok(())
+    }
+}
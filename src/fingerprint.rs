@@ -0,0 +1,227 @@
+use anyhow::{Context, Result, bail};
+use bitvec::prelude::*;
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{config::WatermarkConfig, decomposition::Decomposition};
+
+/// Embeds a different watermark into copies of the same image.
+///
+/// The image is decomposed once; every copy only repeats the embedding and the inverse
+/// transform, see [`Decomposition`].
+pub struct Fingerprinter {
+    decomposition: Decomposition,
+    config: WatermarkConfig,
+}
+
+impl Fingerprinter {
+    /// Decomposes `img` for fingerprinting with `config`.
+    pub fn new(img: &DynamicImage, config: &WatermarkConfig) -> Self {
+        Fingerprinter {
+            decomposition: Decomposition::new(img),
+            config: config.clone(),
+        }
+    }
+
+    /// Maximum number of watermark bits a copy can carry.
+    pub fn capacity(&self) -> usize {
+        self.decomposition.capacity()
+    }
+
+    /// Returns a copy of the image carrying `watermark`, as 8-bit RGB.
+    pub fn render(&self, watermark: &BitSlice<u8>) -> Result<DynamicImage> {
+        if watermark.is_empty() || watermark.len() > self.capacity() {
+            bail!(
+                "watermark of {} bits does not fit, the image holds at most {}",
+                watermark.len(),
+                self.capacity()
+            );
+        }
+        Ok(self.decomposition.render(watermark, &self.config))
+    }
+}
+
+/// Part of a [`PayloadTemplate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Field(String),
+}
+
+/// Text with `{field}` placeholders, e.g. `{customer_id}-{date}`.
+///
+/// Literal braces are written `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl PayloadTemplate {
+    /// Names of the fields used by the template, in order of appearance.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            TemplatePart::Field(name) => Some(name.as_str()),
+            TemplatePart::Literal(_) => None,
+        })
+    }
+
+    /// Substitutes every field with its value from `lookup`.
+    pub fn render<'a>(&self, lookup: impl Fn(&str) -> Option<&'a str>) -> Result<String> {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => rendered.push_str(text),
+                TemplatePart::Field(name) => rendered
+                    .push_str(lookup(name).with_context(|| format!("no value for {{{name}}}"))?),
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+impl FromStr for PayloadTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => bail!("unclosed placeholder in template: {s}"),
+                            Some(c) => name.push(c),
+                        }
+                    }
+                    let name = name.trim();
+                    if name.is_empty() {
+                        bail!("empty placeholder in template: {s}");
+                    }
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Field(name.to_owned()));
+                }
+                '}' => bail!("unmatched '}}' in template: {s}"),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(PayloadTemplate { parts })
+    }
+}
+
+impl fmt::Display for PayloadTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => {
+                    write!(f, "{}", text.replace('{', "{{").replace('}', "}}"))?
+                }
+                TemplatePart::Field(name) => write!(f, "{{{name}}}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Row of a recipients file, by column name
+pub type Recipient = HashMap<String, String>;
+
+/// Reads a CSV file of recipients with a header row.
+pub fn read_recipients<P: AsRef<Path>>(path: P) -> Result<Vec<Recipient>> {
+    let path = path.as_ref();
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("failed to read recipients {}", path.display()))?;
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.trim().to_owned())
+        .collect();
+    reader
+        .records()
+        .map(|record| {
+            let record = record?;
+            Ok(headers
+                .iter()
+                .cloned()
+                .zip(record.iter().map(|v| v.trim().to_owned()))
+                .collect())
+        })
+        .collect()
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Gregorian date of a day count since 1970-01-01 (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let template: PayloadTemplate = "{customer_id}-{ date }".parse().unwrap();
+        assert_eq!(
+            template.fields().collect::<Vec<_>>(),
+            ["customer_id", "date"]
+        );
+
+        let values = HashMap::from([("customer_id", "c42"), ("date", "2024-05-01")]);
+        let rendered = template.render(|name| values.get(name).copied()).unwrap();
+        assert_eq!(rendered, "c42-2024-05-01");
+        assert!(template.render(|_| None).is_err());
+
+        let escaped: PayloadTemplate = "{{x}}{id}".parse().unwrap();
+        assert_eq!(escaped.render(|_| Some("7")).unwrap(), "{x}7");
+        assert_eq!(escaped.to_string(), "{{x}}{id}");
+
+        assert!("{}".parse::<PayloadTemplate>().is_err());
+        assert!("a}".parse::<PayloadTemplate>().is_err());
+        assert!("{id".parse::<PayloadTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+    }
+}
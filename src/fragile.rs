@@ -0,0 +1,316 @@
+//! Fragile watermark, for tamper detection and localization.
+//!
+//! Where the robust watermark hides a payload in the LL subbands and survives processing,
+//! the fragile watermark proves that an image was not edited. Every coefficient of the HH
+//! subbands of Y, Cb and Cr is quantized on one of two dithered lattices, following a keyed
+//! check pattern. These subbands carry the finest details: the pattern is invisible,
+//! survives saving to a lossless 8-bit format, and is destroyed by any local edit.
+//!
+//! Checking compares each 4×4 block of HH coefficients, i.e. each 8×8 pixel region, against
+//! the pattern, and yields a [`TamperMap`]:
+//!
+//! ```
+//! use blind_watermark::prelude::*;
+//! use image::{DynamicImage, Rgb, RgbImage};
+//!
+//! let img = DynamicImage::from(RgbImage::from_fn(64, 64, |x, y| {
+//!     Rgb([(x * 3) as u8 + 20, (y * 3) as u8 + 20, ((x ^ y) * 2) as u8 + 40])
+//! }));
+//! let config = FragileConfigBuilder::default().key(7).build().unwrap();
+//! let mut marked = embed_fragile_image(&img, &config).to_rgb8();
+//! assert!(!tamper_map_image(&marked.clone().into(), &config).is_tampered());
+//!
+//! // Paint over the 8×8 region of block (2, 5)
+//! for (x, y) in (40..48).flat_map(|x| (16..24).map(move |y| (x, y))) {
+//!     marked.put_pixel(x, y, Rgb([200, 30, 30]));
+//! }
+//! let map = tamper_map_image(&marked.into(), &config);
+//! assert_eq!(map.tampered_blocks(), vec![(2, 5)]);
+//! ```
+//!
+//! The pattern depends on the block position and the key, not on the content: blocks moved
+//! within the image are detected, but not blocks copied at the same position from another
+//! image marked with the same key. Lossy compression and resampling destroy the pattern as
+//! well, and the whole image is then reported as tampered.
+
+use image::{DynamicImage, GrayImage, Luma, RgbImage};
+use rand::Rng;
+use rayon::prelude::*;
+use std::fmt;
+
+use crate::{
+    BLOCK_SIZE, DwtedYCrBrAMat,
+    config::FragileConfig,
+    scalar::Scalar,
+    schemes::{keyed_rng, quantize, step},
+};
+
+/// Side of the pixel region covered by a block of HH coefficients
+pub const REGION_SIZE: usize = 2 * BLOCK_SIZE;
+
+/// Outcome of a tamper check, with one score per 8×8 pixel region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TamperMap {
+    /// Fraction of the coefficients of each block not matching the check pattern, highest
+    /// over the channels, row by row
+    pub scores: Vec<f64>,
+    /// Whether each block is considered tampered
+    pub tampered: Vec<bool>,
+    /// Dimensions of the array of blocks (height, width)
+    pub blocks_dimensions: (usize, usize),
+    /// Dimensions of the checked image (height, width)
+    pub dimensions: (usize, usize),
+}
+
+/// Rectangle of pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// Column of the left edge
+    pub x: u32,
+    /// Row of the top edge
+    pub y: u32,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl TamperMap {
+    /// Whether any block is considered tampered.
+    pub fn is_tampered(&self) -> bool {
+        self.tampered.iter().any(|&tampered| tampered)
+    }
+
+    /// Fraction of the blocks considered tampered.
+    pub fn tampered_fraction(&self) -> f64 {
+        if self.tampered.is_empty() {
+            return 0.0;
+        }
+        self.tampered.iter().filter(|&&tampered| tampered).count() as f64
+            / self.tampered.len() as f64
+    }
+
+    /// (row, column) of the tampered blocks, row by row.
+    ///
+    /// Block `(i, j)` covers the pixels from `(i, j) * REGION_SIZE`, see [`REGION_SIZE`].
+    pub fn tampered_blocks(&self) -> Vec<(usize, usize)> {
+        let cols = self.blocks_dimensions.1;
+        (0..self.tampered.len())
+            .filter(|&i| self.tampered[i])
+            .map(|i| (i / cols, i % cols))
+            .collect()
+    }
+
+    /// Bounding boxes of the groups of touching tampered blocks, clipped to the image, in
+    /// the order of their first block.
+    pub fn tampered_regions(&self) -> Vec<Region> {
+        let (rows, cols) = self.blocks_dimensions;
+        let mut seen = vec![false; self.tampered.len()];
+        let mut regions = Vec::new();
+        for start in 0..self.tampered.len() {
+            if !self.tampered[start] || seen[start] {
+                continue;
+            }
+            // Flood fill over the 8 neighbours, tracking the bounds in blocks
+            let (mut top, mut left, mut bottom, mut right) = (rows, cols, 0, 0);
+            let mut stack = vec![start];
+            seen[start] = true;
+            while let Some(i) = stack.pop() {
+                let (row, col) = (i / cols, i % cols);
+                (top, left) = (top.min(row), left.min(col));
+                (bottom, right) = (bottom.max(row), right.max(col));
+                for r in row.saturating_sub(1)..(row + 2).min(rows) {
+                    for c in col.saturating_sub(1)..(col + 2).min(cols) {
+                        let j = r * cols + c;
+                        if self.tampered[j] && !seen[j] {
+                            seen[j] = true;
+                            stack.push(j);
+                        }
+                    }
+                }
+            }
+            let (height, width) = self.dimensions;
+            let (y, x) = (top * REGION_SIZE, left * REGION_SIZE);
+            regions.push(Region {
+                x: x as u32,
+                y: y as u32,
+                width: (((right + 1) * REGION_SIZE).min(width) - x) as u32,
+                height: (((bottom + 1) * REGION_SIZE).min(height) - y) as u32,
+            });
+        }
+        regions
+    }
+
+    /// Mask of the size of the image, white over tampered regions and black elsewhere.
+    pub fn to_image(&self) -> GrayImage {
+        let (height, width) = self.dimensions;
+        GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            Luma([if self.pixel_tampered(x, y) { 255 } else { 0 }])
+        })
+    }
+
+    /// Copy of `img` with the tampered regions tinted red, for review.
+    pub fn highlight(&self, img: &DynamicImage) -> RgbImage {
+        let mut highlighted = img.to_rgb8();
+        for (x, y, pixel) in highlighted.enumerate_pixels_mut() {
+            if self.pixel_tampered(x, y) {
+                let [r, g, b] = pixel.0;
+                pixel.0 = [r / 2 + 128, g / 2, b / 2];
+            }
+        }
+        highlighted
+    }
+
+    fn pixel_tampered(&self, x: u32, y: u32) -> bool {
+        let (row, col) = (y as usize / REGION_SIZE, x as usize / REGION_SIZE);
+        let (rows, cols) = self.blocks_dimensions;
+        row < rows && col < cols && self.tampered[row * cols + col]
+    }
+}
+
+impl<T: Scalar> DwtedYCrBrAMat<T> {
+    /// Quantizes the HH subbands of Y, Cb and Cr on the check pattern of `config`.
+    ///
+    /// The other subbands are left untouched, so that a robust watermark can be embedded
+    /// in the LL subbands as well.
+    pub fn embed_check_pattern(&mut self, config: &FragileConfig) {
+        let area = self.checked_area();
+        let delta = step(config.strength);
+        [&mut self.y.3, &mut self.cb.3, &mut self.cr.3]
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(channel, hh)| {
+                for_each_coefficient(area, config.key, channel, |_, (r, c), bit, dither| {
+                    let value = hh[(r, c)].as_f64();
+                    hh[(r, c)] = T::from_f64(quantize(value, bit, dither * delta, delta));
+                });
+            });
+    }
+
+    /// Compares the HH subbands of Y, Cb and Cr against the check pattern of `config`.
+    pub fn check_pattern(&self, config: &FragileConfig) -> TamperMap {
+        let area = self.checked_area();
+        let blocks_dimensions = block_grid(area);
+        let nblocks = blocks_dimensions.0 * blocks_dimensions.1;
+        let delta = step(config.strength);
+
+        // (mismatching, total) coefficients of each block, per channel
+        let counts: Vec<Vec<(usize, usize)>> = [&self.y.3, &self.cb.3, &self.cr.3]
+            .into_par_iter()
+            .enumerate()
+            .map(|(channel, hh)| {
+                let mut counts = vec![(0, 0); nblocks];
+                for_each_coefficient(area, config.key, channel, |i, (r, c), bit, dither| {
+                    let value = hh[(r, c)].as_f64();
+                    let distance =
+                        |bit| (value - quantize(value, bit, dither * delta, delta)).abs();
+                    counts[i].0 += usize::from(distance(bit) > distance(!bit));
+                    counts[i].1 += 1;
+                });
+                counts
+            })
+            .collect();
+
+        let scores: Vec<f64> = (0..nblocks)
+            .map(|i| {
+                counts
+                    .iter()
+                    .map(|channel| match channel[i] {
+                        (_, 0) => 0.0,
+                        (mismatching, total) => mismatching as f64 / total as f64,
+                    })
+                    .fold(0.0, f64::max)
+            })
+            .collect();
+        TamperMap {
+            tampered: scores
+                .iter()
+                .map(|&score| score > config.tolerance)
+                .collect(),
+            scores,
+            blocks_dimensions,
+            dimensions: self.original_dimensions,
+        }
+    }
+
+    /// (rows, columns) of HH coefficients computed from image pixels only; those of the
+    /// padding row or column would not survive its removal
+    fn checked_area(&self) -> (usize, usize) {
+        let (height, width) = self.original_dimensions;
+        (height / 2, width / 2)
+    }
+}
+
+/// Number of blocks (rows, columns) covering the checked coefficients
+fn block_grid((rows, cols): (usize, usize)) -> (usize, usize) {
+    (rows.div_ceil(BLOCK_SIZE), cols.div_ceil(BLOCK_SIZE))
+}
+
+/// Calls `f` with the block index, the position, the expected bit and the dither (as a
+/// fraction of a step) of every checked coefficient of a channel.
+fn for_each_coefficient(
+    area: (usize, usize),
+    key: u64,
+    channel: usize,
+    mut f: impl FnMut(usize, (usize, usize), bool, f64),
+) {
+    let (rows, cols) = block_grid(area);
+    for i in 0..rows * cols {
+        let mut rng = keyed_rng(key, 3 * i + channel);
+        let (top, left) = (i / cols * BLOCK_SIZE, i % cols * BLOCK_SIZE);
+        for r in top..top + BLOCK_SIZE {
+            for c in left..left + BLOCK_SIZE {
+                // Drawn for every coefficient, so that partial blocks keep the same pattern
+                let (bit, dither) = (rng.random(), rng.random());
+                if r < area.0 && c < area.1 {
+                    f(i, (r, c), bit, dither);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tampered_regions_group_touching_blocks() {
+        // 3×5 blocks over a 20×36 image, the last row and column partial
+        let tampered = [
+            [true, false, false, false, true],
+            [false, true, false, false, true],
+            [false, false, false, false, false],
+        ];
+        let map = TamperMap {
+            scores: vec![0.0; 15],
+            tampered: tampered.into_iter().flatten().collect(),
+            blocks_dimensions: (3, 5),
+            dimensions: (20, 36),
+        };
+        assert_eq!(
+            map.tampered_regions(),
+            vec![
+                Region {
+                    x: 0,
+                    y: 0,
+                    width: 16,
+                    height: 16
+                },
+                Region {
+                    x: 32,
+                    y: 0,
+                    width: 4,
+                    height: 16
+                },
+            ]
+        );
+        assert_eq!(map.tampered_regions()[1].to_string(), "4x16+32+0");
+    }
+}
@@ -11,14 +11,32 @@
 //！- **Random Strategy**: Supports randomized block selection for embedding watermarks, enhancing security.
 //！- **High-Level API**: Provides a fluent API for easy integration.
 
+pub mod attacks;
 pub mod config;
+pub mod decomposition;
+pub mod detection;
+pub mod dual;
+pub mod fingerprint;
+pub mod fragile;
+pub mod manifest;
+pub mod metrics;
+pub mod pipeline;
 pub mod prelude;
+pub mod profile;
 pub(crate) mod quantization;
+pub mod report;
+pub mod scalar;
+pub mod schemes;
+pub mod sidecar;
 pub mod strategy;
+pub mod tardos;
+pub mod tiled;
 pub mod transform;
+pub mod tuning;
 pub mod utils;
 
 use faer::prelude::*;
+use scalar::Scalar;
 const BLOCK_SIZE: usize = 4;
 
 /// Matrix representation of an image in YCbCrA color space.
@@ -26,15 +44,15 @@ const BLOCK_SIZE: usize = 4;
 /// This struct holds the image data separated into Y (Luminance), Cb (Blue-difference),
 /// Cr (Red-difference), and A (Alpha) channels.
 #[derive(Clone, Debug)]
-pub struct YCrBrAMat {
+pub struct YCrBrAMat<T: Scalar = f32> {
     /// Y channel (Luminance)
-    pub y: Mat<f32>,
+    pub y: Mat<T>,
     /// Cb channel (Blue-difference)
-    pub cb: Mat<f32>,
+    pub cb: Mat<T>,
     /// Cr channel (Red-difference)
-    pub cr: Mat<f32>,
+    pub cr: Mat<T>,
     /// Alpha channel
-    pub a: Mat<f32>,
+    pub a: Mat<T>,
     /// Dimensions (height, width)
     pub dimensions: (usize, usize),
 }
@@ -43,15 +61,15 @@ pub struct YCrBrAMat {
 ///
 /// Padding is added to ensure the image dimensions are suitable for DWT (Discrete Wavelet Transform).
 #[derive(Clone, Debug)]
-pub struct PaddedYCrBrAMat {
+pub struct PaddedYCrBrAMat<T: Scalar = f32> {
     /// Y channel (Luminance) with padding
-    pub y: Mat<f32>,
+    pub y: Mat<T>,
     /// Cb channel (Blue-difference) with padding
-    pub cb: Mat<f32>,
+    pub cb: Mat<T>,
     /// Cr channel (Red-difference) with padding
-    pub cr: Mat<f32>,
+    pub cr: Mat<T>,
     /// Alpha channel with padding
-    pub a: Mat<f32>,
+    pub a: Mat<T>,
     /// Original dimensions (height, width) before padding
     pub original_dimensions: (usize, usize),
 }
@@ -61,16 +79,16 @@ pub struct PaddedYCrBrAMat {
 /// The DWT decomposes the image into four subbands: LL (Approximation), HL (Horizontal Detail),
 /// LH (Vertical Detail), and HH (Diagonal Detail).
 #[derive(Clone, Debug)]
-pub struct DwtedYCrBrAMat {
+pub struct DwtedYCrBrAMat<T: Scalar = f32> {
     /// Y channel components (LL, HL, LH, HH)
-    pub y: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub y: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cb channel components (LL, HL, LH, HH)
-    pub cb: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cb: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cr channel components (LL, HL, LH, HH)
-    pub cr: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cr: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
 
     /// Alpha channel (not transformed)
-    pub a: Mat<f32>,
+    pub a: Mat<T>,
     /// Original dimensions (height, width)
     pub original_dimensions: (usize, usize),
 }
@@ -79,25 +97,25 @@ pub struct DwtedYCrBrAMat {
 /// This struct holds the blocks prepared for watermark embedding/extraction, along with
 /// the other subbands preserved for reconstruction.
 #[derive(Clone, Debug)]
-pub struct BlockCutted {
+pub struct BlockCutted<T: Scalar = f32> {
     // Take LL part only
     /// Blocks from Y channel LL subband
-    pub y_ll_blocks: Vec<Block>,
+    pub y_ll_blocks: Vec<Block<T>>,
     /// Blocks from Cb channel LL subband
-    pub cb_ll_blocks: Vec<Block>,
+    pub cb_ll_blocks: Vec<Block<T>>,
     /// Blocks from Cr channel LL subband
-    pub cr_ll_blocks: Vec<Block>,
+    pub cr_ll_blocks: Vec<Block<T>>,
 
     // Preserved for recovery
     /// Y channel components (LL, HL, LH, HH)
-    pub y: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub y: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cb channel components (LL, HL, LH, HH)
-    pub cb: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cb: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cr channel components (LL, HL, LH, HH)
-    pub cr: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cr: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
 
     /// Alpha channel
-    pub a: Mat<f32>,
+    pub a: Mat<T>,
     /// Original dimensions (height, width)
     pub original_dimensions: (usize, usize),
     /// Dimensions of array of blocks (height, width)
@@ -106,34 +124,34 @@ pub struct BlockCutted {
 
 /// A single block of the image (typically 4x4).
 #[derive(Clone, Debug)]
-pub struct Block {
+pub struct Block<T: Scalar = f32> {
     /// 4×4 block matrix
-    pub mat_data: Mat<f32>,
+    pub mat_data: Mat<T>,
     // index in some serial, for strategy purpose
     //pub index: usize,
 }
 
 /// Image data with watermark embedded in the blocks.
 #[derive(Clone, Debug)]
-pub struct Imbedded {
+pub struct Imbedded<T: Scalar = f32> {
     // Take LL part only
     /// Watermarked blocks from Y channel LL subband
-    pub y_ll_blocks: Vec<Block>,
+    pub y_ll_blocks: Vec<Block<T>>,
     /// Watermarked blocks from Cb channel LL subband
-    pub cb_ll_blocks: Vec<Block>,
+    pub cb_ll_blocks: Vec<Block<T>>,
     /// Watermarked blocks from Cr channel LL subband
-    pub cr_ll_blocks: Vec<Block>,
+    pub cr_ll_blocks: Vec<Block<T>>,
 
     // Preserved for recovery
     /// Y channel components (LL, HL, LH, HH)
-    pub y: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub y: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cb channel components (LL, HL, LH, HH)
-    pub cb: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cb: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cr channel components (LL, HL, LH, HH)
-    pub cr: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cr: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
 
     /// Alpha channel
-    pub a: Mat<f32>,
+    pub a: Mat<T>,
     /// Original dimensions (height, width)
     pub original_dimensions: (usize, usize),
     /// Block dimensions (height, width)
@@ -145,25 +163,25 @@ pub struct Imbedded {
 /// This struct represents the state after reassembling the blocks into the LL subband,
 /// ready for Inverse DWT.
 #[derive(Clone, Debug)]
-pub struct AssembledYCrBrAMat {
+pub struct AssembledYCrBrAMat<T: Scalar = f32> {
     /// Y channel components (LL, HL, LH, HH)
-    pub y: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub y: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cb channel components (LL, HL, LH, HH)
-    pub cb: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cb: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
     /// Cr channel components (LL, HL, LH, HH)
-    pub cr: (Mat<f32>, Mat<f32>, Mat<f32>, Mat<f32>),
+    pub cr: (Mat<T>, Mat<T>, Mat<T>, Mat<T>),
 
     /// Alpha channel
-    pub a: Mat<f32>,
+    pub a: Mat<T>,
     /// Original dimensions (height, width)
     pub original_dimensions: (usize, usize),
 }
 
-impl Imbedded {
+impl<T: Scalar> Imbedded<T> {
     /// Assembles the blocks back into the LL subband of the Y, Cb, and Cr channels.
     ///
     /// This reverses the block cutting process, preparing the data for the Inverse DWT.
-    pub fn assemble(self) -> AssembledYCrBrAMat {
+    pub fn assemble(self) -> AssembledYCrBrAMat<T> {
         let (block_count_height, block_count_width) = self.blocks_dimensions;
         //write back to ll part of y, cb, cr
         let mut y_ll = self.y.0;
@@ -1,29 +1,158 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use bitvec::prelude::*;
+use blind_watermark::attacks::{Attack, benchmark};
+use blind_watermark::fingerprint::{Fingerprinter, PayloadTemplate, read_recipients, today};
+use blind_watermark::manifest::{Manifest, ManifestEntry, audit_entry};
+use blind_watermark::metrics::compare_image_files;
 use blind_watermark::prelude::*;
-use clap::{ArgGroup, Args, Parser, Subcommand};
+use blind_watermark::profile::{ConfigFile, PayloadEncoding, Profile, default_config_path};
+use blind_watermark::sidecar::{read_sidecar, sidecar_path};
+use blind_watermark::tardos::{TardosCode, codeword_hex};
+use blind_watermark::transform::embed::{extraction_confidence, soft_to_hard_bits};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use std::path::PathBuf;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
 
-fn main() {
+fn main() -> ExitCode {
     let cli = Cli::parse();
-
-    match cli.command {
-        Commands::Embed(args) => run_embed(args),
-        Commands::Extract(args) => run_extract(args),
+    if !std::io::stdout().is_terminal() {
+        colored::control::set_override(false);
     }
+
+    let format = cli.output_format;
+    let result = load_profile(cli.config.as_deref(), cli.profile.as_deref()).and_then(|profile| {
+        match cli.command {
+            Commands::Embed(args) => run_embed(args, &profile, format),
+            Commands::Extract(args) => run_extract(args, &profile, format),
+            Commands::Compare(args) => run_compare(args, format),
+            Commands::Bench(args) => run_bench(args, &profile, format),
+            Commands::Verify(args) => run_verify(args, &profile, format),
+            Commands::Detect(args) => run_detect(args, &profile, format),
+            Commands::Audit(args) => run_audit(args, &profile, format),
+            Commands::Fingerprint(args) => run_fingerprint(args, &profile, format),
+            Commands::Tardos(TardosCommands::Generate(args)) => run_tardos_generate(args, format),
+            Commands::Tardos(TardosCommands::Accuse(args)) => {
+                run_tardos_accuse(args, &profile, format)
+            }
+        }
+    });
+
+    result.unwrap_or_else(|e| {
+        match format {
+            OutputFormat::Json => print_json(json!({ "error": error_message(&e) })),
+            OutputFormat::Text => eprintln!("{} {}", "error:".red().bold(), error_message(&e)),
+        }
+        ExitCode::FAILURE
+    })
 }
 
 /// Watermark CLI tool
 #[derive(Parser, Debug)]
 #[command(name = "watermark", version, about, author)]
 pub struct Cli {
+    /// Format of the results printed to stdout
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output_format: OutputFormat,
+
+    /// Configuration file (defaults to ~/.config/blind_watermark/config.toml when present)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Profile of the configuration file to use (defaults to its `default_profile`)
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Output format shared by all subcommands
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable, colored when stdout is a terminal
+    Text,
+    /// JSON objects, with bits as hex and timings in milliseconds
+    Json,
+}
+
+/// Watermark settings taking precedence over the profile
+#[derive(Args, Debug)]
+pub struct WatermarkArgs {
+    /// Quantization strength of the first singular value
+    #[arg(long = "strength-1", value_parser = clap::value_parser!(i32).range(1..=255))]
+    pub strength_1: Option<i32>,
+
+    /// Quantization strength of the second singular value
+    #[arg(long = "strength-2", value_parser = clap::value_parser!(i32).range(1..=255))]
+    pub strength_2: Option<i32>,
+
+    /// Do not quantize the second singular value, whatever the seed or profile
+    #[arg(long = "no-strength-2", conflicts_with = "strength_2")]
+    pub no_strength_2: bool,
+
+    /// Embedding scheme: svd_quantization, spread_spectrum, dither_modulation,
+    /// spread_transform_dither or rational_dither
+    #[arg(long)]
+    pub scheme: Option<String>,
+
+    /// Secret key of the embedding scheme, required by all but svd_quantization
+    #[arg(long = "scheme-key")]
+    pub scheme_key: Option<u64>,
+
+    /// Encoding of the watermark string: utf8 or hex
+    #[arg(long)]
+    pub encoding: Option<PayloadEncoding>,
+}
+
+impl WatermarkArgs {
+    /// Applies the strengths given on the command line on top of `config`.
+    fn apply(&self, config: WatermarkConfig) -> WatermarkConfig {
+        WatermarkConfig {
+            strength_1: self.strength_1.unwrap_or(config.strength_1),
+            strength_2: match self.no_strength_2 {
+                true => None,
+                false => self.strength_2.or(config.strength_2),
+            },
+            ..config
+        }
+    }
+
+    /// Embedding configuration: seed defaults, then the profile, then the command line.
+    fn config(&self, seed: Option<u64>, profile: &Profile) -> Result<WatermarkConfig> {
+        let config = self.with_scheme(config_from_seed(seed.or(profile.seed))?, profile)?;
+        Ok(self.apply(profile.apply(config)))
+    }
+
+    /// Key of the embedding scheme, from the command line or the profile.
+    fn scheme_key(&self, profile: &Profile) -> Option<u64> {
+        self.scheme_key.or(profile.scheme_key)
+    }
+
+    /// Sets the scheme selected on the command line or in the profile, if any, with its key.
+    fn with_scheme(&self, config: WatermarkConfig, profile: &Profile) -> Result<WatermarkConfig> {
+        let Some(kind) = self.scheme.as_deref().or(profile.scheme.as_deref()) else {
+            return Ok(config);
+        };
+        Ok(WatermarkConfig {
+            scheme: EmbeddingScheme::from_kind(kind, self.scheme_key(profile))?,
+            ..config
+        })
+    }
+
+    fn encoding(&self, profile: &Profile) -> PayloadEncoding {
+        self.encoding.or(profile.encoding).unwrap_or_default()
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Embed a watermark
@@ -31,13 +160,44 @@ pub enum Commands {
 
     /// Extract a watermark
     Extract(ExtractArgs),
+
+    /// Compare an original and a watermarked image
+    Compare(CompareArgs),
+
+    /// Measure watermark robustness against common attacks
+    Bench(BenchArgs),
+
+    /// Check that an image carries the expected watermark
+    Verify(VerifyArgs),
+
+    /// Test whether an image carries a watermark (exits with 1 when absent)
+    Detect(DetectArgs),
+
+    /// Re-extract the outputs listed in an embedding manifest and check them
+    Audit(AuditArgs),
+
+    /// Embed a different watermark into one copy of an image per recipient
+    Fingerprint(FingerprintArgs),
+
+    /// Collusion-resistant fingerprint codes
+    #[command(subcommand)]
+    Tardos(TardosCommands),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TardosCommands {
+    /// Generate a code word per recipient, to embed with `fingerprint -t {tardos} --encoding hex`
+    Generate(TardosGenerateArgs),
+
+    /// Rank the recipients whose copies were mixed into an image (exits with 1 when nobody is accused)
+    Accuse(TardosAccuseArgs),
 }
 
 #[derive(Args, Debug)]
 #[command(group(
     ArgGroup::new("output_mode")
-        .required(true)
-        .args(["output", "prefix"])
+        .multiple(true)
+        .args(["output", "prefix", "out_dir"])
 ))]
 pub struct EmbedArgs {
     /// Input file or directory
@@ -48,27 +208,90 @@ pub struct EmbedArgs {
     #[arg(short, long)]
     pub string: String,
 
-    /// Output file (conflicts with --prefix)
-    #[arg(short, long, conflicts_with = "prefix")]
+    /// Output file (conflicts with --prefix and --out-dir)
+    #[arg(short, long, conflicts_with_all = ["prefix", "out_dir"])]
     pub output: Option<PathBuf>,
 
     /// Prefix for batch mode (conflicts with --output)
     #[arg(short, long, conflicts_with = "output")]
     pub prefix: Option<String>,
 
+    /// Write batch outputs under this directory, mirroring the input tree
+    #[arg(long, conflicts_with = "output")]
+    pub out_dir: Option<PathBuf>,
+
+    /// Replace existing outputs in batch mode
+    #[arg(long, conflicts_with = "skip_existing")]
+    pub overwrite: bool,
+
+    /// Leave existing outputs untouched in batch mode
+    #[arg(long)]
+    pub skip_existing: bool,
+
+    /// Convert batch outputs to this image format
+    #[arg(long, value_enum)]
+    pub convert: Option<ConvertFormat>,
+
     /// Optional seed
     #[arg(long)]
     pub seed: Option<u64>,
 
+    #[command(flatten)]
+    pub watermark: WatermarkArgs,
+
     /// Recursively scan directory
     #[arg(short, long)]
     pub recursive: bool,
+
+    /// Use the strongest strengths keeping RGB PSNR above this value (dB)
+    #[arg(long, conflicts_with = "target_ssim")]
+    pub target_psnr: Option<f64>,
+
+    /// Use the strongest strengths keeping SSIM (Y channel) above this value
+    #[arg(long, conflicts_with = "target_psnr")]
+    pub target_ssim: Option<f64>,
+
+    /// Write the files that failed in batch mode to this CSV file
+    #[arg(long)]
+    pub failure_report: Option<PathBuf>,
+
+    /// Record hashes, payload, strengths and PSNR of every output in this JSON manifest
+    #[arg(long)]
+    pub manifest: Option<PathBuf>,
+}
+
+/// Image format of converted batch outputs
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ConvertFormat {
+    Png,
+    Jpg,
+    Webp,
+}
+
+impl ConvertFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ConvertFormat::Png => "png",
+            ConvertFormat::Jpg => "jpg",
+            ConvertFormat::Webp => "webp",
+        }
+    }
+}
+
+impl EmbedArgs {
+    fn quality_target(&self) -> Option<QualityTarget> {
+        match (self.target_psnr, self.target_ssim) {
+            (Some(psnr), _) => Some(QualityTarget::Psnr(psnr)),
+            (_, Some(ssim)) => Some(QualityTarget::Ssim(ssim)),
+            (None, None) => None,
+        }
+    }
 }
 
 #[derive(Args, Debug)]
 pub struct ExtractArgs {
-    /// Input file
-    #[arg(short, long, value_parser = is_file)]
+    /// Input file or directory
+    #[arg(short, long, value_parser = exists)]
     pub input: PathBuf,
 
     /// Expected watermark length
@@ -78,6 +301,228 @@ pub struct ExtractArgs {
     /// Optional seed
     #[arg(short, long)]
     pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub watermark: WatermarkArgs,
+
+    /// Sidecar with the embedding strengths (defaults to <INPUT>.bwm when present)
+    #[arg(long)]
+    pub sidecar: Option<PathBuf>,
+
+    /// Recursively scan directory
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Only process files matching this glob in batch mode (repeatable)
+    #[arg(short, long)]
+    pub glob: Vec<String>,
+
+    /// Format of batch results, regardless of --output-format
+    #[arg(short, long, value_enum, default_value_t = BatchFormat::Jsonl)]
+    pub format: BatchFormat,
+}
+
+/// Output format of batch extraction, one record per file
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum BatchFormat {
+    /// Comma-separated values with a header row
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// Input file
+    #[arg(short, long, value_parser = is_file)]
+    pub input: PathBuf,
+
+    /// Expected watermark string
+    #[arg(short, long)]
+    pub expect: String,
+
+    /// Optional seed
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub watermark: WatermarkArgs,
+
+    /// Sidecar with the embedding strengths (defaults to <INPUT>.bwm when present)
+    #[arg(long)]
+    pub sidecar: Option<PathBuf>,
+
+    /// Highest bit error rate still accepted
+    #[arg(long, default_value_t = 0.0)]
+    pub max_ber: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct DetectArgs {
+    /// Input file
+    #[arg(short, long, value_parser = is_file)]
+    pub input: PathBuf,
+
+    /// Expected watermark string, for a stronger test
+    #[arg(short, long)]
+    pub expect: Option<String>,
+
+    /// Optional seed
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub watermark: WatermarkArgs,
+
+    /// Sidecar with the embedding strengths (defaults to <INPUT>.bwm when present)
+    #[arg(long)]
+    pub sidecar: Option<PathBuf>,
+
+    /// Probability of reporting a watermark in an unmarked image
+    #[arg(long, default_value_t = 1e-6)]
+    pub false_positive: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct FingerprintArgs {
+    /// Input image
+    #[arg(short, long, value_parser = is_file)]
+    pub input: PathBuf,
+
+    /// CSV file of recipients, with a header row
+    #[arg(long, value_parser = is_file)]
+    pub recipients: PathBuf,
+
+    /// Watermark template filled from the recipient's columns, e.g. `{customer_id}-{date}`
+    ///
+    /// `{row}` (1-based), `{date}` (today, UTC) and `{stem}` (input file stem) are available
+    /// unless a column has the same name.
+    #[arg(short, long)]
+    pub template: PayloadTemplate,
+
+    /// Template of the copies' file stems, which keep the input's extension
+    #[arg(long, default_value = "{stem}-{row}")]
+    pub name: PayloadTemplate,
+
+    /// Directory of the copies
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Replace existing copies
+    #[arg(long)]
+    pub overwrite: bool,
+
+    /// Optional seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub watermark: WatermarkArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TardosGenerateArgs {
+    /// CSV file of recipients, with a header row
+    #[arg(long, value_parser = is_file)]
+    pub recipients: PathBuf,
+
+    /// Copy of the recipients file with an added `tardos` column holding the code words
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Secret code table, needed to accuse
+    #[arg(long)]
+    pub code: PathBuf,
+
+    /// Maximum number of colluders to resist
+    #[arg(short, long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..))]
+    pub colluders: u16,
+
+    /// Probability of accusing any innocent recipient
+    #[arg(long, default_value_t = 1e-3)]
+    pub false_positive: f64,
+
+    /// Seed of the code generation (random when omitted)
+    #[arg(long)]
+    pub code_seed: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct TardosAccuseArgs {
+    /// Input file
+    #[arg(short, long, value_parser = is_file)]
+    pub input: PathBuf,
+
+    /// Code table written by `tardos generate`
+    #[arg(long, value_parser = exists)]
+    pub code: PathBuf,
+
+    /// Optional seed
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub watermark: WatermarkArgs,
+
+    /// Sidecar with the embedding strengths (defaults to <INPUT>.bwm when present)
+    #[arg(long)]
+    pub sidecar: Option<PathBuf>,
+
+    /// Number of highest-ranked recipients to list
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct AuditArgs {
+    /// Manifest written by `embed --manifest`
+    #[arg(value_parser = exists)]
+    pub manifest: PathBuf,
+
+    /// Seed used when embedding
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    /// Secret key of the embedding scheme, for manifests of keyed schemes
+    #[arg(long = "scheme-key")]
+    pub scheme_key: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Original image
+    #[arg(value_parser = is_file)]
+    pub reference: PathBuf,
+
+    /// Watermarked (or otherwise modified) image
+    #[arg(value_parser = is_file)]
+    pub distorted: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// Input image
+    #[arg(short, long, value_parser = is_file)]
+    pub input: PathBuf,
+
+    /// Watermark string
+    #[arg(short, long)]
+    pub string: String,
+
+    /// Optional seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[command(flatten)]
+    pub watermark: WatermarkArgs,
+
+    /// Attack as `name:parameter`, e.g. `jpeg:75` (repeatable, defaults to a standard suite)
+    #[arg(short, long = "attack", value_parser = parse_attack)]
+    pub attacks: Vec<Attack>,
+}
+
+fn parse_attack(s: &str) -> Result<Attack, String> {
+    s.parse().map_err(|e: anyhow::Error| e.to_string())
 }
 
 fn is_file(s: &str) -> Result<PathBuf, String> {
@@ -85,82 +530,1230 @@ fn is_file(s: &str) -> Result<PathBuf, String> {
     if p.is_file() {
         Ok(p)
     } else {
-        Err("extract --input only accepts a single file".into())
+        Err(format!("{} is not a file", p.display()))
     }
 }
 
-fn run_embed(args: EmbedArgs) {
-    if let Some(out) = args.output {
-        embed_watermark_string(args.input, out, &args.string, args.seed).unwrap();
+fn exists(s: &str) -> Result<PathBuf, String> {
+    let p = PathBuf::from(s);
+    if p.exists() {
+        Ok(p)
     } else {
-        let walk = match args.recursive {
-            true => WalkBuilder::new(&args.input)
-                .standard_filters(true)
-                .hidden(false)
-                .build(),
-            false => WalkBuilder::new(&args.input)
-                .standard_filters(true)
-                .hidden(false)
-                .max_depth(Some(1))
-                .build(),
+        Err(format!("{} does not exist", p.display()))
+    }
+}
+
+/// Collects the image files under `input`, optionally filtered by glob patterns.
+fn collect_images(input: &Path, recursive: bool, globs: &[String]) -> Result<Vec<PathBuf>> {
+    let mut overrides = OverrideBuilder::new(input);
+    for glob in globs {
+        overrides.add(glob)?;
+    }
+
+    let walk = WalkBuilder::new(input)
+        .standard_filters(true)
+        .hidden(false)
+        .max_depth(if recursive { None } else { Some(1) })
+        .overrides(overrides.build()?)
+        .build();
+
+    let mut files: Vec<PathBuf> = walk
+        .filter_map(|e| {
+            if let Ok(e) = e
+                && let Some(ext) = e.path().extension()
+                && let Some(ext) = ext.to_str()
+                && matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp")
+            {
+                Some(e.path().to_path_buf())
+            } else {
+                None
+            }
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn progress_bar(len: usize, verb: &str) -> ProgressBar {
+    let pb = ProgressBar::new(len as u64);
+    let template = format!("   {verb:>9} [{{bar:50}}] {{pos}}/{{len}} ({{eta}})");
+    let style = ProgressStyle::with_template(&template)
+        .unwrap()
+        .progress_chars("=> ");
+    pb.set_style(style);
+    pb
+}
+
+fn print_json(value: Value) {
+    println!("{}", serde_json::to_string_pretty(&value).unwrap());
+}
+
+/// Lowercase hex of the bits, packed least significant bit first as they are embedded.
+fn to_hex(bits: &BitSlice<u8>) -> String {
+    bits.to_bitvec()
+        .into_vec()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Renders the error chain, skipping causes already spelled out by their parent.
+fn error_message(e: &anyhow::Error) -> String {
+    let mut message = String::new();
+    for cause in e.chain() {
+        let cause = cause.to_string();
+        if !message.contains(&cause) {
+            if !message.is_empty() {
+                message.push_str(": ");
+            }
+            message.push_str(&cause);
+        }
+    }
+    message
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn run_embed(args: EmbedArgs, profile: &Profile, format: OutputFormat) -> Result<ExitCode> {
+    let target = args.quality_target();
+    let config = args.watermark.config(args.seed, profile)?;
+    let payload = args.watermark.encoding(profile).encode(&args.string)?;
+    let bits = payload.view_bits::<Lsb0>();
+    // Returns the configuration used and, when a manifest is requested, the output's entry
+    let embed = |input: &Path, output: &Path| -> Result<(WatermarkConfig, Option<ManifestEntry>)> {
+        let config = match target {
+            None => {
+                embed_watermark_bits_with_config(input, output, bits, &config)?;
+                config.clone()
+            }
+            Some(target) => embed_watermark_bits_with_target(input, output, bits, &config, target)?,
         };
+        let entry = args
+            .manifest
+            .as_ref()
+            .map(|_| ManifestEntry::record(input, output, &args.string, bits, &config))
+            .transpose()?;
+        Ok((config, entry))
+    };
 
-        // Collect image files first
-        let files: Vec<PathBuf> = walk
-            .filter_map(|e| {
-                if let Ok(e) = e
-                    && let Some(ext) = e.path().extension()
-                    && let Some(ext) = ext.to_str()
-                    && matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp")
-                {
-                    Some(e.path().to_path_buf())
-                } else {
-                    None
+    if let Some(out) = &args.output {
+        let start = Instant::now();
+        let (config, entry) = embed(&args.input, out)?;
+        if let Some(manifest) = &args.manifest {
+            Manifest::new(entry.into_iter().collect()).write(manifest)?;
+        }
+        if format == OutputFormat::Json {
+            print_json(json!({
+                "input": args.input,
+                "output": out,
+                "payload": args.string,
+                "bits": to_hex(bits),
+                "strength_1": config.strength_1,
+                "strength_2": config.strength_2,
+                "scheme": config.scheme.kind(),
+                "elapsed_ms": elapsed_ms(start),
+            }));
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let batch_start = Instant::now();
+    // Output options of the command line take precedence over the profile
+    let out_dir = args.out_dir.as_deref().or(profile.out_dir.as_deref());
+    let prefix = args.prefix.as_ref().or(profile.prefix.as_ref());
+    let convert = match args.convert {
+        Some(convert) => Some(convert.extension()),
+        None => profile.convert.map(|format| format.extensions_str()[0]),
+    };
+    let overwrite = args.overwrite || (profile.overwrite && !args.skip_existing);
+    let skip_existing = args.skip_existing || (profile.skip_existing && !args.overwrite);
+
+    if prefix.is_none() && out_dir.is_none() {
+        bail!("one of --output, --prefix or --out-dir is required");
+    }
+    let prefix = prefix.map(String::as_str).unwrap_or_default();
+    if prefix.is_empty() && out_dir.is_none() && convert.is_none() {
+        bail!("--prefix must not be empty without --out-dir, or the inputs would be overwritten");
+    }
+
+    let mut files = collect_images(&args.input, args.recursive, &[])?;
+    // Outputs of an earlier run must not be embedded again
+    if let Some(out_dir) = out_dir
+        && let Ok(out_dir) = out_dir.canonicalize()
+    {
+        files.retain(|f| f.canonicalize().map_or(true, |f| !f.starts_with(&out_dir)));
+    }
+    let outputs = batch_outputs(&files, &args.input, out_dir, prefix, convert);
+    let pb = progress_bar(files.len(), "Embedding");
+
+    // Returns the output path, whether it was written and its manifest entry
+    let embed_file = |input: &Path,
+                      output: &Result<PathBuf>|
+     -> Result<(PathBuf, bool, Option<ManifestEntry>)> {
+        let output = match output {
+            Ok(output) => output.clone(),
+            Err(e) => bail!("{}", error_message(e)),
+        };
+        if output.exists() && !overwrite {
+            if skip_existing {
+                return Ok((output, false, None));
+            }
+            bail!(
+                "{} already exists (use --overwrite or --skip-existing)",
+                output.display()
+            );
+        }
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let (_, entry) = embed(input, &output)?;
+        Ok((output, true, entry))
+    };
+
+    // Multi-threaded processing, failures are collected instead of aborting the batch
+    let outcomes: Vec<EmbedOutcome> = files
+        .par_iter()
+        .zip(&outputs)
+        .map(|(input, output)| {
+            let start = Instant::now();
+            if format == OutputFormat::Text {
+                pb.println(format!(
+                    "   {} {}",
+                    "Embedding".green().bold(),
+                    input.display()
+                ));
+            }
+            // Run embed
+            let result = embed_file(input, output);
+
+            pb.inc(1);
+            let mut outcome = EmbedOutcome {
+                input: input.clone(),
+                output: None,
+                skipped: false,
+                entry: None,
+                elapsed_ms: elapsed_ms(start),
+                error: None,
+            };
+            match result {
+                Ok((output, written, entry)) => {
+                    outcome.output = Some(output);
+                    outcome.skipped = !written;
+                    outcome.entry = entry;
                 }
+                Err(e) => outcome.error = Some(error_message(&e)),
+            }
+            outcome
+        })
+        .collect();
+    pb.finish_and_clear();
+
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+    let skipped = outcomes.iter().filter(|o| o.skipped).count();
+    let succeeded = outcomes.len() - failed - skipped;
+    if let Some(report) = &args.failure_report {
+        write_failure_report(report, &outcomes)?;
+    }
+    if let Some(manifest) = &args.manifest {
+        let entries = outcomes.iter().filter_map(|o| o.entry.clone()).collect();
+        Manifest::new(entries).write(manifest)?;
+    }
+
+    match format {
+        OutputFormat::Json => {
+            let files: Vec<_> = outcomes
+                .iter()
+                .map(|o| {
+                    json!({
+                        "input": o.input,
+                        "output": o.output,
+                        "skipped": o.skipped,
+                        "elapsed_ms": o.elapsed_ms,
+                        "error": o.error,
+                    })
+                })
+                .collect();
+            print_json(json!({
+                "payload": args.string,
+                "bits": to_hex(bits),
+                "files": files,
+                "succeeded": succeeded,
+                "skipped": skipped,
+                "failed": failed,
+                "elapsed_ms": elapsed_ms(batch_start),
+            }));
+        }
+        OutputFormat::Text => {
+            println!("{}", "    Done embedding.".yellow().bold());
+            for o in &outcomes {
+                if let Some(error) = &o.error {
+                    println!(
+                        "   {} {}: {}",
+                        "Failed".red().bold(),
+                        o.input.display(),
+                        error
+                    );
+                }
+            }
+            let summary = format!("{succeeded} succeeded, {skipped} skipped, {failed} failed");
+            if failed == 0 {
+                println!("    {}", summary.green());
+            } else {
+                println!("    {}", summary.red());
+            }
+        }
+    }
+
+    Ok(if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Embedding result of a single file in batch mode
+struct EmbedOutcome {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    skipped: bool,
+    entry: Option<ManifestEntry>,
+    elapsed_ms: f64,
+    error: Option<String>,
+}
+
+/// Output path of `input` in batch mode.
+///
+/// The file name gets `prefix` prepended and, with `convert`, a new extension. Outputs go next
+/// to their input, or under `out_dir` at the same path relative to the scanned `root`.
+fn batch_output(
+    input: &Path,
+    root: &Path,
+    out_dir: Option<&Path>,
+    prefix: &str,
+    convert: Option<&str>,
+) -> Result<PathBuf> {
+    let name = input
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("illformed file name {}", input.display()))?;
+    let relative = input
+        .strip_prefix(root)
+        .ok()
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .unwrap_or(Path::new(name));
+
+    let output = match out_dir {
+        Some(out_dir) => out_dir.join(relative),
+        None => input.to_path_buf(),
+    };
+    let output = output.with_file_name(format!("{prefix}{name}"));
+    Ok(match convert {
+        Some(extension) => output.with_extension(extension),
+        None => output,
+    })
+}
+
+/// Output paths of `files` in batch mode, see [`batch_output`].
+///
+/// An output that is an input of the batch, or that several inputs map to, is an error for
+/// each input concerned, so that the batch never overwrites its own files.
+fn batch_outputs(
+    files: &[PathBuf],
+    root: &Path,
+    out_dir: Option<&Path>,
+    prefix: &str,
+    convert: Option<&str>,
+) -> Vec<Result<PathBuf>> {
+    let inputs: HashMap<PathBuf, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(i, input)| (resolved_path(input), i))
+        .collect();
+    let mut outputs: Vec<Result<PathBuf>> = files
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let output = batch_output(input, root, out_dir, prefix, convert)?;
+            match inputs.get(&resolved_path(&output)) {
+                Some(&j) if j == i => {
+                    bail!("output {} would overwrite its input", output.display())
+                }
+                Some(&j) => bail!(
+                    "output {} would overwrite input {}",
+                    output.display(),
+                    files[j].display()
+                ),
+                None => Ok(output),
+            }
+        })
+        .collect();
+
+    let mut inputs_by_output: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (i, output) in outputs.iter().enumerate() {
+        if let Ok(output) = output {
+            inputs_by_output
+                .entry(resolved_path(output))
+                .or_default()
+                .push(i);
+        }
+    }
+    for inputs in inputs_by_output.values().filter(|inputs| inputs.len() > 1) {
+        for &i in inputs {
+            let others: Vec<String> = inputs
+                .iter()
+                .filter(|&&j| j != i)
+                .map(|&j| files[j].display().to_string())
+                .collect();
+            let output = outputs[i].as_ref().unwrap().display().to_string();
+            outputs[i] = Err(anyhow::anyhow!(
+                "output {output} is also the output of {}",
+                others.join(", ")
+            ));
+        }
+    }
+    outputs
+}
+
+/// Absolute path of `path` with symbolic links resolved, also when the file itself does not
+/// exist yet but its directory does.
+fn resolved_path(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Writes the failed files of a batch as CSV, with a header row even when none failed.
+fn write_failure_report(path: &Path, outcomes: &[EmbedOutcome]) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)
+        .with_context(|| format!("cannot write failure report {}", path.display()))?;
+    writer.write_record(["input", "error"])?;
+    for o in outcomes {
+        if let Some(error) = &o.error {
+            writer.write_record([o.input.display().to_string(), error.clone()])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Configuration for extracting from `input`.
+///
+/// Strengths and scheme recorded in its sidecar, if any, override the profile, and strengths
+/// given on the command line override both. The key of the scheme comes from the command line
+/// or the profile.
+fn extraction_config(
+    input: &Path,
+    seed: Option<u64>,
+    sidecar: Option<&Path>,
+    watermark: &WatermarkArgs,
+    profile: &Profile,
+) -> Result<WatermarkConfig> {
+    let config = config_from_seed(seed.or(profile.seed))?;
+    let config = profile.apply(watermark.with_scheme(config, profile)?);
+    let default_sidecar = sidecar_path(input);
+    let sidecar = sidecar.or_else(|| Some(default_sidecar.as_path()).filter(|p| p.is_file()));
+    let config = match sidecar {
+        Some(sidecar) => read_sidecar(sidecar, config, watermark.scheme_key(profile))?,
+        None => config,
+    };
+    Ok(watermark.apply(config))
+}
+
+/// Loads the selected profile from the configuration file, if there is one.
+fn load_profile(config: Option<&Path>, profile: Option<&str>) -> Result<Profile> {
+    let path = match config {
+        Some(path) => Some(path.to_path_buf()),
+        None => default_config_path().filter(|path| path.is_file()),
+    };
+    match path {
+        Some(path) => ConfigFile::read(path)?.profile(profile),
+        None if profile.is_some() => bail!("--profile requires a configuration file"),
+        None => Ok(Profile::default()),
+    }
+}
+
+fn run_extract(args: ExtractArgs, profile: &Profile, format: OutputFormat) -> Result<ExitCode> {
+    if args.input.is_dir() {
+        return run_extract_batch(args, profile);
+    }
+    let start = Instant::now();
+    let config = extraction_config(
+        &args.input,
+        args.seed,
+        args.sidecar.as_deref(),
+        &args.watermark,
+        profile,
+    )?;
+
+    let soft_bits = extract_soft_bits_with_config(&args.input, args.length, &config)?;
+    let bits = soft_to_hard_bits(&soft_bits);
+    let extracted = args.watermark.encoding(profile).decode(bits.as_raw_slice());
+
+    match format {
+        OutputFormat::Json => {
+            print_json(json!({
+                "input": args.input,
+                "payload": extracted.as_ref().ok(),
+                "bits": to_hex(&bits),
+                "confidence": extraction_confidence(&soft_bits),
+                "elapsed_ms": elapsed_ms(start),
+                "error": extracted.as_ref().err().map(error_message),
+            }));
+            Ok(if extracted.is_ok() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
             })
-            .collect();
-
-        let pb = ProgressBar::new(files.len() as u64);
-        let style = ProgressStyle::with_template("   Embedding [{bar:50}] {pos}/{len} ({eta})")
-            .unwrap()
-            .progress_chars("=> ");
-        pb.set_style(style);
-
-        let prefix = args
-            .prefix
-            .expect("--prefix is required for directory input");
-
-        // Multi-threaded processing
-        files.par_iter().for_each(|input| {
-            let stem = input
-                .file_stem()
-                .expect("illformed input")
-                .to_str()
-                .expect("illformed input");
-            let ext = input
-                .extension()
-                .expect("illformed input")
-                .to_str()
-                .expect("illformed input");
-            let output = input.with_file_name(format!("{}{}.{}", prefix, stem, ext));
+        }
+        OutputFormat::Text => {
+            println!("    {} {}", "Extracted".yellow().bold(), extracted?);
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// Extraction result of a single file in batch mode
+struct ExtractOutcome {
+    path: PathBuf,
+    payload: Option<String>,
+    bits: Option<String>,
+    confidence: Option<f64>,
+    elapsed_ms: f64,
+    error: Option<String>,
+}
+
+fn run_extract_batch(args: ExtractArgs, profile: &Profile) -> Result<ExitCode> {
+    let encoding = args.watermark.encoding(profile);
+    let files = collect_images(&args.input, args.recursive, &args.glob)?;
+    let pb = progress_bar(files.len(), "Extracting");
+
+    let outcomes: Vec<ExtractOutcome> = files
+        .par_iter()
+        .map(|path| {
+            let start = Instant::now();
+            let extracted = extraction_config(
+                path,
+                args.seed,
+                args.sidecar.as_deref(),
+                &args.watermark,
+                profile,
+            )
+            .and_then(|config| extract_soft_bits_with_config(path, args.length, &config));
+            pb.inc(1);
+
+            let mut outcome = ExtractOutcome {
+                path: path.clone(),
+                payload: None,
+                bits: None,
+                confidence: None,
+                elapsed_ms: 0.0,
+                error: None,
+            };
+            match extracted {
+                Ok(soft_bits) => {
+                    let bits = soft_to_hard_bits(&soft_bits);
+                    outcome.bits = Some(to_hex(&bits));
+                    outcome.confidence = Some(extraction_confidence(&soft_bits));
+                    match encoding.decode(bits.as_raw_slice()) {
+                        Ok(payload) => outcome.payload = Some(payload),
+                        Err(e) => outcome.error = Some(error_message(&e)),
+                    }
+                }
+                Err(e) => outcome.error = Some(error_message(&e)),
+            }
+            outcome.elapsed_ms = elapsed_ms(start);
+            outcome
+        })
+        .collect();
+    pb.finish_and_clear();
+
+    match args.format {
+        BatchFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            writer.write_record([
+                "path",
+                "payload",
+                "bits",
+                "confidence",
+                "elapsed_ms",
+                "error",
+            ])?;
+            for o in &outcomes {
+                writer.write_record([
+                    o.path.display().to_string(),
+                    o.payload.clone().unwrap_or_default(),
+                    o.bits.clone().unwrap_or_default(),
+                    o.confidence.map(|c| format!("{c:.4}")).unwrap_or_default(),
+                    format!("{:.1}", o.elapsed_ms),
+                    o.error.clone().unwrap_or_default(),
+                ])?;
+            }
+            writer.flush()?;
+        }
+        BatchFormat::Jsonl => {
+            for o in &outcomes {
+                let json = json!({
+                    "path": o.path,
+                    "payload": o.payload,
+                    "bits": o.bits,
+                    "confidence": o.confidence,
+                    "elapsed_ms": o.elapsed_ms,
+                    "error": o.error,
+                });
+                println!("{json}");
+            }
+        }
+    }
+    Ok(if outcomes.iter().any(|o| o.error.is_some()) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn run_compare(args: CompareArgs, format: OutputFormat) -> Result<ExitCode> {
+    let start = Instant::now();
+    let report = compare_image_files(&args.reference, &args.distorted)?;
+
+    match format {
+        OutputFormat::Json => print_json(json!({
+            "reference": args.reference,
+            "distorted": args.distorted,
+            "psnr": { "rgb": report.psnr_rgb, "y": report.psnr_y },
+            "ssim": { "rgb": report.ssim_rgb, "y": report.ssim_y },
+            "ms_ssim": { "rgb": report.ms_ssim_rgb, "y": report.ms_ssim_y },
+            "max_abs_error": { "rgb": report.max_abs_error_rgb, "y": report.max_abs_error_y },
+            "elapsed_ms": elapsed_ms(start),
+        })),
+        OutputFormat::Text => {
+            println!(
+                "    {:<14} {:>10} {:>10}",
+                "Metric".bold(),
+                "RGB".bold(),
+                "Y".bold()
+            );
+            println!(
+                "    {:<14} {:>10.2} {:>10.2}",
+                "PSNR (dB)", report.psnr_rgb, report.psnr_y
+            );
+            println!(
+                "    {:<14} {:>10.4} {:>10.4}",
+                "SSIM", report.ssim_rgb, report.ssim_y
+            );
+            println!(
+                "    {:<14} {:>10.4} {:>10.4}",
+                "MS-SSIM", report.ms_ssim_rgb, report.ms_ssim_y
+            );
+            println!(
+                "    {:<14} {:>10.1} {:>10.1}",
+                "Max abs error", report.max_abs_error_rgb, report.max_abs_error_y
+            );
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_bench(args: BenchArgs, profile: &Profile, format: OutputFormat) -> Result<ExitCode> {
+    let start = Instant::now();
+    let img = image::open(&args.input)?;
+    let config = args.watermark.config(args.seed, profile)?;
+    let payload = args.watermark.encoding(profile).encode(&args.string)?;
+    let attacks = if args.attacks.is_empty() {
+        Attack::default_suite()
+    } else {
+        args.attacks
+    };
+
+    let watermark = payload.view_bits::<Lsb0>();
+    let outcomes = benchmark(&img, watermark, &config, &attacks)?;
+
+    match format {
+        OutputFormat::Json => {
+            let results: Vec<_> = outcomes
+                .iter()
+                .map(|o| {
+                    json!({
+                        "attack": o.attack.to_string(),
+                        "bits": to_hex(&o.report.bits),
+                        "ber": o.report.bit_error_rate,
+                        "nc": o.report.normalized_correlation,
+                        "wrong_bits": o.report.wrong_bits,
+                    })
+                })
+                .collect();
+            print_json(json!({
+                "input": args.input,
+                "payload": args.string,
+                "bits": to_hex(watermark),
+                "results": results,
+                "elapsed_ms": elapsed_ms(start),
+            }));
+        }
+        OutputFormat::Text => {
+            println!(
+                "    {:<20} {:>8} {:>8}",
+                "Attack".bold(),
+                "BER".bold(),
+                "NC".bold()
+            );
+            for o in &outcomes {
+                let ber = format!("{:>8.4}", o.report.bit_error_rate);
+                let ber = if o.report.is_exact() {
+                    ber.green()
+                } else {
+                    ber.red()
+                };
+                println!(
+                    "    {:<20} {} {:>8.4}",
+                    o.attack.to_string(),
+                    ber,
+                    o.report.normalized_correlation
+                );
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_verify(args: VerifyArgs, profile: &Profile, format: OutputFormat) -> Result<ExitCode> {
+    let start = Instant::now();
+    let config = extraction_config(
+        &args.input,
+        args.seed,
+        args.sidecar.as_deref(),
+        &args.watermark,
+        profile,
+    )?;
+    let encoding = args.watermark.encoding(profile);
+    let expected = encoding.encode(&args.expect)?;
+    let report =
+        verify_watermark_bits_with_config(&args.input, expected.view_bits::<Lsb0>(), &config)?;
+
+    let passed = report.bit_error_rate <= args.max_ber;
+    match format {
+        OutputFormat::Json => print_json(json!({
+            "input": args.input,
+            "expected": args.expect,
+            "payload": encoding.decode(report.bits.as_raw_slice()).ok(),
+            "bits": to_hex(&report.bits),
+            "ber": report.bit_error_rate,
+            "nc": report.normalized_correlation,
+            "wrong_bits": report.wrong_bits,
+            "passed": passed,
+            "elapsed_ms": elapsed_ms(start),
+        })),
+        OutputFormat::Text => {
+            let status = if passed {
+                "Verified".green().bold()
+            } else {
+                "Mismatch".red().bold()
+            };
+            println!(
+                "    {} BER {:.4}, NC {:.4}",
+                status, report.bit_error_rate, report.normalized_correlation
+            );
+            if !report.is_exact() {
+                println!(
+                    "    {} {:?}",
+                    "Wrong bits".yellow().bold(),
+                    report.wrong_bits
+                );
+            }
+        }
+    }
+
+    Ok(if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn run_detect(args: DetectArgs, profile: &Profile, format: OutputFormat) -> Result<ExitCode> {
+    if !(args.false_positive > 0.0 && args.false_positive < 1.0) {
+        bail!("--false-positive must be within (0, 1)");
+    }
+    let start = Instant::now();
+    let config = extraction_config(
+        &args.input,
+        args.seed,
+        args.sidecar.as_deref(),
+        &args.watermark,
+        profile,
+    )?;
+    let expected = args
+        .expect
+        .as_deref()
+        .map(|e| args.watermark.encoding(profile).encode(e))
+        .transpose()?;
+    let expected = expected.as_ref().map(|e| e.view_bits::<Lsb0>());
+    let detection =
+        detect_watermark_with_config(&args.input, expected, &config, args.false_positive)?;
+
+    match format {
+        OutputFormat::Json => print_json(json!({
+            "input": args.input,
+            "expected": args.expect,
+            "present": detection.present,
+            "score": detection.score,
+            "threshold": detection.threshold,
+            "samples": detection.samples,
+            "elapsed_ms": elapsed_ms(start),
+        })),
+        OutputFormat::Text => {
+            let status = if detection.present {
+                "Present".green().bold()
+            } else {
+                "Absent".red().bold()
+            };
+            println!(
+                "    {} score {:.2} (threshold {:.2}, {} samples)",
+                status, detection.score, detection.threshold, detection.samples
+            );
+        }
+    }
+
+    Ok(if detection.present {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn run_audit(args: AuditArgs, profile: &Profile, format: OutputFormat) -> Result<ExitCode> {
+    let start = Instant::now();
+    let manifest = Manifest::read(&args.manifest)?;
+    let seed = args.seed.or(profile.seed);
+    let pb = progress_bar(manifest.entries.len(), "Auditing");
+
+    let outcomes: Vec<_> = manifest
+        .entries
+        .par_iter()
+        .map(|entry| {
+            let outcome = audit_entry(entry, seed, args.scheme_key.or(profile.scheme_key));
+            pb.inc(1);
+            outcome
+        })
+        .collect();
+    pb.finish_and_clear();
+
+    let passed = outcomes
+        .iter()
+        .filter(|o| o.as_ref().is_ok_and(|o| o.passed()))
+        .count();
+    let failed = outcomes.len() - passed;
+
+    match format {
+        OutputFormat::Json => {
+            let entries: Vec<_> = manifest
+                .entries
+                .iter()
+                .zip(&outcomes)
+                .map(|(entry, outcome)| match outcome {
+                    Ok(o) => json!({
+                        "output": entry.output,
+                        "payload": entry.payload,
+                        "hash_matches": o.hash_matches,
+                        "ber": o.report.bit_error_rate,
+                        "nc": o.report.normalized_correlation,
+                        "wrong_bits": o.report.wrong_bits,
+                        "passed": o.passed(),
+                        "error": null,
+                    }),
+                    Err(e) => json!({
+                        "output": entry.output,
+                        "payload": entry.payload,
+                        "passed": false,
+                        "error": error_message(e),
+                    }),
+                })
+                .collect();
+            print_json(json!({
+                "manifest": args.manifest,
+                "crate_version": manifest.crate_version,
+                "entries": entries,
+                "passed": passed,
+                "failed": failed,
+                "elapsed_ms": elapsed_ms(start),
+            }));
+        }
+        OutputFormat::Text => {
+            for (entry, outcome) in manifest.entries.iter().zip(&outcomes) {
+                match outcome {
+                    Ok(o) => {
+                        let status = if o.passed() {
+                            "Passed".green().bold()
+                        } else {
+                            "Mismatch".red().bold()
+                        };
+                        let modified = if o.hash_matches { "" } else { " (modified)" };
+                        println!(
+                            "    {} {} BER {:.4}{}",
+                            status,
+                            entry.output.display(),
+                            o.report.bit_error_rate,
+                            modified.yellow()
+                        );
+                    }
+                    Err(e) => println!(
+                        "    {} {}: {}",
+                        "Failed".red().bold(),
+                        entry.output.display(),
+                        error_message(e)
+                    ),
+                }
+            }
+            let summary = format!("{passed} passed, {failed} failed");
+            if failed == 0 {
+                println!("    {}", summary.green());
+            } else {
+                println!("    {}", summary.red());
+            }
+        }
+    }
+
+    Ok(if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Fingerprinting result of a single recipient
+struct FingerprintOutcome {
+    row: usize,
+    payload: Option<String>,
+    output: Option<PathBuf>,
+    elapsed_ms: f64,
+    error: Option<String>,
+}
+
+fn run_fingerprint(
+    args: FingerprintArgs,
+    profile: &Profile,
+    format: OutputFormat,
+) -> Result<ExitCode> {
+    let batch_start = Instant::now();
+    let config = args.watermark.config(args.seed, profile)?;
+    let encoding = args.watermark.encoding(profile);
+    let out_dir = args
+        .out_dir
+        .as_deref()
+        .or(profile.out_dir.as_deref())
+        .context("--out-dir is required")?;
+    let recipients = read_recipients(&args.recipients)?;
+    if let Some(first) = recipients.first() {
+        let known =
+            |name: &str| first.contains_key(name) || ["row", "date", "stem"].contains(&name);
+        if let Some(name) = args
+            .template
+            .fields()
+            .chain(args.name.fields())
+            .find(|n| !known(n))
+        {
+            bail!("no column {name} in {}", args.recipients.display());
+        }
+    }
+
+    let stem = args
+        .input
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .context("illformed input file name")?;
+    let ext = args
+        .input
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .context("illformed input file name")?;
+    let date = today();
+
+    // Decomposed once, shared by every copy
+    let fingerprinter = Fingerprinter::new(&image::open(&args.input)?, &config);
+    std::fs::create_dir_all(out_dir)?;
+    let pb = progress_bar(recipients.len(), "Marking");
+
+    let render_copy = |row: usize, payload: &str, output: &Path| -> Result<()> {
+        if output.exists() && !args.overwrite {
+            bail!("{} already exists (use --overwrite)", output.display());
+        }
+        let bytes = encoding.encode(payload)?;
+        fingerprinter
+            .render(bytes.view_bits::<Lsb0>())?
+            .save(output)?;
+        if format == OutputFormat::Text {
             pb.println(format!(
-                "   {} {}",
-                "Embedding".green().bold(),
-                input.display()
+                "   {} {} → {}",
+                "Marked".green().bold(),
+                row,
+                output.display()
             ));
-            // Run embed
-            embed_watermark_string(input, &output, &args.string, args.seed).unwrap();
+        }
+        Ok(())
+    };
+
+    // Payload and output of every copy, rendered before writing any so that two recipients
+    // cannot be written to the same file
+    let planned: Vec<(Option<String>, Result<PathBuf>)> = recipients
+        .iter()
+        .enumerate()
+        .map(|(i, recipient)| {
+            let row = (i + 1).to_string();
+            let lookup = |name: &str| {
+                recipient.get(name).map(String::as_str).or(match name {
+                    "row" => Some(row.as_str()),
+                    "date" => Some(date.as_str()),
+                    "stem" => Some(stem),
+                    _ => None,
+                })
+            };
+            let payload = args.template.render(lookup);
+            let output = args.name.render(lookup).and_then(|name| {
+                if name.is_empty() || name.contains(['/', '\\']) {
+                    bail!("invalid file name rendered from --name: {name:?}");
+                }
+                Ok(out_dir.join(format!("{name}.{ext}")))
+            });
+            match payload {
+                Ok(payload) => (Some(payload), output),
+                Err(e) => (None, Err(e)),
+            }
+        })
+        .collect();
+    // Copies must be told apart by their file and by their watermark
+    let mut rows_by_output: HashMap<&Path, usize> = HashMap::new();
+    let mut rows_by_payload: HashMap<&str, usize> = HashMap::new();
+    for (i, (payload, output)) in planned.iter().enumerate() {
+        if let Ok(output) = output
+            && let Some(first) = rows_by_output.insert(output, i + 1)
+        {
+            bail!(
+                "recipients of rows {first} and {} are both named {} (make --name unique)",
+                i + 1,
+                output.display()
+            );
+        }
+        if let Some(payload) = payload
+            && let Some(first) = rows_by_payload.insert(payload, i + 1)
+        {
+            bail!(
+                "recipients of rows {first} and {} both get the payload {payload:?} (make --template unique)",
+                i + 1
+            );
+        }
+    }
+
+    let outcomes: Vec<FingerprintOutcome> = planned
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, (payload, output))| {
+            let start = Instant::now();
+            let mut outcome = FingerprintOutcome {
+                row: i + 1,
+                payload: payload.clone(),
+                output: output.as_ref().ok().cloned(),
+                elapsed_ms: 0.0,
+                error: None,
+            };
+            let result = output.and_then(|output| {
+                render_copy(i + 1, payload.as_deref().unwrap_or_default(), &output)
+            });
+            if let Err(e) = result {
+                outcome.error = Some(error_message(&e));
+            }
 
             pb.inc(1);
-        });
-        pb.finish_and_clear();
-        println!("{}", "    Done embedding.".yellow().bold());
+            outcome.elapsed_ms = elapsed_ms(start);
+            outcome
+        })
+        .collect();
+    pb.finish_and_clear();
+
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+    let succeeded = outcomes.len() - failed;
+
+    match format {
+        OutputFormat::Json => {
+            let copies: Vec<_> = outcomes
+                .iter()
+                .map(|o| {
+                    let bits = o
+                        .payload
+                        .as_deref()
+                        .and_then(|p| encoding.encode(p).ok())
+                        .map(|bytes| to_hex(bytes.view_bits::<Lsb0>()));
+                    json!({
+                        "row": o.row,
+                        "payload": o.payload,
+                        "bits": bits,
+                        "output": o.output,
+                        "elapsed_ms": o.elapsed_ms,
+                        "error": o.error,
+                    })
+                })
+                .collect();
+            print_json(json!({
+                "input": args.input,
+                "template": args.template.to_string(),
+                "copies": copies,
+                "succeeded": succeeded,
+                "failed": failed,
+                "elapsed_ms": elapsed_ms(batch_start),
+            }));
+        }
+        OutputFormat::Text => {
+            println!("{}", "    Done fingerprinting.".yellow().bold());
+            for o in &outcomes {
+                if let Some(error) = &o.error {
+                    println!("   {} row {}: {}", "Failed".red().bold(), o.row, error);
+                }
+            }
+            let summary = format!("{succeeded} succeeded, {failed} failed");
+            if failed == 0 {
+                println!("    {}", summary.green());
+            } else {
+                println!("    {}", summary.red());
+            }
+        }
+    }
+
+    Ok(if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn run_tardos_generate(args: TardosGenerateArgs, format: OutputFormat) -> Result<ExitCode> {
+    let start = Instant::now();
+    if !(args.false_positive > 0.0 && args.false_positive < 1.0) {
+        bail!("--false-positive must be within (0, 1)");
+    }
+    let mut reader = csv::Reader::from_path(&args.recipients)
+        .with_context(|| format!("failed to read recipients {}", args.recipients.display()))?;
+    let mut headers = reader.headers()?.clone();
+    if headers.iter().any(|h| h.trim() == "tardos") {
+        bail!("{} already has a tardos column", args.recipients.display());
     }
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+    if records.is_empty() {
+        bail!("no recipients in {}", args.recipients.display());
+    }
+
+    let seed = args.code_seed.unwrap_or_else(rand::random);
+    let code = TardosCode::generate(
+        records.len(),
+        args.colluders.into(),
+        args.false_positive,
+        seed,
+    );
+    code.write(&args.code)?;
+
+    let mut writer = csv::Writer::from_path(&args.output)?;
+    headers.push_field("tardos");
+    writer.write_record(&headers)?;
+    for (mut record, codeword) in records.into_iter().zip(&code.codewords) {
+        record.push_field(&codeword_hex(codeword));
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+
+    match format {
+        OutputFormat::Json => print_json(json!({
+            "recipients": code.codewords.len(),
+            "colluders": code.colluders,
+            "false_positive": code.false_positive,
+            "length": code.len(),
+            "code": args.code,
+            "output": args.output,
+            "elapsed_ms": elapsed_ms(start),
+        })),
+        OutputFormat::Text => println!(
+            "    {} {} code words of {} bits → {} (keep {} secret)",
+            "Generated".green().bold(),
+            code.codewords.len(),
+            code.len(),
+            args.output.display(),
+            args.code.display()
+        ),
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
-fn run_extract(args: ExtractArgs) {
-    let extracted = extract_watermark_string(args.input, args.length, args.seed)
-        .expect("Failed to extract watermark");
+fn run_tardos_accuse(
+    args: TardosAccuseArgs,
+    profile: &Profile,
+    format: OutputFormat,
+) -> Result<ExitCode> {
+    let start = Instant::now();
+    let code = TardosCode::read(&args.code)?;
+    let config = extraction_config(
+        &args.input,
+        args.seed,
+        args.sidecar.as_deref(),
+        &args.watermark,
+        profile,
+    )?;
+    let soft_bits = extract_soft_bits_with_config(&args.input, code.len(), &config)?;
+    let accusations = code.accuse(&soft_bits);
+    let accused = accusations.iter().filter(|a| a.accused).count();
+    let threshold = code.threshold();
 
-    println!("    {} {}", "Extracted".yellow().bold(), extracted);
+    match format {
+        OutputFormat::Json => {
+            let suspects: Vec<_> = accusations
+                .iter()
+                .take(args.top)
+                .map(|a| {
+                    json!({
+                        "row": a.recipient + 1,
+                        "score": a.score,
+                        "accused": a.accused,
+                    })
+                })
+                .collect();
+            print_json(json!({
+                "input": args.input,
+                "threshold": threshold,
+                "false_positive": code.false_positive,
+                "suspects": suspects,
+                "accused": accused,
+                "elapsed_ms": elapsed_ms(start),
+            }));
+        }
+        OutputFormat::Text => {
+            for a in accusations.iter().take(args.top) {
+                let status = if a.accused {
+                    "Accused".red().bold()
+                } else {
+                    "Cleared".green().bold()
+                };
+                println!(
+                    "    {} row {} score {:.2}",
+                    status,
+                    a.recipient + 1,
+                    a.score
+                );
+            }
+            println!(
+                "    {accused} accused (threshold {threshold:.2}, false-accusation probability {})",
+                code.false_positive
+            );
+        }
+    }
+
+    Ok(if accused > 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
 }
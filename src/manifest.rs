@@ -0,0 +1,334 @@
+use anyhow::{Context, Result, bail};
+use bitvec::prelude::*;
+use image::ImageReader;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::{EmbeddingScheme, WatermarkConfig, WatermarkMode},
+    metrics::psnr_rgb,
+    profile::PayloadEncoding,
+    report::ExtractionReport,
+    utils::{config_from_seed, verify_watermark_bits_with_config},
+};
+
+/// Version of the manifest layout written by [`Manifest::write`].
+pub const MANIFEST_VERSION: u64 = 1;
+
+/// Record of the watermarks embedded during one run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// Version of the crate that embedded the watermarks
+    pub crate_version: String,
+    /// One entry per watermarked file
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Record of a single watermarked file.
+///
+/// The seed of the random strategy and the key of the embedding scheme are secrets and are
+/// not recorded; auditing needs them again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    /// Absolute path of the original image
+    pub input: PathBuf,
+    /// SHA-256 of the original image file, as hex
+    pub input_sha256: String,
+    /// Absolute path of the watermarked image
+    pub output: PathBuf,
+    /// SHA-256 of the watermarked image file, as hex
+    pub output_sha256: String,
+    /// The watermark as given by the user
+    pub payload: String,
+    /// The embedded bits
+    pub bits: BitVec<u8>,
+    /// Quantization strength of the first singular value
+    pub strength_1: i32,
+    /// Quantization strength of the second singular value
+    pub strength_2: Option<i32>,
+    /// Whether the random strategy was used
+    pub strategy: bool,
+    /// Embedding scheme, see [`EmbeddingScheme::kind`]
+    pub scheme: String,
+    /// RGB PSNR of the watermarked image against the original, in dB
+    pub psnr: f64,
+}
+
+impl ManifestEntry {
+    /// Records an embedding that has been written to `output`.
+    ///
+    /// Both files are hashed, and the output is decoded again to measure its PSNR.
+    pub fn record<P: AsRef<Path>>(
+        input: P,
+        output: P,
+        payload: &str,
+        bits: &BitSlice<u8>,
+        config: &WatermarkConfig,
+    ) -> Result<Self> {
+        let (input, output) = (
+            input.as_ref().canonicalize()?,
+            output.as_ref().canonicalize()?,
+        );
+        let original = ImageReader::open(&input)?.decode()?.to_rgb32f();
+        let watermarked = ImageReader::open(&output)?.decode()?.to_rgb32f();
+        if original.dimensions() != watermarked.dimensions() {
+            bail!("output dimensions differ from the input");
+        }
+
+        Ok(ManifestEntry {
+            input_sha256: sha256_file(&input)?,
+            output_sha256: sha256_file(&output)?,
+            input,
+            output,
+            payload: payload.to_owned(),
+            bits: bits.to_bitvec(),
+            strength_1: config.strength_1,
+            strength_2: config.strength_2,
+            strategy: matches!(config.mode, WatermarkMode::Strategy(_)),
+            scheme: config.scheme.kind().to_owned(),
+            psnr: psnr_rgb(&original, &watermarked),
+        })
+    }
+
+    /// Configuration the entry was embedded with, given the seed of the random strategy and
+    /// the key of the embedding scheme, when they are used.
+    pub fn config(&self, seed: Option<u64>, key: Option<u64>) -> Result<WatermarkConfig> {
+        let base = match (self.strategy, seed) {
+            (true, None) => bail!("a seed is required, the watermark uses the random strategy"),
+            (true, seed) => config_from_seed(seed)?,
+            (false, _) => WatermarkConfig::default(),
+        };
+        Ok(WatermarkConfig {
+            strength_1: self.strength_1,
+            strength_2: self.strength_2,
+            scheme: EmbeddingScheme::from_kind(&self.scheme, key)?,
+            ..base
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "input": self.input,
+            "input_sha256": self.input_sha256,
+            "output": self.output,
+            "output_sha256": self.output_sha256,
+            "payload": self.payload,
+            "bits": PayloadEncoding::Hex.decode(self.bits.as_raw_slice()).unwrap_or_default(),
+            "config": {
+                "strength_1": self.strength_1,
+                "strength_2": self.strength_2,
+                "mode": if self.strategy { "strategy" } else { "normal" },
+                "scheme": self.scheme,
+            },
+            "psnr": self.psnr,
+        })
+    }
+
+    fn from_json(value: &Value) -> Result<Self> {
+        let string = |key: &str| {
+            value[key]
+                .as_str()
+                .map(str::to_owned)
+                .with_context(|| format!("missing {key}"))
+        };
+        let config = &value["config"];
+        let strength = |key: &str| -> Result<Option<i32>> {
+            match &config[key] {
+                Value::Null => Ok(None),
+                v => Ok(Some(
+                    v.as_i64()
+                        .and_then(|v| i32::try_from(v).ok())
+                        .with_context(|| format!("invalid {key}"))?,
+                )),
+            }
+        };
+
+        Ok(ManifestEntry {
+            input: string("input")?.into(),
+            input_sha256: string("input_sha256")?,
+            output: string("output")?.into(),
+            output_sha256: string("output_sha256")?,
+            payload: string("payload")?,
+            bits: BitVec::from_vec(PayloadEncoding::Hex.encode(&string("bits")?)?),
+            strength_1: strength("strength_1")?.context("missing strength_1")?,
+            strength_2: strength("strength_2")?,
+            strategy: match config["mode"].as_str() {
+                Some("strategy") => true,
+                Some("normal") => false,
+                _ => bail!("invalid mode"),
+            },
+            // Entries written before schemes were recorded all used the default one
+            scheme: match config["scheme"].as_str() {
+                None => EmbeddingScheme::default().kind().to_owned(),
+                Some(kind) => {
+                    // Checks the name only, the key is not recorded
+                    EmbeddingScheme::from_kind(kind, Some(0))?;
+                    kind.to_owned()
+                }
+            },
+            // Identical images have an infinite PSNR, which JSON stores as null
+            psnr: value["psnr"].as_f64().unwrap_or(f64::INFINITY),
+        })
+    }
+}
+
+impl Manifest {
+    /// Creates a manifest for the current crate version.
+    pub fn new(entries: Vec<ManifestEntry>) -> Self {
+        Manifest {
+            crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+            entries,
+        }
+    }
+
+    /// Writes the manifest as pretty-printed JSON.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let entries: Vec<_> = self.entries.iter().map(ManifestEntry::to_json).collect();
+        let json = json!({
+            "manifest_version": MANIFEST_VERSION,
+            "crate_version": self.crate_version,
+            "entries": entries,
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&json)?)?;
+        Ok(())
+    }
+
+    /// Reads a manifest written by [`Manifest::write`].
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read manifest {}", path.display()))?;
+        let json: Value = serde_json::from_str(&content)
+            .with_context(|| format!("invalid manifest {}", path.display()))?;
+
+        match json["manifest_version"].as_u64() {
+            Some(MANIFEST_VERSION) => {}
+            Some(version) => bail!("unsupported manifest version {version}"),
+            None => bail!("missing manifest_version in {}", path.display()),
+        }
+        let entries = json["entries"]
+            .as_array()
+            .context("missing entries")?
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                ManifestEntry::from_json(entry).with_context(|| format!("invalid entry {i}"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Manifest {
+            crate_version: json["crate_version"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned(),
+            entries,
+        })
+    }
+}
+
+/// Result of checking a watermarked file against its manifest entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditOutcome {
+    /// Whether the file is byte-identical to the recorded output
+    pub hash_matches: bool,
+    /// Extracted bits compared against the recorded ones
+    pub report: ExtractionReport,
+}
+
+impl AuditOutcome {
+    /// Whether the recorded watermark was extracted without error.
+    pub fn passed(&self) -> bool {
+        self.report.is_exact()
+    }
+}
+
+/// Re-extracts the watermark of a manifest entry and compares it against the record.
+///
+/// # Arguments
+///
+/// * `entry` - The manifest entry to check.
+/// * `seed` - Seed used for the random strategy during embedding.
+/// * `key` - Key of the embedding scheme, for the schemes using one.
+pub fn audit_entry(
+    entry: &ManifestEntry,
+    seed: Option<u64>,
+    key: Option<u64>,
+) -> Result<AuditOutcome> {
+    let config = entry.config(seed, key)?;
+    let hash_matches = sha256_file(&entry.output)? == entry.output_sha256;
+    let report = verify_watermark_bits_with_config(&entry.output, &entry.bits, &config)?;
+    Ok(AuditOutcome {
+        hash_matches,
+        report,
+    })
+}
+
+/// SHA-256 of a file's contents, as lowercase hex.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    PayloadEncoding::Hex.decode(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> ManifestEntry {
+        ManifestEntry {
+            input: "/data/in.png".into(),
+            input_sha256: "00".repeat(32),
+            output: "/data/out.png".into(),
+            output_sha256: "ff".repeat(32),
+            payload: "hi".into(),
+            bits: BitVec::from_vec(b"hi".to_vec()),
+            strength_1: 36,
+            strength_2: Some(20),
+            strategy: true,
+            scheme: "svd_quantization".into(),
+            psnr: 41.5,
+        }
+    }
+
+    #[test]
+    fn test_entry_json_round_trip() {
+        let entry = entry();
+        let json = entry.to_json();
+        assert_eq!(json["bits"], "6869");
+        assert_eq!(json["config"]["mode"], "strategy");
+        assert!(json.get("seed").is_none() && json["config"].get("seed").is_none());
+        assert_eq!(ManifestEntry::from_json(&json).unwrap(), entry);
+
+        // Written before schemes were recorded
+        let mut legacy = json.clone();
+        legacy["config"].as_object_mut().unwrap().remove("scheme");
+        assert_eq!(ManifestEntry::from_json(&legacy).unwrap(), entry);
+        let mut unknown = json;
+        unknown["config"]["scheme"] = "dct_lsb".into();
+        assert!(ManifestEntry::from_json(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_entry_config_needs_seed() {
+        let entry = entry();
+        assert!(entry.config(None, None).is_err());
+        let config = entry.config(Some(3), None).unwrap();
+        assert!(matches!(config.mode, WatermarkMode::Strategy(3)));
+        assert_eq!(config.strength_2, Some(20));
+        assert_eq!(config.scheme, EmbeddingScheme::SvdQuantization);
+    }
+
+    #[test]
+    fn test_entry_config_needs_key() {
+        let entry = ManifestEntry {
+            scheme: "rational_dither".into(),
+            ..entry()
+        };
+        assert!(entry.config(Some(3), None).is_err());
+        let config = entry.config(Some(3), Some(9)).unwrap();
+        assert_eq!(config.scheme, EmbeddingScheme::RationalDither { key: 9 });
+    }
+}
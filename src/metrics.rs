@@ -0,0 +1,449 @@
+use anyhow::{Result, bail};
+use bitvec::prelude::*;
+use faer::prelude::*;
+use image::{DynamicImage, GenericImageView, ImageReader, Rgb32FImage};
+use std::path::Path;
+
+/// Side length of the Gaussian window used by SSIM.
+const SSIM_WINDOW: usize = 11;
+/// Standard deviation of the Gaussian window used by SSIM.
+const SSIM_SIGMA: f64 = 1.5;
+/// Per-scale exponents of MS-SSIM, from finest to coarsest scale.
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// Quality of a distorted image measured against its reference.
+///
+/// Every metric is given on the RGB channels (averaged, or pooled for PSNR) and on the
+/// luminance (Y) channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityReport {
+    /// PSNR over R, G and B, in dB
+    pub psnr_rgb: f64,
+    /// PSNR over Y, in dB
+    pub psnr_y: f64,
+    /// Mean SSIM of R, G and B
+    pub ssim_rgb: f64,
+    /// SSIM over Y
+    pub ssim_y: f64,
+    /// Mean MS-SSIM of R, G and B
+    pub ms_ssim_rgb: f64,
+    /// MS-SSIM over Y
+    pub ms_ssim_y: f64,
+    /// Largest absolute difference over R, G and B, in 8-bit levels
+    pub max_abs_error_rgb: f64,
+    /// Largest absolute difference over Y, in 8-bit levels
+    pub max_abs_error_y: f64,
+}
+
+/// Compares two decoded images.
+///
+/// Both images are converted to RGB, alpha is ignored. Fails when dimensions differ.
+pub fn compare_images(reference: &DynamicImage, distorted: &DynamicImage) -> Result<QualityReport> {
+    if reference.dimensions() != distorted.dimensions() {
+        bail!(
+            "dimension mismatch: {:?} vs {:?}",
+            reference.dimensions(),
+            distorted.dimensions()
+        );
+    }
+    let (a, a_y) = image_planes(&reference.to_rgb32f());
+    let (b, b_y) = image_planes(&distorted.to_rgb32f());
+
+    let mean_over_channels =
+        |metric: fn(&Plane, &Plane) -> f64| (0..3).map(|c| metric(&a[c], &b[c])).sum::<f64>() / 3.0;
+
+    Ok(QualityReport {
+        psnr_rgb: mse_to_psnr(mean_over_channels(plane_mse)),
+        psnr_y: mse_to_psnr(plane_mse(&a_y, &b_y)),
+        ssim_rgb: mean_over_channels(|x, y| ssim_components(x, y).0),
+        ssim_y: ssim_components(&a_y, &b_y).0,
+        ms_ssim_rgb: mean_over_channels(plane_ms_ssim),
+        ms_ssim_y: plane_ms_ssim(&a_y, &b_y),
+        max_abs_error_rgb: (0..3)
+            .map(|c| plane_max_abs_error(&a[c], &b[c]))
+            .fold(0.0, f64::max),
+        max_abs_error_y: plane_max_abs_error(&a_y, &b_y),
+    })
+}
+
+/// Compares two image files, see [`compare_images`].
+pub fn compare_image_files<P: AsRef<Path>>(reference: P, distorted: P) -> Result<QualityReport> {
+    let reference = ImageReader::open(reference)?.decode()?;
+    let distorted = ImageReader::open(distorted)?.decode()?;
+    compare_images(&reference, &distorted)
+}
+
+/// Peak Signal-to-Noise Ratio between two planes with values in `[0, 1]`, in dB.
+///
+/// Returns `f64::INFINITY` for identical planes.
+pub fn psnr(reference: MatRef<f32>, distorted: MatRef<f32>) -> f64 {
+    assert_eq!(reference.shape(), distorted.shape(), "shape mismatch");
+    mse_to_psnr(plane_mse(&to_f64(reference), &to_f64(distorted)))
+}
+
+/// Structural Similarity Index between two planes with values in `[0, 1]`.
+///
+/// Uses the usual 11×11 Gaussian window with σ = 1.5. Planes smaller than the window
+/// are compared with a single window covering the whole plane.
+pub fn ssim(reference: MatRef<f32>, distorted: MatRef<f32>) -> f64 {
+    assert_eq!(reference.shape(), distorted.shape(), "shape mismatch");
+    let x = to_f64(reference);
+    let y = to_f64(distorted);
+    ssim_components(&x, &y).0
+}
+
+/// Multi-Scale Structural Similarity Index between two planes with values in `[0, 1]`.
+///
+/// Uses up to five dyadic scales with the standard exponents. Scales whose plane would be
+/// smaller than the SSIM window are dropped and the remaining exponents renormalized.
+pub fn ms_ssim(reference: MatRef<f32>, distorted: MatRef<f32>) -> f64 {
+    assert_eq!(reference.shape(), distorted.shape(), "shape mismatch");
+    plane_ms_ssim(&to_f64(reference), &to_f64(distorted))
+}
+
+/// Largest absolute difference between two planes with values in `[0, 1]`, in 8-bit levels.
+pub fn max_abs_error(reference: MatRef<f32>, distorted: MatRef<f32>) -> f64 {
+    assert_eq!(reference.shape(), distorted.shape(), "shape mismatch");
+    plane_max_abs_error(&to_f64(reference), &to_f64(distorted))
+}
+
+/// Fraction of bits that differ between the embedded and the extracted watermark.
+pub fn bit_error_rate(expected: &BitSlice<u8>, extracted: &BitSlice<u8>) -> f64 {
+    assert_eq!(expected.len(), extracted.len(), "length mismatch");
+    let errors = expected
+        .iter()
+        .zip(extracted.iter())
+        .filter(|(a, b)| **a != **b)
+        .count();
+    errors as f64 / expected.len().max(1) as f64
+}
+
+/// Normalized correlation between the embedded and the extracted watermark.
+///
+/// Bits are mapped to ±1 before correlating, so the result lies in `[-1, 1]`: 1 for a
+/// perfect extraction, around 0 for an unrelated bit string.
+pub fn normalized_correlation(expected: &BitSlice<u8>, extracted: &BitSlice<u8>) -> f64 {
+    1.0 - 2.0 * bit_error_rate(expected, extracted)
+}
+
+/// PSNR over the R, G and B channels of two images, in dB.
+///
+/// The mean squared error is pooled over all three channels before converting to dB.
+pub fn psnr_rgb(reference: &Rgb32FImage, distorted: &Rgb32FImage) -> f64 {
+    assert_eq!(
+        reference.dimensions(),
+        distorted.dimensions(),
+        "shape mismatch"
+    );
+    let (a, _) = image_planes(reference);
+    let (b, _) = image_planes(distorted);
+    let mse = (0..3).map(|c| plane_mse(&a[c], &b[c])).sum::<f64>() / 3.0;
+    mse_to_psnr(mse)
+}
+
+/// SSIM computed on the luminance (Y) channel of two images.
+pub fn ssim_y(reference: &Rgb32FImage, distorted: &Rgb32FImage) -> f64 {
+    assert_eq!(
+        reference.dimensions(),
+        distorted.dimensions(),
+        "shape mismatch"
+    );
+    let (_, a_y) = image_planes(reference);
+    let (_, b_y) = image_planes(distorted);
+    ssim_components(&a_y, &b_y).0
+}
+
+/// Splits an RGB image into its R, G and B planes.
+pub fn rgb_planes(img: &Rgb32FImage) -> [Mat<f32>; 3] {
+    let (width, height) = img.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    std::array::from_fn(|c| {
+        Mat::from_fn(height, width, |i, j| img.get_pixel(j as u32, i as u32)[c])
+    })
+}
+
+/// Luminance plane of an RGB image, using the same BT.709 weights as the embedding pipeline.
+pub fn luma_plane(img: &Rgb32FImage) -> Mat<f32> {
+    let (width, height) = img.dimensions();
+    Mat::from_fn(height as usize, width as usize, |i, j| {
+        let p = img.get_pixel(j as u32, i as u32);
+        0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2]
+    })
+}
+
+fn mse_to_psnr(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (1.0 / mse).log10()
+    }
+}
+
+/// Row-major `f64` copy of a plane, used for the windowed SSIM computations.
+#[derive(Clone)]
+struct Plane {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+impl Plane {
+    fn from_fn(rows: usize, cols: usize, f: impl Fn(usize, usize) -> f64) -> Self {
+        let data = (0..rows)
+            .flat_map(|i| (0..cols).map(move |j| (i, j)))
+            .map(|(i, j)| f(i, j))
+            .collect();
+        Plane { rows, cols, data }
+    }
+
+    fn zip_with(&self, other: &Plane, f: impl Fn(f64, f64) -> f64) -> Plane {
+        Plane {
+            rows: self.rows,
+            cols: self.cols,
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(&a, &b)| f(a, b))
+                .collect(),
+        }
+    }
+
+    fn at(&self, i: usize, j: usize) -> f64 {
+        self.data[i * self.cols + j]
+    }
+}
+
+fn to_f64(mat: MatRef<f32>) -> Plane {
+    Plane::from_fn(mat.nrows(), mat.ncols(), |i, j| mat[(i, j)] as f64)
+}
+
+/// R, G, B and luminance planes of an image, read straight from its buffer.
+fn image_planes(img: &Rgb32FImage) -> ([Plane; 3], Plane) {
+    let (width, height) = img.dimensions();
+    let (rows, cols) = (height as usize, width as usize);
+    let pixels: Vec<&[f32]> = img.as_raw().chunks_exact(3).collect();
+    let channel = |c: usize| Plane {
+        rows,
+        cols,
+        data: pixels.iter().map(|p| p[c] as f64).collect(),
+    };
+    let luma = Plane {
+        rows,
+        cols,
+        data: pixels
+            .iter()
+            .map(|p| (0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2]) as f64)
+            .collect(),
+    };
+    ([channel(0), channel(1), channel(2)], luma)
+}
+
+fn plane_mse(x: &Plane, y: &Plane) -> f64 {
+    let sum: f64 = x
+        .data
+        .iter()
+        .zip(&y.data)
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum();
+    sum / x.data.len().max(1) as f64
+}
+
+fn plane_max_abs_error(x: &Plane, y: &Plane) -> f64 {
+    let max = x
+        .data
+        .iter()
+        .zip(&y.data)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f64::max);
+    max * 255.0
+}
+
+fn plane_ms_ssim(x: &Plane, y: &Plane) -> f64 {
+    let mut scales = 1;
+    let (mut rows, mut cols) = (x.rows, x.cols);
+    while scales < MS_SSIM_WEIGHTS.len() && rows / 2 >= SSIM_WINDOW && cols / 2 >= SSIM_WINDOW {
+        scales += 1;
+        rows /= 2;
+        cols /= 2;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let total: f64 = weights.iter().sum();
+
+    let (mut x, mut y) = (x.clone(), y.clone());
+    let mut result = 1.0;
+    for (scale, weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_components(&x, &y);
+        // The luminance term only enters at the coarsest scale
+        let term = if scale + 1 == scales { ssim } else { cs };
+        result *= term.max(0.0).powf(weight / total);
+        x = downsample(&x);
+        y = downsample(&y);
+    }
+    result
+}
+
+/// Means of the SSIM map and of the contrast-structure map of two planes.
+fn ssim_components(x: &Plane, y: &Plane) -> (f64, f64) {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let mu_x = gaussian_filter_valid(x);
+    let mu_y = gaussian_filter_valid(y);
+    let e_xx = gaussian_filter_valid(&x.zip_with(x, |a, b| a * b));
+    let e_yy = gaussian_filter_valid(&y.zip_with(y, |a, b| a * b));
+    let e_xy = gaussian_filter_valid(&x.zip_with(y, |a, b| a * b));
+
+    let mut ssim_sum = 0.0;
+    let mut cs_sum = 0.0;
+    for k in 0..mu_x.data.len() {
+        let (mx, my) = (mu_x.data[k], mu_y.data[k]);
+        let var_x = e_xx.data[k] - mx * mx;
+        let var_y = e_yy.data[k] - my * my;
+        let cov = e_xy.data[k] - mx * my;
+        let cs = (2.0 * cov + C2) / (var_x + var_y + C2);
+        ssim_sum += (2.0 * mx * my + C1) / (mx * mx + my * my + C1) * cs;
+        cs_sum += cs;
+    }
+    let count = mu_x.data.len().max(1) as f64;
+    (ssim_sum / count, cs_sum / count)
+}
+
+/// Halves both dimensions by averaging 2×2 neighbourhoods.
+fn downsample(plane: &Plane) -> Plane {
+    Plane::from_fn(plane.rows / 2, plane.cols / 2, |i, j| {
+        (plane.at(2 * i, 2 * j)
+            + plane.at(2 * i, 2 * j + 1)
+            + plane.at(2 * i + 1, 2 * j)
+            + plane.at(2 * i + 1, 2 * j + 1))
+            / 4.0
+    })
+}
+
+/// Normalized 1D Gaussian kernel of the given length.
+fn gaussian_kernel(len: usize) -> Vec<f64> {
+    let center = (len as f64 - 1.0) / 2.0;
+    let kernel: Vec<f64> = (0..len)
+        .map(|i| {
+            let d = i as f64 - center;
+            (-d * d / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp()
+        })
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    kernel.into_iter().map(|k| k / total).collect()
+}
+
+/// Separable Gaussian filtering keeping only fully covered positions.
+///
+/// The window shrinks to the plane size when the plane is smaller than [`SSIM_WINDOW`].
+fn gaussian_filter_valid(plane: &Plane) -> Plane {
+    let (rows, cols) = (plane.rows, plane.cols);
+    let win_r = SSIM_WINDOW.min(rows);
+    let win_c = SSIM_WINDOW.min(cols);
+    let kernel_r = gaussian_kernel(win_r);
+    let kernel_c = gaussian_kernel(win_c);
+
+    let out_rows = rows - win_r + 1;
+    let out_cols = cols - win_c + 1;
+
+    // Filter along rows first, then along columns
+    let mut horizontal = vec![0.0; rows * out_cols];
+    for i in 0..rows {
+        let row = &plane.data[i * cols..(i + 1) * cols];
+        let out = &mut horizontal[i * out_cols..(i + 1) * out_cols];
+        for (j, value) in out.iter_mut().enumerate() {
+            *value = kernel_c.iter().zip(&row[j..]).map(|(w, x)| w * x).sum();
+        }
+    }
+    let mut data = vec![0.0; out_rows * out_cols];
+    for (k, w) in kernel_r.iter().enumerate() {
+        for i in 0..out_rows {
+            let src = &horizontal[(i + k) * out_cols..(i + k + 1) * out_cols];
+            let out = &mut data[i * out_cols..(i + 1) * out_cols];
+            for (o, x) in out.iter_mut().zip(src) {
+                *o += w * x;
+            }
+        }
+    }
+    Plane {
+        rows: out_rows,
+        cols: out_cols,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn gradient(rows: usize, cols: usize) -> Mat<f32> {
+        Mat::from_fn(rows, cols, |i, j| ((i * 7 + j * 3) % 32) as f32 / 32.0)
+    }
+
+    #[test]
+    fn test_psnr_identical_is_infinite() {
+        let a = gradient(16, 16);
+        assert!(psnr(a.as_ref(), a.as_ref()).is_infinite());
+    }
+
+    #[test]
+    fn test_psnr_constant_offset() {
+        let a = Mat::<f32>::full(8, 8, 0.5);
+        let b = Mat::<f32>::full(8, 8, 0.6);
+        // MSE = 0.01 => PSNR = 20 dB
+        assert_relative_eq!(psnr(a.as_ref(), b.as_ref()), 20.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_ssim_identical_is_one() {
+        let a = gradient(24, 20);
+        assert_relative_eq!(ssim(a.as_ref(), a.as_ref()), 1.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_ssim_decreases_with_noise() {
+        let a = gradient(24, 24);
+        let slightly = Mat::from_fn(24, 24, |i, j| {
+            a[(i, j)] + if (i + j) % 2 == 0 { 0.01 } else { -0.01 }
+        });
+        let heavily = Mat::from_fn(24, 24, |i, j| {
+            a[(i, j)] + if (i + j) % 2 == 0 { 0.2 } else { -0.2 }
+        });
+        let s1 = ssim(a.as_ref(), slightly.as_ref());
+        let s2 = ssim(a.as_ref(), heavily.as_ref());
+        assert!(s1 < 1.0);
+        assert!(s2 < s1);
+    }
+
+    #[test]
+    fn test_ms_ssim_identical_is_one() {
+        let a = gradient(96, 80);
+        assert_relative_eq!(ms_ssim(a.as_ref(), a.as_ref()), 1.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_max_abs_error() {
+        let a = gradient(8, 8);
+        let mut b = a.clone();
+        b[(3, 5)] += 4.0 / 255.0;
+        b[(1, 1)] -= 2.0 / 255.0;
+        assert_relative_eq!(max_abs_error(a.as_ref(), b.as_ref()), 4.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_bit_error_rate_and_correlation() {
+        let expected = bits![u8, Lsb0; 1, 0, 1, 1];
+        let extracted = bits![u8, Lsb0; 1, 1, 1, 0];
+        assert_relative_eq!(bit_error_rate(expected, extracted), 0.5);
+        assert_relative_eq!(normalized_correlation(expected, extracted), 0.0);
+        assert_relative_eq!(normalized_correlation(expected, expected), 1.0);
+    }
+
+    #[test]
+    fn test_compare_images_rejects_dimension_mismatch() {
+        let a = DynamicImage::new_rgb8(8, 8);
+        let b = DynamicImage::new_rgb8(8, 9);
+        assert!(compare_images(&a, &b).is_err());
+    }
+}
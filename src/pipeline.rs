@@ -0,0 +1,289 @@
+//! Composable watermarking pipelines.
+//!
+//! A [`Pipeline`] chains a [`DomainTransform`], which maps an image to the planes carrying
+//! the watermark and back, with a [`BlockEmbedder`], which hides one bit in a 4×4 block of
+//! these planes. Blocks are assigned watermark bits according to the [`WatermarkMode`], and
+//! every plane votes on each bit at extraction.
+//!
+//! The built-in chain, [`HaarDwt`] with the [`WatermarkConfig`] as embedder, produces the same
+//! images as [`crate::utils::embed_watermark_image`], whatever its
+//! [`crate::config::EmbeddingScheme`]; implementing either trait swaps one half of it,
+//! e.g. for a different color transform, or for DWT and DCT without SVD.
+
+use anyhow::{Result, bail};
+use bitvec::prelude::*;
+use faer::Mat;
+use image::{DynamicImage, Rgba32FImage};
+use rayon::prelude::*;
+use std::marker::PhantomData;
+
+use crate::{
+    BLOCK_SIZE, DwtedYCrBrAMat, YCrBrAMat,
+    config::{WatermarkConfig, WatermarkMode},
+    scalar::Scalar,
+    strategy::Permutation,
+    transform::{
+        block4::{Mat4, Svd4, dct4},
+        embed::{extract_singular_bit, quantize_svd, soft_to_hard_bits, vote},
+    },
+};
+
+/// Reversible transform from an image to the planes carrying the watermark.
+pub trait DomainTransform {
+    /// Scalar type of the planes
+    type Scalar: Scalar;
+    /// Everything besides the planes needed to invert the transform
+    type State;
+
+    /// Transforms an image into the planes to watermark, all of the same size.
+    fn forward(&self, img: &Rgba32FImage) -> (Vec<Mat<Self::Scalar>>, Self::State);
+
+    /// Rebuilds the image from the (watermarked) planes.
+    fn inverse(&self, planes: Vec<Mat<Self::Scalar>>, state: Self::State) -> Rgba32FImage;
+}
+
+/// Rule hiding one bit in a 4×4 block.
+pub trait BlockEmbedder<T: Scalar>: Sync {
+    /// Returns the block carrying `bit`. `position` is the index of the block in the plane,
+    /// row by row, for rules keyed per block.
+    fn embed_bit(&self, block: &Mat4<T>, bit: bool, position: usize) -> Mat4<T>;
+
+    /// Reads the bit carried by the block at `position`.
+    fn extract_bit(&self, block: &Mat4<T>, position: usize) -> bool;
+}
+
+/// Color conversion to YCbCr and one level of Haar DWT, carrying the watermark in the LL
+/// subbands of Y, Cb and Cr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HaarDwt<T: Scalar = f32>(PhantomData<T>);
+
+impl<T: Scalar> DomainTransform for HaarDwt<T> {
+    type Scalar = T;
+    type State = DwtedYCrBrAMat<T>;
+
+    fn forward(&self, img: &Rgba32FImage) -> (Vec<Mat<T>>, DwtedYCrBrAMat<T>) {
+        let ycbcr: YCrBrAMat<T> = img.clone().into();
+        let mut dwted = ycbcr.add_padding().dwt();
+        let planes = [&mut dwted.y.0, &mut dwted.cb.0, &mut dwted.cr.0]
+            .map(|plane| std::mem::replace(plane, Mat::new()))
+            .into();
+        (planes, dwted)
+    }
+
+    fn inverse(&self, planes: Vec<Mat<T>>, mut dwted: DwtedYCrBrAMat<T>) -> Rgba32FImage {
+        let [y, cb, cr]: [Mat<T>; 3] = planes.try_into().expect("one plane per channel");
+        (dwted.y.0, dwted.cb.0, dwted.cr.0) = (y, cb, cr);
+        dwted.idwt().remove_padding().into()
+    }
+}
+
+/// Quantization of the singular values of the DCT of each block, see
+/// [`WatermarkConfig::strength_1`] and [`WatermarkConfig::strength_2`].
+#[derive(Debug, Clone, Copy)]
+pub struct SvdQuantization {
+    /// Quantization strength of the first singular value
+    pub strength_1: i32,
+    /// Quantization strength of the second singular value, if used
+    pub strength_2: Option<i32>,
+}
+
+impl From<&WatermarkConfig> for SvdQuantization {
+    fn from(config: &WatermarkConfig) -> Self {
+        SvdQuantization {
+            strength_1: config.strength_1,
+            strength_2: config.strength_2,
+        }
+    }
+}
+
+impl<T: Scalar> BlockEmbedder<T> for SvdQuantization {
+    fn embed_bit(&self, block: &Mat4<T>, bit: bool, _position: usize) -> Mat4<T> {
+        let svd = Svd4::new(&dct4(block));
+        quantize_svd(&svd, bit, self.strength_1, self.strength_2)
+    }
+
+    fn extract_bit(&self, block: &Mat4<T>, _position: usize) -> bool {
+        extract_singular_bit(block, self.strength_1, self.strength_2)
+    }
+}
+
+/// A domain transform and a block embedder, composed into a watermarking pipeline.
+#[derive(Debug, Clone)]
+pub struct Pipeline<D, E> {
+    /// Transform to and from the watermarked planes
+    pub transform: D,
+    /// Rule hiding a bit in each block
+    pub embedder: E,
+    /// Distribution of the watermark bits over the blocks
+    pub mode: WatermarkMode,
+}
+
+impl Pipeline<HaarDwt, WatermarkConfig> {
+    /// The built-in chain, in `f32`, with the scheme, strengths and mode of `config`.
+    pub fn from_config(config: &WatermarkConfig) -> Self {
+        Pipeline::new(HaarDwt::default(), config.clone(), config.mode)
+    }
+}
+
+impl Default for Pipeline<HaarDwt, WatermarkConfig> {
+    fn default() -> Self {
+        Self::from_config(&WatermarkConfig::default())
+    }
+}
+
+impl<D, E> Pipeline<D, E>
+where
+    D: DomainTransform,
+    E: BlockEmbedder<D::Scalar>,
+{
+    /// Composes `transform` and `embedder`, distributing the bits according to `mode`.
+    pub fn new(transform: D, embedder: E, mode: WatermarkMode) -> Self {
+        Pipeline {
+            transform,
+            embedder,
+            mode,
+        }
+    }
+
+    /// Embeds watermark bits into a decoded image.
+    ///
+    /// # Returns
+    ///
+    /// The watermarked image before quantization, see
+    /// [`crate::utils::embed_watermark_image_as`].
+    ///
+    /// Fails if the watermark is empty or the planes have fewer blocks than it has bits.
+    pub fn embed(&self, img: &DynamicImage, watermark: &BitSlice<u8>) -> Result<Rgba32FImage> {
+        let (mut planes, state) = self.transform.forward(&img.to_rgba32f());
+        let grid = block_grid(&planes);
+        let nblocks = grid.0 * grid.1;
+        check_capacity(nblocks, watermark.len())?;
+
+        let perm = Permutation::from_mode(self.mode, nblocks);
+        let embedder = &self.embedder;
+        for plane in &mut planes {
+            let blocks: Vec<_> = (0..nblocks)
+                .into_par_iter()
+                .map(|i| {
+                    let bit = watermark[perm.corresponding_wmbits_position(i, watermark.len())];
+                    embedder.embed_bit(&read_block(plane, grid, i), bit, i)
+                })
+                .collect();
+            for (i, block) in blocks.iter().enumerate() {
+                write_block(plane, grid, i, block);
+            }
+        }
+        Ok(self.transform.inverse(planes, state))
+    }
+
+    /// Extracts watermark bits from a decoded image, by majority over blocks and planes.
+    ///
+    /// Fails under the same conditions as [`Pipeline::extract_soft_bits`].
+    pub fn extract_watermark_bits(&self, img: &DynamicImage, wm_len: usize) -> Result<BitVec<u8>> {
+        Ok(soft_to_hard_bits(&self.extract_soft_bits(img, wm_len)?))
+    }
+
+    /// Extracts the fraction of `true` votes behind each watermark bit.
+    ///
+    /// See [`crate::BlockCutted::extract_soft_bits`]. Fails if `wm_len` is zero or the planes
+    /// have fewer blocks than `wm_len`.
+    pub fn extract_soft_bits(&self, img: &DynamicImage, wm_len: usize) -> Result<Vec<f64>> {
+        let (planes, _) = self.transform.forward(&img.to_rgba32f());
+        let grid = block_grid(&planes);
+        let nblocks = grid.0 * grid.1;
+        check_capacity(nblocks, wm_len)?;
+
+        let embedder = &self.embedder;
+        let block_votes: Vec<usize> = (0..nblocks)
+            .into_par_iter()
+            .map(|i| {
+                planes
+                    .iter()
+                    .filter(|plane| embedder.extract_bit(&read_block(plane, grid, i), i))
+                    .count()
+            })
+            .collect();
+        let perm = Permutation::from_mode(self.mode, nblocks);
+        Ok(vote(&block_votes, planes.len(), &perm, wm_len))
+    }
+}
+
+fn check_capacity(nblocks: usize, wm_len: usize) -> Result<()> {
+    if wm_len == 0 {
+        bail!("watermark length cannot be zero");
+    }
+    if wm_len > nblocks {
+        bail!("watermark of {wm_len} bits does not fit, the image holds at most {nblocks}");
+    }
+    Ok(())
+}
+
+/// Number of whole blocks (rows, columns) in each plane
+fn block_grid<T: Scalar>(planes: &[Mat<T>]) -> (usize, usize) {
+    let shape = planes.first().expect("at least one plane").shape();
+    assert!(
+        planes.iter().all(|plane| plane.shape() == shape),
+        "planes of different sizes"
+    );
+    (shape.0 / BLOCK_SIZE, shape.1 / BLOCK_SIZE)
+}
+
+fn read_block<T: Scalar>(plane: &Mat<T>, (_, cols): (usize, usize), i: usize) -> Mat4<T> {
+    let (top, left) = (i / cols * BLOCK_SIZE, i % cols * BLOCK_SIZE);
+    std::array::from_fn(|r| std::array::from_fn(|c| plane[(top + r, left + c)]))
+}
+
+fn write_block<T: Scalar>(
+    plane: &mut Mat<T>,
+    (_, cols): (usize, usize),
+    i: usize,
+    block: &Mat4<T>,
+) {
+    let (top, left) = (i / cols * BLOCK_SIZE, i % cols * BLOCK_SIZE);
+    for (r, row) in block.iter().enumerate() {
+        for (c, &value) in row.iter().enumerate() {
+            plane[(top + r, left + c)] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{config_from_seed, embed_watermark_image_as};
+    use image::RgbImage;
+
+    #[test]
+    fn test_builtin_chain_matches_default() {
+        let img = DynamicImage::from(RgbImage::from_fn(70, 54, |x, y| {
+            image::Rgb([(x * 3) as u8, (y * 4) as u8, ((x * y) % 256) as u8])
+        }));
+        let config = config_from_seed(Some(9)).unwrap();
+        let watermark = bits![u8, Lsb0; 1, 0, 0, 1, 1];
+
+        let pipeline = Pipeline::from_config(&config);
+        let watermarked = pipeline.embed(&img, watermark).unwrap();
+        assert_eq!(
+            watermarked,
+            embed_watermark_image_as::<f32>(&img, watermark, &config)
+        );
+        assert_eq!(
+            pipeline
+                .extract_watermark_bits(&watermarked.into(), watermark.len())
+                .unwrap(),
+            watermark
+        );
+    }
+
+    #[test]
+    fn test_undersized_image() {
+        let img = DynamicImage::from(RgbImage::new(16, 16));
+        let pipeline = Pipeline::default();
+        // 16×16 pixels hold 2×2 blocks
+        assert!(pipeline.embed(&img, bits![u8, Lsb0; 1; 5]).is_err());
+        assert!(pipeline.embed(&img, bits![u8, Lsb0;]).is_err());
+        assert!(pipeline.extract_soft_bits(&img, 5).is_err());
+        assert!(pipeline.extract_soft_bits(&img, 0).is_err());
+        assert_eq!(pipeline.extract_soft_bits(&img, 4).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{Context, Result, bail};
+use image::ImageFormat;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::{Table, Value};
+
+use crate::config::{EmbeddingScheme, WatermarkConfig};
+use crate::tuning::{MAX_STRENGTH, MIN_STRENGTH};
+
+/// Name of the configuration directory under the user configuration directory.
+pub const CONFIG_DIR_NAME: &str = "blind_watermark";
+
+/// Name of the configuration file within [`CONFIG_DIR_NAME`].
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Returns the default configuration file path.
+///
+/// This is `$XDG_CONFIG_HOME/blind_watermark/config.toml`, or
+/// `~/.config/blind_watermark/config.toml` when `XDG_CONFIG_HOME` is unset.
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+/// How a payload given as text maps to the embedded bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "crate::config::repr::VersionedPayloadEncoding",
+        from = "crate::config::repr::VersionedPayloadEncoding"
+    )
+)]
+pub enum PayloadEncoding {
+    /// The UTF-8 bytes of the text
+    #[default]
+    Utf8,
+    /// Bytes written as hexadecimal digits, e.g. `c0ffee`
+    Hex,
+}
+
+impl PayloadEncoding {
+    /// Converts a payload to the bytes to embed.
+    pub fn encode(self, payload: &str) -> Result<Vec<u8>> {
+        match self {
+            PayloadEncoding::Utf8 => Ok(payload.as_bytes().to_vec()),
+            PayloadEncoding::Hex => {
+                if payload.len() % 2 != 0 {
+                    bail!("hex payload must have an even number of digits");
+                }
+                (0..payload.len())
+                    .step_by(2)
+                    .map(|i| {
+                        let digits = payload.get(i..i + 2).unwrap_or_default();
+                        u8::from_str_radix(digits, 16)
+                            .with_context(|| format!("invalid hex digits in payload: {digits}"))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Converts extracted bytes back to a payload.
+    pub fn decode(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            PayloadEncoding::Utf8 => {
+                String::from_utf8(bytes.to_vec()).context("extracted watermark is not valid UTF-8")
+            }
+            PayloadEncoding::Hex => Ok(bytes.iter().map(|b| format!("{b:02x}")).collect()),
+        }
+    }
+}
+
+impl fmt::Display for PayloadEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadEncoding::Utf8 => write!(f, "utf8"),
+            PayloadEncoding::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+impl FromStr for PayloadEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "utf8" | "utf-8" => Ok(PayloadEncoding::Utf8),
+            "hex" => Ok(PayloadEncoding::Hex),
+            other => bail!("unknown payload encoding: {other} (expected utf8 or hex)"),
+        }
+    }
+}
+
+/// Named set of settings from the configuration file.
+///
+/// Every setting is optional; command-line arguments take precedence over it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    /// Seed for the random strategy
+    pub seed: Option<u64>,
+    /// Quantization strength of the first singular value
+    pub strength_1: Option<i32>,
+    /// Quantization strength of the second singular value; `Some(None)` turns it off, which
+    /// `strength_2 = 0` writes in the file
+    pub strength_2: Option<Option<i32>>,
+    /// Embedding scheme, by its [`EmbeddingScheme::kind`]
+    pub scheme: Option<String>,
+    /// Secret key of the embedding scheme
+    pub scheme_key: Option<u64>,
+    /// Encoding of payloads given as text
+    pub encoding: Option<PayloadEncoding>,
+    /// File name prefix of batch outputs
+    pub prefix: Option<String>,
+    /// Root directory of batch outputs
+    pub out_dir: Option<PathBuf>,
+    /// Image format batch outputs are converted to
+    pub convert: Option<ImageFormat>,
+    /// Replace existing outputs
+    pub overwrite: bool,
+    /// Leave existing outputs untouched
+    pub skip_existing: bool,
+}
+
+impl Profile {
+    /// Applies the strengths of the profile on top of `base`.
+    ///
+    /// The scheme needs its key, which the command line may give instead, so it is left to
+    /// the caller.
+    pub fn apply(&self, base: WatermarkConfig) -> WatermarkConfig {
+        WatermarkConfig {
+            strength_1: self.strength_1.unwrap_or(base.strength_1),
+            strength_2: self.strength_2.unwrap_or(base.strength_2),
+            ..base
+        }
+    }
+
+    fn from_table(table: &Table) -> Result<Self> {
+        let mut profile = Profile::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "seed" => profile.seed = Some(integer(key, value)?),
+                "strength_1" => profile.strength_1 = Some(strength(key, value)?),
+                "strength_2" => {
+                    profile.strength_2 = Some(match integer(key, value)? {
+                        0 => None,
+                        _ => Some(strength(key, value)?),
+                    })
+                }
+                "scheme" => {
+                    let kind = string(key, value)?;
+                    // Checks the name only, the key may come from the command line
+                    EmbeddingScheme::from_kind(kind, Some(0))?;
+                    profile.scheme = Some(kind.to_owned());
+                }
+                "scheme_key" => profile.scheme_key = Some(integer(key, value)?),
+                "encoding" => profile.encoding = Some(string(key, value)?.parse()?),
+                "prefix" => profile.prefix = Some(string(key, value)?.to_owned()),
+                "out_dir" => profile.out_dir = Some(PathBuf::from(string(key, value)?)),
+                "convert" => {
+                    let extension = string(key, value)?;
+                    let format = ImageFormat::from_extension(extension)
+                        .with_context(|| format!("unknown image format: {extension}"))?;
+                    profile.convert = Some(format);
+                }
+                "overwrite" => profile.overwrite = boolean(key, value)?,
+                "skip_existing" => profile.skip_existing = boolean(key, value)?,
+                other => bail!("unknown profile key: {other}"),
+            }
+        }
+        if profile.overwrite && profile.skip_existing {
+            bail!("overwrite and skip_existing cannot both be set");
+        }
+        Ok(profile)
+    }
+}
+
+/// Contents of a configuration file.
+///
+/// ```toml
+/// default_profile = "social-media"
+///
+/// [profiles.social-media]
+/// seed = 42
+/// strength_1 = 48
+/// out_dir = "watermarked"
+/// convert = "jpg"
+/// skip_existing = true
+///
+/// [profiles.archive]
+/// strength_1 = 24
+/// strength_2 = 0 # off, even with a seed
+/// encoding = "hex"
+///
+/// [profiles.keyed]
+/// scheme = "spread_spectrum"
+/// scheme_key = 1234
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigFile {
+    /// Profile used when none is selected
+    pub default_profile: Option<String>,
+    /// Profiles by name
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ConfigFile {
+    /// Reads and parses a configuration file.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        content
+            .parse()
+            .with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Returns the profile called `name`, or the default profile when `name` is `None`.
+    ///
+    /// Without a default profile, an empty profile is returned.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let Some(name) = name.or(self.default_profile.as_deref()) else {
+            return Ok(Profile::default());
+        };
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None => bail!(
+                "unknown profile: {name} (available: {})",
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+}
+
+impl FromStr for ConfigFile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let table: Table = s.parse()?;
+        let mut config = ConfigFile::default();
+        for (key, value) in &table {
+            match key.as_str() {
+                "default_profile" => config.default_profile = Some(string(key, value)?.to_owned()),
+                "profiles" => {
+                    let Value::Table(profiles) = value else {
+                        bail!("profiles must be a table");
+                    };
+                    for (name, profile) in profiles {
+                        let Value::Table(profile) = profile else {
+                            bail!("profile {name} must be a table");
+                        };
+                        let profile = Profile::from_table(profile)
+                            .with_context(|| format!("invalid profile {name}"))?;
+                        config.profiles.insert(name.clone(), profile);
+                    }
+                }
+                other => bail!("unknown config key: {other}"),
+            }
+        }
+        if let Some(name) = &config.default_profile
+            && !config.profiles.contains_key(name)
+        {
+            bail!("default profile {name} is not defined");
+        }
+        Ok(config)
+    }
+}
+
+fn string<'a>(key: &str, value: &'a Value) -> Result<&'a str> {
+    value
+        .as_str()
+        .with_context(|| format!("{key} must be a string"))
+}
+
+fn boolean(key: &str, value: &Value) -> Result<bool> {
+    value
+        .as_bool()
+        .with_context(|| format!("{key} must be a boolean"))
+}
+
+fn integer<T: TryFrom<i64>>(key: &str, value: &Value) -> Result<T> {
+    value
+        .as_integer()
+        .and_then(|v| T::try_from(v).ok())
+        .with_context(|| format!("{key} must be a non-negative integer in range"))
+}
+
+fn strength(key: &str, value: &Value) -> Result<i32> {
+    let strength = integer(key, value)?;
+    if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&strength) {
+        bail!("{key} must be within {MIN_STRENGTH}..={MAX_STRENGTH}");
+    }
+    Ok(strength)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+default_profile = "print"
+
+[profiles.print]
+strength_1 = 60
+strength_2 = 30
+seed = 7
+
+[profiles.plain]
+strength_2 = 0
+
+[profiles.social-media]
+encoding = "hex"
+out_dir = "out"
+convert = "jpg"
+skip_existing = true
+scheme = "spread_spectrum"
+scheme_key = 12
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config: ConfigFile = CONFIG.parse().unwrap();
+
+        let print = config.profile(None).unwrap();
+        assert_eq!(print.seed, Some(7));
+        assert_eq!(print.strength_1, Some(60));
+        let applied = print.apply(WatermarkConfig::default());
+        assert_eq!(applied.strength_1, 60);
+        assert_eq!(applied.strength_2, Some(30));
+
+        let social = config.profile(Some("social-media")).unwrap();
+        let seeded = WatermarkConfig {
+            strength_2: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(social.apply(seeded.clone()).strength_2, Some(20));
+        assert_eq!(social.encoding, Some(PayloadEncoding::Hex));
+        assert_eq!(social.out_dir, Some(PathBuf::from("out")));
+        assert_eq!(social.convert, Some(ImageFormat::Jpeg));
+        assert!(social.skip_existing);
+        assert_eq!(social.scheme.as_deref(), Some("spread_spectrum"));
+        assert_eq!(social.scheme_key, Some(12));
+
+        // Turned off, also over the strength the seed brings
+        let plain = config.profile(Some("plain")).unwrap();
+        assert_eq!(plain.strength_2, Some(None));
+        assert_eq!(plain.apply(seeded).strength_2, None);
+
+        assert!(config.profile(Some("archive")).is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(
+            "[profiles.a]\nstrength_1 = 0"
+                .parse::<ConfigFile>()
+                .is_err()
+        );
+        assert!("[profiles.a]\ncolour = 1".parse::<ConfigFile>().is_err());
+        assert!(
+            "[profiles.a]\nscheme = \"dct_lsb\""
+                .parse::<ConfigFile>()
+                .is_err()
+        );
+        assert!(
+            "default_profile = \"b\"\n[profiles.a]"
+                .parse::<ConfigFile>()
+                .is_err()
+        );
+        assert_eq!(
+            "".parse::<ConfigFile>().unwrap().profile(None).unwrap(),
+            Profile::default()
+        );
+    }
+
+    #[test]
+    fn test_payload_encoding() {
+        assert_eq!(
+            PayloadEncoding::Hex.encode("c0ffee").unwrap(),
+            [0xc0, 0xff, 0xee]
+        );
+        assert_eq!(
+            PayloadEncoding::Hex.decode(&[0xc0, 0xff, 0xee]).unwrap(),
+            "c0ffee"
+        );
+        assert!(PayloadEncoding::Hex.encode("abc").is_err());
+        assert!(PayloadEncoding::Hex.encode("zz").is_err());
+        assert_eq!(PayloadEncoding::Utf8.encode("hi").unwrap(), b"hi");
+        assert!(PayloadEncoding::Utf8.decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_payload_encoding_serde() {
+        let json = serde_json::to_string(&PayloadEncoding::Hex).unwrap();
+        assert_eq!(json, r#"{"version":"1","kind":"hex"}"#);
+        assert_eq!(
+            serde_json::from_str::<PayloadEncoding>(&json).unwrap(),
+            PayloadEncoding::Hex
+        );
+        assert_eq!(
+            serde_json::from_str::<PayloadEncoding>(r#"{"version":"1","kind":"utf8"}"#).unwrap(),
+            PayloadEncoding::Utf8
+        );
+        assert!(serde_json::from_str::<PayloadEncoding>(r#""hex""#).is_err());
+    }
+}
@@ -1,17 +1,27 @@
+use crate::scalar::Scalar;
+
 /// Quantizes a singular value based on the bit and strength
-pub fn embed_quantization(target: f32, bit: bool, strength: i32) -> f32 {
-    let target = target * 255.0;
-    let f_strength = strength as f32;
-    (((target / f_strength).floor() + if bit { 3.0 / 4.0 } else { 1.0 / 4.0 }) * f_strength) / 255.0
+pub fn embed_quantization<T: Scalar>(target: T, bit: bool, strength: i32) -> T {
+    let scale = T::from_f64(255.0);
+    let target = target * scale;
+    let f_strength = T::from_f64(strength as f64);
+    let offset = T::from_f64(if bit { 3.0 / 4.0 } else { 1.0 / 4.0 });
+    (((target / f_strength).floor() + offset) * f_strength) / scale
 }
 
 /// Extracts the bit from a singular value using the quantization strength
-pub fn extract_quantization(target: f32, strength: i32) -> bool {
-    let target = target * 255.0;
-    let f_strength = strength as f32;
-    target % f_strength > f_strength / 2.0
+pub fn extract_quantization<T: Scalar>(target: T, strength: i32) -> bool {
+    let target = target * T::from_f64(255.0);
+    let f_strength = T::from_f64(strength as f64);
+    target % f_strength > f_strength / T::from_f64(2.0)
 }
 
+/// Position of a singular value within its quantization cell, in `[0, 1)`
+pub fn quantization_phase<T: Scalar>(target: T, strength: i32) -> T {
+    let target = target * T::from_f64(255.0);
+    let f_strength = T::from_f64(strength as f64);
+    (target % f_strength) / f_strength
+}
 /// Average the result from first two singular values
 pub fn average_value(first: bool, second: bool) -> bool {
     let mut mean: f32 = 0.0;
@@ -0,0 +1,83 @@
+use bitvec::prelude::*;
+
+use crate::{
+    BlockCutted,
+    config::WatermarkConfig,
+    metrics::{bit_error_rate, normalized_correlation},
+    scalar::Scalar,
+};
+
+/// Result of extracting a watermark whose expected content is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractionReport {
+    /// The extracted bits
+    pub bits: BitVec<u8>,
+    /// Fraction of bits extracted incorrectly
+    pub bit_error_rate: f64,
+    /// Normalized correlation between expected and extracted bits, in `[-1, 1]`
+    pub normalized_correlation: f64,
+    /// Indices of the bits that differ from the expected watermark
+    pub wrong_bits: Vec<usize>,
+}
+
+impl ExtractionReport {
+    /// Compares extracted bits against the expected watermark.
+    pub fn new(bits: BitVec<u8>, expected: &BitSlice<u8>) -> Self {
+        assert_eq!(bits.len(), expected.len(), "length mismatch");
+        let wrong_bits = bits
+            .iter()
+            .zip(expected.iter())
+            .enumerate()
+            .filter(|(_, (a, b))| **a != **b)
+            .map(|(i, _)| i)
+            .collect();
+        ExtractionReport {
+            bit_error_rate: bit_error_rate(expected, &bits),
+            normalized_correlation: normalized_correlation(expected, &bits),
+            bits,
+            wrong_bits,
+        }
+    }
+
+    /// Whether every bit was extracted correctly.
+    pub fn is_exact(&self) -> bool {
+        self.wrong_bits.is_empty()
+    }
+}
+
+impl<T: Scalar> BlockCutted<T> {
+    /// Extract watermark bits and compare them against the expected watermark
+    pub fn verify_watermark_bits(
+        &self,
+        expected: &BitSlice<u8>,
+        config: &WatermarkConfig,
+    ) -> ExtractionReport {
+        let bits = self.extract_watermark_bits(expected.len(), config);
+        ExtractionReport::new(bits, expected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_lists_wrong_bits() {
+        let expected = bits![u8, Lsb0; 1, 0, 1, 1, 0, 0, 1, 0];
+        let extracted = bitvec![u8, Lsb0; 1, 1, 1, 1, 0, 0, 0, 0];
+        let report = ExtractionReport::new(extracted, expected);
+        assert_eq!(report.wrong_bits, vec![1, 6]);
+        assert_eq!(report.bit_error_rate, 0.25);
+        assert_eq!(report.normalized_correlation, 0.5);
+        assert!(!report.is_exact());
+    }
+
+    #[test]
+    fn test_report_exact() {
+        let expected = bits![u8, Lsb0; 1, 0, 1];
+        let report = ExtractionReport::new(expected.to_bitvec(), expected);
+        assert!(report.is_exact());
+        assert_eq!(report.bit_error_rate, 0.0);
+        assert_eq!(report.normalized_correlation, 1.0);
+    }
+}
@@ -0,0 +1,64 @@
+//! Floating-point types the pipeline runs in.
+//!
+//! Every stage from [`crate::YCrBrAMat`] to [`crate::Imbedded`] is generic over its scalar
+//! type, which defaults to `f32`. With `f64`, the color conversion, the wavelet transform and
+//! the block transforms lose less precision, e.g. for archival copies of 16-bit sources, at
+//! twice the memory:
+//!
+//! ```
+//! use bitvec::prelude::*;
+//! use blind_watermark::prelude::*;
+//! use image::{DynamicImage, RgbImage};
+//!
+//! let img = DynamicImage::from(RgbImage::from_fn(64, 64, |x, y| {
+//!     image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x ^ y) * 4) as u8])
+//! }));
+//! let config = WatermarkConfig::default();
+//! let watermarked = embed_watermark_image_as::<f64>(&img, bits![u8, Lsb0; 1, 0, 1], &config);
+//! let archived = DynamicImage::from(DynamicImage::from(watermarked).to_rgb16());
+//! let extracted = extract_watermark_image_as::<f64>(&archived, 3, &config);
+//! assert_eq!(extracted, bits![u8, Lsb0; 1, 0, 1]);
+//! ```
+
+use std::fmt::Debug;
+use std::sync::LazyLock;
+
+use faer::traits::RealField;
+use num::{Float, NumCast, traits::FloatConst};
+
+use crate::transform::block4::{Mat4, dct4_basis};
+
+/// Scalar type of the image matrices: `f32` (the default) or `f64`.
+pub trait Scalar: Float + FloatConst + RealField + Send + Sync + Debug + 'static {
+    /// Normalized 4×4 DCT-II basis, computed once per type.
+    fn dct4_basis() -> &'static Mat4<Self>;
+
+    /// Converts from `f64`, rounding to the nearest value.
+    fn from_f64(value: f64) -> Self {
+        <Self as NumCast>::from(value).expect("floats convert between each other")
+    }
+
+    /// Converts to `f64`.
+    fn as_f64(self) -> f64 {
+        self.to_f64().expect("floats convert between each other")
+    }
+
+    /// Converts to `f32`, rounding to the nearest value.
+    fn as_f32(self) -> f32 {
+        self.to_f32().expect("floats convert between each other")
+    }
+}
+
+impl Scalar for f32 {
+    fn dct4_basis() -> &'static Mat4<f32> {
+        static BASIS: LazyLock<Mat4<f32>> = LazyLock::new(dct4_basis);
+        &BASIS
+    }
+}
+
+impl Scalar for f64 {
+    fn dct4_basis() -> &'static Mat4<f64> {
+        static BASIS: LazyLock<Mat4<f64>> = LazyLock::new(dct4_basis);
+        &BASIS
+    }
+}
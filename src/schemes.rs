@@ -0,0 +1,372 @@
+//! Embedding schemes, the rules hiding one bit in a 4×4 block.
+//!
+//! Besides the quantization of singular values, three classic rules work on the
+//! mid-frequency coefficients of the block's DCT, those whose indices sum to 2 or 3:
+//!
+//! - [`SpreadSpectrum`] adds or subtracts a keyed pattern and detects it by correlation.
+//! - [`DitherModulation`] quantizes each coefficient on one of two dithered lattices (QIM).
+//! - [`SpreadTransformDither`] dither-modulates the projection on a keyed direction (STDM),
+//!   spreading the quantization error over all coefficients.
+//! - [`RationalDither`] dither-modulates the same projection divided by the amplitude of the
+//!   other coefficients (RDM), which makes it invariant to a gain on the pixel values.
+//!
+//! Keyed values depend on the block position, so that every block uses its own pattern.
+//! [`WatermarkConfig`] implements [`BlockEmbedder`] by dispatching on its
+//! [`EmbeddingScheme`].
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+use crate::{
+    config::{EmbeddingScheme, WatermarkConfig},
+    pipeline::{BlockEmbedder, SvdQuantization},
+    scalar::Scalar,
+    transform::block4::{Mat4, dct4, idct4},
+};
+
+/// DCT coefficients whose indices sum to 2 or 3
+const MID_BAND: [(usize, usize); 7] = [(0, 2), (1, 1), (2, 0), (0, 3), (1, 2), (2, 1), (3, 0)];
+
+/// Coefficients of the mid band, as a vector
+type Band = [f64; MID_BAND.len()];
+
+/// Additive spread spectrum over the mid-frequency DCT coefficients.
+#[derive(Debug, Clone, Copy)]
+pub struct SpreadSpectrum {
+    /// Amplitude of the pattern, in 8-bit levels
+    pub strength: i32,
+    /// Secret key of the patterns
+    pub key: u64,
+}
+
+/// Dither modulation (QIM) of each mid-frequency DCT coefficient.
+#[derive(Debug, Clone, Copy)]
+pub struct DitherModulation {
+    /// Quantization step, in 8-bit levels
+    pub strength: i32,
+    /// Secret key of the dithers
+    pub key: u64,
+}
+
+/// Spread-transform dither modulation (STDM) of the mid-frequency DCT coefficients.
+#[derive(Debug, Clone, Copy)]
+pub struct SpreadTransformDither {
+    /// Quantization step of the projection, in 8-bit levels
+    pub strength: i32,
+    /// Secret key of the directions and dithers
+    pub key: u64,
+}
+
+/// Rational dither modulation of the mid-frequency DCT coefficients.
+///
+/// The projection is divided by the norm of the coefficients outside the mid band, which
+/// embedding leaves untouched and which scales like the projection under a gain.
+#[derive(Debug, Clone, Copy)]
+pub struct RationalDither {
+    /// Quantization step of the projection for a mid-gray block, in 8-bit levels; it scales
+    /// with the amplitude of the block
+    pub strength: i32,
+    /// Secret key of the directions and dithers
+    pub key: u64,
+}
+
+/// Norm outside the mid band of a mid-gray luma block: 4×4 LL values of 1
+const REFERENCE_NORM: f64 = 4.0;
+
+/// Smallest normalizing norm, so that black blocks keep a usable step
+const MIN_NORM: f64 = 1.0 / 255.0;
+
+impl<T: Scalar> BlockEmbedder<T> for SpreadSpectrum {
+    fn embed_bit(&self, block: &Mat4<T>, bit: bool, position: usize) -> Mat4<T> {
+        let pattern = keyed_pattern(self.key, position);
+        let amplitude = sign(bit) * step(self.strength);
+        map_band(block, |band| add_scaled(band, &pattern, amplitude))
+    }
+
+    fn extract_bit(&self, block: &Mat4<T>, position: usize) -> bool {
+        dot(&read_band(block), &keyed_pattern(self.key, position)) > 0.0
+    }
+}
+
+impl<T: Scalar> BlockEmbedder<T> for DitherModulation {
+    fn embed_bit(&self, block: &Mat4<T>, bit: bool, position: usize) -> Mat4<T> {
+        let delta = step(self.strength);
+        let dithers = keyed_dithers(self.key, position);
+        map_band(block, |band| {
+            for (value, dither) in band.iter_mut().zip(dithers) {
+                *value = quantize(*value, bit, dither * delta, delta);
+            }
+        })
+    }
+
+    fn extract_bit(&self, block: &Mat4<T>, position: usize) -> bool {
+        let delta = step(self.strength);
+        let dithers = keyed_dithers(self.key, position);
+        let band = read_band(block);
+        let distance = |bit| {
+            band.iter()
+                .zip(dithers)
+                .map(|(&value, dither)| {
+                    (value - quantize(value, bit, dither * delta, delta)).powi(2)
+                })
+                .sum::<f64>()
+        };
+        distance(true) < distance(false)
+    }
+}
+
+impl<T: Scalar> BlockEmbedder<T> for SpreadTransformDither {
+    fn embed_bit(&self, block: &Mat4<T>, bit: bool, position: usize) -> Mat4<T> {
+        let (direction, dither) = keyed_direction(self.key, position);
+        let delta = step(self.strength);
+        map_band(block, |band| {
+            let projection = dot(band, &direction);
+            let shift = quantize(projection, bit, dither * delta, delta) - projection;
+            add_scaled(band, &direction, shift);
+        })
+    }
+
+    fn extract_bit(&self, block: &Mat4<T>, position: usize) -> bool {
+        let (direction, dither) = keyed_direction(self.key, position);
+        let delta = step(self.strength);
+        let projection = dot(&read_band(block), &direction);
+        let distance = |bit| (projection - quantize(projection, bit, dither * delta, delta)).abs();
+        distance(true) < distance(false)
+    }
+}
+
+impl<T: Scalar> BlockEmbedder<T> for RationalDither {
+    fn embed_bit(&self, block: &Mat4<T>, bit: bool, position: usize) -> Mat4<T> {
+        let (direction, dither) = keyed_direction(self.key, position);
+        let delta = step(self.strength) / REFERENCE_NORM;
+        let norm = reference_norm(block);
+        map_band(block, |band| {
+            let ratio = dot(band, &direction) / norm;
+            let shift = quantize(ratio, bit, dither * delta, delta) - ratio;
+            add_scaled(band, &direction, shift * norm);
+        })
+    }
+
+    fn extract_bit(&self, block: &Mat4<T>, position: usize) -> bool {
+        let (direction, dither) = keyed_direction(self.key, position);
+        let delta = step(self.strength) / REFERENCE_NORM;
+        let ratio = dot(&read_band(block), &direction) / reference_norm(block);
+        let distance = |bit| (ratio - quantize(ratio, bit, dither * delta, delta)).abs();
+        distance(true) < distance(false)
+    }
+}
+
+impl<T: Scalar> BlockEmbedder<T> for WatermarkConfig {
+    fn embed_bit(&self, block: &Mat4<T>, bit: bool, position: usize) -> Mat4<T> {
+        let strength = self.strength_1;
+        match self.scheme {
+            EmbeddingScheme::SvdQuantization => {
+                SvdQuantization::from(self).embed_bit(block, bit, position)
+            }
+            EmbeddingScheme::SpreadSpectrum { key } => {
+                SpreadSpectrum { strength, key }.embed_bit(block, bit, position)
+            }
+            EmbeddingScheme::DitherModulation { key } => {
+                DitherModulation { strength, key }.embed_bit(block, bit, position)
+            }
+            EmbeddingScheme::SpreadTransformDither { key } => {
+                SpreadTransformDither { strength, key }.embed_bit(block, bit, position)
+            }
+            EmbeddingScheme::RationalDither { key } => {
+                RationalDither { strength, key }.embed_bit(block, bit, position)
+            }
+        }
+    }
+
+    fn extract_bit(&self, block: &Mat4<T>, position: usize) -> bool {
+        let strength = self.strength_1;
+        match self.scheme {
+            EmbeddingScheme::SvdQuantization => {
+                SvdQuantization::from(self).extract_bit(block, position)
+            }
+            EmbeddingScheme::SpreadSpectrum { key } => {
+                SpreadSpectrum { strength, key }.extract_bit(block, position)
+            }
+            EmbeddingScheme::DitherModulation { key } => {
+                DitherModulation { strength, key }.extract_bit(block, position)
+            }
+            EmbeddingScheme::SpreadTransformDither { key } => {
+                SpreadTransformDither { strength, key }.extract_bit(block, position)
+            }
+            EmbeddingScheme::RationalDither { key } => {
+                RationalDither { strength, key }.extract_bit(block, position)
+            }
+        }
+    }
+}
+
+/// Correlation of the mid band with the [`SpreadSpectrum`] pattern of `position`, and its
+/// variance when the block carries no pattern.
+///
+/// The pattern signs are independent, so that the correlation of an unmarked band has zero
+/// mean and a variance of its energy over the number of coefficients.
+pub(crate) fn spread_correlation<T: Scalar>(
+    block: &Mat4<T>,
+    key: u64,
+    position: usize,
+) -> (f64, f64) {
+    let band = read_band(block);
+    (
+        dot(&band, &keyed_pattern(key, position)),
+        dot(&band, &band) / MID_BAND.len() as f64,
+    )
+}
+
+/// Strength in 8-bit levels, on the scale of the planes
+pub(crate) fn step(strength: i32) -> f64 {
+    strength as f64 / 255.0
+}
+
+fn sign(bit: bool) -> f64 {
+    if bit { 1.0 } else { -1.0 }
+}
+
+/// Nearest point to `value` of the lattice `delta·ℤ + dither`, shifted by half a step for
+/// a `true` bit.
+pub(crate) fn quantize(value: f64, bit: bool, dither: f64, delta: f64) -> f64 {
+    let offset = dither + if bit { delta / 2.0 } else { 0.0 };
+    ((value - offset) / delta).round() * delta + offset
+}
+
+fn read_band<T: Scalar>(block: &Mat4<T>) -> Band {
+    let dct = dct4(block);
+    MID_BAND.map(|(r, c)| dct[r][c].as_f64())
+}
+
+/// Norm of the DCT coefficients outside the mid band, at least [`MIN_NORM`]
+fn reference_norm<T: Scalar>(block: &Mat4<T>) -> f64 {
+    let dct = dct4(block);
+    let total: f64 = dct.iter().flatten().map(|x| x.as_f64().powi(2)).sum();
+    let band: f64 = MID_BAND
+        .iter()
+        .map(|&(r, c)| dct[r][c].as_f64().powi(2))
+        .sum();
+    (total - band).max(0.0).sqrt().max(MIN_NORM)
+}
+
+/// Applies `f` to the mid band of the block's DCT.
+fn map_band<T: Scalar>(block: &Mat4<T>, f: impl FnOnce(&mut Band)) -> Mat4<T> {
+    let mut dct = dct4(block);
+    let mut band = MID_BAND.map(|(r, c)| dct[r][c].as_f64());
+    f(&mut band);
+    for (&(r, c), value) in MID_BAND.iter().zip(band) {
+        dct[r][c] = T::from_f64(value);
+    }
+    idct4(&dct)
+}
+
+fn dot(a: &Band, b: &Band) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// `band += scale · direction`
+fn add_scaled(band: &mut Band, direction: &Band, scale: f64) {
+    band.iter_mut()
+        .zip(direction)
+        .for_each(|(value, d)| *value += scale * d);
+}
+
+pub(crate) fn keyed_rng(key: u64, position: usize) -> Pcg64 {
+    Pcg64::seed_from_u64(key ^ (position as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// Unit-norm ±1 pattern
+fn keyed_pattern(key: u64, position: usize) -> Band {
+    let mut rng = keyed_rng(key, position);
+    let scale = 1.0 / (MID_BAND.len() as f64).sqrt();
+    std::array::from_fn(|_| sign(rng.random()) * scale)
+}
+
+/// Dithers as fractions of a step, within `[0, 1)`; scaled by the step when quantizing
+fn keyed_dithers(key: u64, position: usize) -> [f64; MID_BAND.len()] {
+    let mut rng = keyed_rng(key, position);
+    std::array::from_fn(|_| rng.random::<f64>())
+}
+
+/// Unit direction with uniformly distributed orientation, and the dither of the projection
+/// as a fraction of a step
+fn keyed_direction(key: u64, position: usize) -> (Band, f64) {
+    let mut rng = keyed_rng(key, position);
+    let mut direction: Band = std::array::from_fn(|_| {
+        // Box–Muller
+        let (u1, u2): (f64, f64) = (1.0 - rng.random::<f64>(), rng.random());
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    });
+    let norm = dot(&direction, &direction).sqrt();
+    direction.iter_mut().for_each(|d| *d /= norm);
+    (direction, rng.random())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks() -> Vec<Mat4> {
+        (0..20)
+            .map(|k| {
+                std::array::from_fn(|r| {
+                    std::array::from_fn(|c| {
+                        let x = (k * 31 + r * 7 + c * 13) % 17;
+                        0.3 + x as f32 * 0.05 + (k % 3) as f32 * 0.4
+                    })
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_keyed_values() {
+        assert!((dot(&keyed_pattern(1, 2), &keyed_pattern(1, 2)) - 1.0).abs() < 1e-12);
+        assert_ne!(keyed_pattern(1, 2), keyed_pattern(1, 3));
+        assert_ne!(keyed_pattern(1, 2), keyed_pattern(2, 2));
+        let (direction, dither) = keyed_direction(5, 8);
+        assert!((dot(&direction, &direction) - 1.0).abs() < 1e-12);
+        assert!((0.0..1.0).contains(&dither));
+    }
+
+    #[test]
+    fn test_rational_dither_gain_invariant() {
+        let embedder = RationalDither {
+            strength: 36,
+            key: 4,
+        };
+        for (i, block) in blocks().iter().enumerate() {
+            for bit in [false, true] {
+                let marked = embedder.embed_bit(block, bit, i);
+                assert!((reference_norm(&marked) - reference_norm(block)).abs() < 1e-5);
+                for gain in [0.8, 1.0, 1.2] {
+                    let scaled = marked.map(|row| row.map(|x| x * gain));
+                    assert_eq!(embedder.extract_bit(&scaled, i), bit, "gain {gain}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dithered_schemes_use_key() {
+        // Extraction with the wrong key is close to chance
+        let embedder = DitherModulation {
+            strength: 36,
+            key: 1,
+        };
+        let wrong = DitherModulation {
+            strength: 36,
+            key: 2,
+        };
+        let blocks = blocks();
+        let agreeing = blocks
+            .iter()
+            .enumerate()
+            .filter(|&(i, block)| {
+                let marked = embedder.embed_bit(block, true, i);
+                BlockEmbedder::<f32>::extract_bit(&wrong, &marked, i)
+            })
+            .count();
+        assert!(agreeing < blocks.len(), "{agreeing}");
+    }
+}
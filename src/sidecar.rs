@@ -0,0 +1,152 @@
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::{EmbeddingScheme, WatermarkConfig},
+    tuning::{MAX_STRENGTH, MIN_STRENGTH},
+};
+
+/// Extension appended to an image file name to form its sidecar path.
+pub const SIDECAR_EXTENSION: &str = "bwm";
+
+/// Returns the sidecar path of an image, e.g. `out.png` → `out.png.bwm`.
+pub fn sidecar_path<P: AsRef<Path>>(img: P) -> PathBuf {
+    let mut name = img.as_ref().as_os_str().to_owned();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Writes the embedding strengths and scheme of `config` to a sidecar file.
+///
+/// The sidecar is a small `key = value` text file. Only the strengths and the kind of
+/// scheme are recorded; the seed and the key of the scheme are secrets and must be provided
+/// again at extraction time.
+pub fn write_sidecar<P: AsRef<Path>>(path: P, config: &WatermarkConfig) -> Result<()> {
+    let mut content = String::from("# blind_watermark sidecar\n");
+    content.push_str(&format!("strength_1 = {}\n", config.strength_1));
+    if let Some(strength_2) = config.strength_2 {
+        content.push_str(&format!("strength_2 = {}\n", strength_2));
+    }
+    content.push_str(&format!("scheme = {}\n", config.scheme.kind()));
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Reads a sidecar file and applies the recorded strengths and scheme on top of `base`.
+///
+/// A sidecar without `strength_2` disables the second singular value, and one without
+/// `scheme`, written by earlier releases, keeps the scheme of `base`. `key` is the key of
+/// the recorded scheme, required when it uses one.
+pub fn read_sidecar<P: AsRef<Path>>(
+    path: P,
+    base: WatermarkConfig,
+    key: Option<u64>,
+) -> Result<WatermarkConfig> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read sidecar {}", path.display()))?;
+    parse_sidecar(&content, base, key)
+}
+
+fn parse_sidecar(
+    content: &str,
+    base: WatermarkConfig,
+    key: Option<u64>,
+) -> Result<WatermarkConfig> {
+    let mut strength_1 = None;
+    let mut strength_2 = None;
+    let mut scheme = base.scheme;
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, value)) = line.split_once('=') else {
+            bail!("malformed sidecar line: {line}");
+        };
+        let strength = || -> Result<i32> {
+            let value: i32 = value
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid value in sidecar line: {line}"))?;
+            if !(MIN_STRENGTH..=MAX_STRENGTH).contains(&value) {
+                bail!("sidecar strength must be within {MIN_STRENGTH}..={MAX_STRENGTH}: {line}");
+            }
+            Ok(value)
+        };
+        match name.trim() {
+            "strength_1" => strength_1 = Some(strength()?),
+            "strength_2" => strength_2 = Some(strength()?),
+            "scheme" => scheme = EmbeddingScheme::from_kind(value.trim(), key)?,
+            other => bail!("unknown sidecar key: {other}"),
+        }
+    }
+
+    let Some(strength_1) = strength_1 else {
+        bail!("sidecar is missing strength_1");
+    };
+    Ok(WatermarkConfig {
+        strength_1,
+        strength_2,
+        scheme,
+        ..base
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path("dir/out.png"),
+            PathBuf::from("dir/out.png.bwm")
+        );
+    }
+
+    #[test]
+    fn test_parse_sidecar() {
+        let parse = |content| parse_sidecar(content, Default::default(), None);
+        let config = parse("# comment\nstrength_1 = 24\nstrength_2 = 13\n").unwrap();
+        assert_eq!(config.strength_1, 24);
+        assert_eq!(config.strength_2, Some(13));
+
+        let config = parse("strength_1=30").unwrap();
+        assert_eq!(config.strength_1, 30);
+        assert_eq!(config.strength_2, None);
+
+        assert!(parse("strength_2 = 13").is_err());
+        assert!(parse("seed = 1").is_err());
+        assert!(parse("strength_1 = 0").is_err());
+        assert!(parse("strength_1 = 24\nstrength_2 = -3").is_err());
+        assert!(parse("strength_1 = 256").is_err());
+    }
+
+    #[test]
+    fn test_sidecar_scheme() {
+        let config = WatermarkConfig {
+            strength_1: 20,
+            scheme: EmbeddingScheme::SpreadSpectrum { key: 8 },
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("blind_watermark_{}.bwm", std::process::id()));
+        write_sidecar(&path, &config).unwrap();
+
+        let read = read_sidecar(&path, Default::default(), Some(8)).unwrap();
+        assert_eq!(read.strength_1, 20);
+        assert_eq!(read.scheme, EmbeddingScheme::SpreadSpectrum { key: 8 });
+        assert!(read_sidecar(&path, Default::default(), None).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // Sidecars of earlier releases keep the scheme of the base
+        let base = WatermarkConfig {
+            scheme: EmbeddingScheme::DitherModulation { key: 1 },
+            ..Default::default()
+        };
+        let read = parse_sidecar("strength_1 = 20", base.clone(), None).unwrap();
+        assert_eq!(read.scheme, EmbeddingScheme::DitherModulation { key: 1 });
+        assert!(parse_sidecar("strength_1 = 20\nscheme = dct_lsb", base, None).is_err());
+    }
+}
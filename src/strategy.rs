@@ -1,6 +1,8 @@
 use rand::{SeedableRng, seq::SliceRandom};
 use rand_pcg::Pcg64;
 
+use crate::config::WatermarkMode;
+
 /// A permutation strategy for randomizing watermark embedding positions.
 ///
 /// This struct handles the generation of a random permutation sequence based on a seed,
@@ -26,6 +28,19 @@ impl Permutation {
         Self { f, n }
     }
 
+    /// Creates the permutation used by a watermark mode.
+    ///
+    /// `Normal` mode uses the identity permutation.
+    pub(crate) fn from_mode(mode: WatermarkMode, n: usize) -> Self {
+        match mode {
+            WatermarkMode::Normal => Self {
+                f: (0..n).collect(),
+                n,
+            },
+            WatermarkMode::Strategy(seed) => Self::new(n, seed),
+        }
+    }
+
     /// Calculates the position of the watermark bit corresponding to a given block.
     ///
     /// # Arguments
@@ -50,6 +65,8 @@ impl Permutation {
     /// # Returns
     ///
     /// A vector of block indices that should contain the specified watermark bit.
+    ///
+    /// This scans every block; use [`Permutation::block_buckets`] to look up every bit.
     pub fn corresponding_block_positions(
         &self,
         wmbits_position: usize,
@@ -59,4 +76,33 @@ impl Permutation {
             .filter(|&i| self.f[i] % wm_len == wmbits_position)
             .collect()
     }
+
+    /// Groups the block positions by the watermark bit they carry, in one pass.
+    ///
+    /// Bucket `i` holds the same blocks as [`Permutation::corresponding_block_positions`]
+    /// for bit `i`, in increasing order.
+    pub fn block_buckets(&self, wm_len: usize) -> Vec<Vec<usize>> {
+        let mut buckets = vec![Vec::with_capacity(self.n.div_ceil(wm_len.max(1))); wm_len];
+        for i in 0..self.n {
+            buckets[self.corresponding_wmbits_position(i, wm_len)].push(i);
+        }
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_buckets_match_positions() {
+        let perm = Permutation::new(103, 7);
+        for wm_len in [1, 5, 103] {
+            let buckets = perm.block_buckets(wm_len);
+            assert_eq!(buckets.len(), wm_len);
+            for (i, bucket) in buckets.iter().enumerate() {
+                assert_eq!(bucket, &perm.corresponding_block_positions(i, wm_len));
+            }
+        }
+    }
 }
@@ -0,0 +1,318 @@
+use anyhow::{Context, Result, bail};
+use bitvec::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use serde_json::{Value, json};
+use std::f64::consts::PI;
+use std::path::Path;
+
+use crate::profile::PayloadEncoding;
+
+/// Version of the code table layout written by [`TardosCode::write`].
+pub const TARDOS_VERSION: u64 = 1;
+
+/// Code length factor `d` in `m = d·c²·ln(n/ε)`.
+///
+/// Tardos' original construction uses 100; the symmetric score used here (Škorić et al.)
+/// needs about `π²`, which is doubled to leave room for the bias cutoff and noisy extraction.
+const LENGTH_FACTOR: f64 = 2.0 * PI * PI;
+
+/// Probabilistic fingerprint code resisting collusion of up to `colluders` recipients.
+///
+/// Every position has a secret bias `p`; each recipient's code word bit is `true` with
+/// probability `p`. When colluders mix their copies, the positions where their code words
+/// agree keep that value, which correlates the extracted bits with their code words only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TardosCode {
+    /// Maximum number of colluders the code is designed for
+    pub colluders: usize,
+    /// Probability of accusing any innocent recipient
+    pub false_positive: f64,
+    /// Secret bias of each position
+    pub biases: Vec<f64>,
+    /// Code word of each recipient
+    pub codewords: Vec<BitVec<u8>>,
+}
+
+/// Score of a recipient after accusation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Accusation {
+    /// Index of the recipient in the code table
+    pub recipient: usize,
+    /// Accusation score; innocent recipients stay below the threshold
+    pub score: f64,
+    /// Whether the score exceeds the threshold
+    pub accused: bool,
+}
+
+impl TardosCode {
+    /// Code length needed for `users` recipients and up to `colluders` colluders.
+    ///
+    /// The length is rounded up to whole bytes.
+    pub fn code_length(users: usize, colluders: usize, false_positive: f64) -> usize {
+        let k = accusation_exponent(users, false_positive);
+        let m = (LENGTH_FACTOR * (colluders * colluders) as f64 * k).ceil() as usize;
+        m.div_ceil(8) * 8
+    }
+
+    /// Generates code words for `users` recipients.
+    ///
+    /// # Arguments
+    ///
+    /// * `users` - Number of recipients.
+    /// * `colluders` - Maximum number of colluders to resist.
+    /// * `false_positive` - Probability of accusing any innocent recipient.
+    /// * `seed` - Seed for the biases and code words.
+    pub fn generate(users: usize, colluders: usize, false_positive: f64, seed: u64) -> Self {
+        assert!(
+            users > 0 && colluders > 0,
+            "users and colluders must be positive"
+        );
+        assert!(
+            false_positive > 0.0 && false_positive < 1.0,
+            "false-positive probability must be within (0, 1)"
+        );
+        let m = Self::code_length(users, colluders, false_positive);
+        let cutoff = bias_cutoff(m, accusation_exponent(users, false_positive));
+        let mut rng = Pcg64::seed_from_u64(seed);
+
+        // p = sin²(r) with r uniform over the range where p stays within the cutoff
+        let r_min = cutoff.sqrt().asin();
+        let biases: Vec<f64> = (0..m)
+            .map(|_| rng.random_range(r_min..PI / 2.0 - r_min).sin().powi(2))
+            .collect();
+        let codewords = (0..users)
+            .map(|_| biases.iter().map(|&p| rng.random::<f64>() < p).collect())
+            .collect();
+
+        TardosCode {
+            colluders,
+            false_positive,
+            biases,
+            codewords,
+        }
+    }
+
+    /// Number of bits of each code word.
+    pub fn len(&self) -> usize {
+        self.biases.len()
+    }
+
+    /// Whether the code words are empty.
+    pub fn is_empty(&self) -> bool {
+        self.biases.is_empty()
+    }
+
+    /// Score above which a recipient is accused.
+    ///
+    /// For an innocent recipient, each position contributes a term of zero mean and variance
+    /// at most 1, bounded by the bias cutoff, so a Chernoff bound gives
+    /// `P(score > 2·√(m·k)) ≤ e^(-k)`. With `k = ln(n/ε)`, the probability of accusing any
+    /// innocent among `n` recipients is at most `ε`.
+    pub fn threshold(&self) -> f64 {
+        let k = accusation_exponent(self.codewords.len(), self.false_positive);
+        2.0 * (self.len() as f64 * k).sqrt()
+    }
+
+    /// Symmetric accusation scores of every recipient.
+    ///
+    /// `soft_bits` are the fractions of `true` votes from extraction; ambiguous bits
+    /// (close to 0.5) barely contribute.
+    pub fn scores(&self, soft_bits: &[f64]) -> Vec<f64> {
+        assert_eq!(soft_bits.len(), self.len(), "length mismatch");
+        self.codewords
+            .iter()
+            .map(|codeword| {
+                codeword
+                    .iter()
+                    .zip(&self.biases)
+                    .zip(soft_bits)
+                    .map(|((x, &p), &s)| {
+                        let agreement = if *x {
+                            ((1.0 - p) / p).sqrt()
+                        } else {
+                            -(p / (1.0 - p)).sqrt()
+                        };
+                        (2.0 * s - 1.0) * agreement
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Ranks recipients by accusation score, highest first.
+    pub fn accuse(&self, soft_bits: &[f64]) -> Vec<Accusation> {
+        let threshold = self.threshold();
+        let mut accusations: Vec<Accusation> = self
+            .scores(soft_bits)
+            .into_iter()
+            .enumerate()
+            .map(|(recipient, score)| Accusation {
+                recipient,
+                score,
+                accused: score > threshold,
+            })
+            .collect();
+        accusations.sort_by(|a, b| b.score.total_cmp(&a.score));
+        accusations
+    }
+
+    /// Writes the code table as JSON.
+    ///
+    /// The table holds the secret biases and must be kept private.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let codewords: Vec<_> = self.codewords.iter().map(codeword_hex).collect();
+        let json = json!({
+            "version": TARDOS_VERSION,
+            "colluders": self.colluders,
+            "false_positive": self.false_positive,
+            "length": self.len(),
+            "biases": self.biases,
+            "codewords": codewords,
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&json)?)?;
+        Ok(())
+    }
+
+    /// Reads a code table written by [`TardosCode::write`].
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read code table {}", path.display()))?;
+        let json: Value = serde_json::from_str(&content)
+            .with_context(|| format!("invalid code table {}", path.display()))?;
+
+        match json["version"].as_u64() {
+            Some(TARDOS_VERSION) => {}
+            Some(version) => bail!("unsupported code table version {version}"),
+            None => bail!("missing version in {}", path.display()),
+        }
+        let length = json["length"].as_u64().context("missing length")? as usize;
+        let biases: Vec<f64> = json["biases"]
+            .as_array()
+            .context("missing biases")?
+            .iter()
+            .map(|p| p.as_f64().filter(|p| *p > 0.0 && *p < 1.0))
+            .collect::<Option<_>>()
+            .context("biases must be within (0, 1)")?;
+        let codewords = json["codewords"]
+            .as_array()
+            .context("missing codewords")?
+            .iter()
+            .map(|hex| {
+                let bytes =
+                    PayloadEncoding::Hex.encode(hex.as_str().context("invalid codeword")?)?;
+                Ok(BitVec::from_vec(bytes))
+            })
+            .collect::<Result<Vec<BitVec<u8>>>>()?;
+        if biases.len() != length || codewords.iter().any(|c| c.len() != length) {
+            bail!("code table lengths do not match");
+        }
+
+        Ok(TardosCode {
+            colluders: json["colluders"].as_u64().context("missing colluders")? as usize,
+            false_positive: json["false_positive"]
+                .as_f64()
+                .context("missing false_positive")?,
+            biases,
+            codewords,
+        })
+    }
+}
+
+/// Code word as hex, packed least significant bit first as it is embedded.
+pub fn codeword_hex(codeword: &BitVec<u8>) -> String {
+    PayloadEncoding::Hex
+        .decode(codeword.as_raw_slice())
+        .unwrap_or_default()
+}
+
+/// Exponent `k = ln(n/ε)` of the per-recipient false-accusation probability `e^(-k)`.
+fn accusation_exponent(users: usize, false_positive: f64) -> f64 {
+    (users as f64 / false_positive).ln()
+}
+
+/// Smallest bias keeping every score term within the range required by the Chernoff bound.
+fn bias_cutoff(length: usize, k: f64) -> f64 {
+    k / (length as f64 + k)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Soft bits of a copy mixed by `colluders`, each bit taken from a random colluder.
+    fn interleave(code: &TardosCode, colluders: &[usize], rng: &mut Pcg64) -> Vec<f64> {
+        (0..code.len())
+            .map(|i| {
+                let from = colluders[rng.random_range(0..colluders.len())];
+                code.codewords[from][i] as u8 as f64
+            })
+            .collect()
+    }
+
+    /// Soft bits of the average of the colluders' copies.
+    fn average(code: &TardosCode, colluders: &[usize]) -> Vec<f64> {
+        (0..code.len())
+            .map(|i| {
+                let ones = colluders.iter().filter(|&&u| code.codewords[u][i]).count();
+                ones as f64 / colluders.len() as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_code_length() {
+        let m = TardosCode::code_length(100, 2, 1e-3);
+        assert_eq!(m % 8, 0);
+        assert!(m < 100 * 4 * 12, "{m}");
+        assert!(TardosCode::code_length(100, 3, 1e-3) > m);
+    }
+
+    #[test]
+    fn test_accuse_colluders() {
+        let mut rng = Pcg64::seed_from_u64(7);
+        for (trial, colluders) in [[3, 41], [10, 77], [0, 99]].iter().enumerate() {
+            let code = TardosCode::generate(100, 2, 1e-3, trial as u64);
+            for soft_bits in [
+                interleave(&code, colluders, &mut rng),
+                average(&code, colluders),
+            ] {
+                let accusations = code.accuse(&soft_bits);
+                assert!(colluders.contains(&accusations[0].recipient));
+                assert!(accusations[0].accused, "{:?}", accusations[0]);
+                for a in accusations.iter().filter(|a| a.accused) {
+                    assert!(colluders.contains(&a.recipient), "innocent accused: {a:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_recipient_copy() {
+        let code = TardosCode::generate(50, 3, 1e-3, 1);
+        let soft_bits: Vec<f64> = code.codewords[5].iter().map(|b| *b as u8 as f64).collect();
+        let accusations = code.accuse(&soft_bits);
+        assert_eq!(accusations[0].recipient, 5);
+        assert!(accusations[0].accused);
+        assert!(!accusations[1].accused);
+
+        // Without any information, nobody is accused
+        let accusations = code.accuse(&vec![0.5; code.len()]);
+        assert!(accusations.iter().all(|a| !a.accused && a.score == 0.0));
+    }
+
+    #[test]
+    fn test_code_table_round_trip() {
+        let code = TardosCode::generate(4, 2, 1e-2, 3);
+        let path = std::env::temp_dir().join(format!("tardos_{}.json", std::process::id()));
+        code.write(&path).unwrap();
+        let read = TardosCode::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.codewords, code.codewords);
+        assert_eq!(read.colluders, 2);
+        for (a, b) in read.biases.iter().zip(&code.biases) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use anyhow::{Context, Result, bail};
+use bitvec::prelude::*;
+use image::{DynamicImage, ImageBuffer, RgbImage};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::{
+    BLOCK_SIZE, YCrBrAMat, config::WatermarkConfig, strategy::Permutation, utils::render_imbedded,
+};
+
+/// Default number of pixel rows processed at once.
+pub const DEFAULT_STRIP_ROWS: u32 = 256;
+
+/// Pixel rows covered by one row of blocks: the DWT halves the height of the `BLOCK_SIZE` rows.
+const BLOCK_ROWS: u32 = 2 * BLOCK_SIZE as u32;
+
+/// Image rows, read from top to bottom.
+pub trait RowSource {
+    /// Width and height of the whole image.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Reads the next `rows` rows.
+    fn read_rows(&mut self, rows: u32) -> Result<DynamicImage>;
+}
+
+/// Rows of an image already decoded in memory.
+pub struct ImageRows<'a> {
+    img: &'a DynamicImage,
+    next: u32,
+}
+
+impl<'a> ImageRows<'a> {
+    /// Reads `img` from its first row.
+    pub fn new(img: &'a DynamicImage) -> Self {
+        ImageRows { img, next: 0 }
+    }
+}
+
+impl RowSource for ImageRows<'_> {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.img.width(), self.img.height())
+    }
+
+    fn read_rows(&mut self, rows: u32) -> Result<DynamicImage> {
+        if self.next + rows > self.img.height() {
+            bail!("read past the end of the image");
+        }
+        let strip = self.img.crop_imm(0, self.next, self.img.width(), rows);
+        self.next += rows;
+        Ok(strip)
+    }
+}
+
+/// Rows of a PNG file, decoded as they are read.
+pub struct PngRows {
+    reader: png::Reader<BufReader<File>>,
+    color: (png::ColorType, png::BitDepth),
+}
+
+impl PngRows {
+    /// Opens a non-interlaced PNG file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        // Same expansion as the `image` crate, which keeps 16-bit samples
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let reader = decoder.read_info()?;
+        if reader.info().interlaced {
+            bail!(
+                "{} is interlaced and cannot be read by rows",
+                path.display()
+            );
+        }
+        let color = reader.output_color_type();
+        Ok(PngRows { reader, color })
+    }
+}
+
+impl RowSource for PngRows {
+    fn dimensions(&self) -> (u32, u32) {
+        let info = self.reader.info();
+        (info.width, info.height)
+    }
+
+    fn read_rows(&mut self, rows: u32) -> Result<DynamicImage> {
+        let width = self.dimensions().0;
+        let mut data = Vec::new();
+        for _ in 0..rows {
+            let row = self
+                .reader
+                .next_row()?
+                .context("read past the end of the image")?;
+            data.extend_from_slice(row.data());
+        }
+
+        let wide = || {
+            data.chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect::<Vec<_>>()
+        };
+        use png::{BitDepth::*, ColorType::*};
+        let strip = match self.color {
+            (Grayscale, Eight) => {
+                ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageLuma8)
+            }
+            (GrayscaleAlpha, Eight) => {
+                ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageLumaA8)
+            }
+            (Rgb, Eight) => ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageRgb8),
+            (Rgba, Eight) => ImageBuffer::from_raw(width, rows, data).map(DynamicImage::ImageRgba8),
+            (Grayscale, Sixteen) => {
+                ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageLuma16)
+            }
+            (GrayscaleAlpha, Sixteen) => {
+                ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageLumaA16)
+            }
+            (Rgb, Sixteen) => {
+                ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageRgb16)
+            }
+            (Rgba, Sixteen) => {
+                ImageBuffer::from_raw(width, rows, wide()).map(DynamicImage::ImageRgba16)
+            }
+            (color, depth) => bail!("unsupported PNG color type {color:?} at {depth:?}"),
+        };
+        strip.context("truncated PNG rows")
+    }
+}
+
+/// Embeds and extracts strip by strip, holding only one strip's planes in memory.
+///
+/// Every block covers 8×8 pixels and every step of the pipeline is local to it, so strips
+/// whose height is a multiple of 8 give bit-identical results to
+/// [`crate::utils::embed_watermark_image`] and [`crate::utils::extract_soft_bits_image`].
+/// Only the permutation of the random strategy spans the whole image, at one index per block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TiledEngine {
+    strip_rows: u32,
+}
+
+impl Default for TiledEngine {
+    fn default() -> Self {
+        TiledEngine {
+            strip_rows: DEFAULT_STRIP_ROWS,
+        }
+    }
+}
+
+impl TiledEngine {
+    /// Processes `strip_rows` pixel rows at once, rounded up to a multiple of 8.
+    pub fn new(strip_rows: u32) -> Self {
+        TiledEngine {
+            strip_rows: strip_rows.max(1).div_ceil(BLOCK_ROWS) * BLOCK_ROWS,
+        }
+    }
+
+    /// Number of pixel rows processed at once.
+    pub fn strip_rows(&self) -> u32 {
+        self.strip_rows
+    }
+
+    /// Embeds watermark bits, handing each watermarked strip to `sink` as 8-bit RGB.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Rows of the image to watermark.
+    /// * `watermark` - Watermark bits to embed.
+    /// * `config` - Embedding configuration.
+    /// * `sink` - Receives the watermarked strips from top to bottom.
+    pub fn embed<F>(
+        &self,
+        source: &mut impl RowSource,
+        watermark: &BitSlice<u8>,
+        config: &WatermarkConfig,
+        mut sink: F,
+    ) -> Result<()>
+    where
+        F: FnMut(RgbImage) -> Result<()>,
+    {
+        let (width, height) = source.dimensions();
+        let (nblocks, blocks_width) = check_capacity(width, height, watermark.len())?;
+        let perm = Permutation::from_mode(config.mode, nblocks);
+
+        for (top, rows) in self.strips(height) {
+            let ycbcr: YCrBrAMat = source.read_rows(rows)?.to_rgba32f().into();
+            let first_block = (top / BLOCK_ROWS) as usize * blocks_width;
+            let imbedded = ycbcr.add_padding().dwt().cut().embed_watermark_bits_at(
+                watermark,
+                config,
+                &perm,
+                first_block,
+            );
+            sink(render_imbedded(imbedded).into_rgb8())?;
+        }
+        Ok(())
+    }
+
+    /// Extracts the fraction of `true` votes behind each watermark bit.
+    ///
+    /// See [`crate::BlockCutted::extract_soft_bits`].
+    pub fn extract_soft_bits(
+        &self,
+        source: &mut impl RowSource,
+        wm_len: usize,
+        config: &WatermarkConfig,
+    ) -> Result<Vec<f64>> {
+        let (width, height) = source.dimensions();
+        let (nblocks, blocks_width) = check_capacity(width, height, wm_len)?;
+        let perm = Permutation::from_mode(config.mode, nblocks);
+
+        let mut votes = vec![0usize; wm_len];
+        let mut counts = vec![0usize; wm_len];
+        for (top, rows) in self.strips(height) {
+            let ycbcr: YCrBrAMat = source.read_rows(rows)?.to_rgba32f().into();
+            let first_block = (top / BLOCK_ROWS) as usize * blocks_width;
+            let block_bits = ycbcr
+                .add_padding()
+                .dwt()
+                .cut()
+                .extract_block_bits(config, first_block);
+            for (i, (y, cb, cr)) in block_bits.into_iter().enumerate() {
+                let position = perm.corresponding_wmbits_position(first_block + i, wm_len);
+                votes[position] += y as usize + cb as usize + cr as usize;
+                counts[position] += 3;
+            }
+        }
+
+        Ok(votes
+            .into_iter()
+            .zip(counts)
+            .map(|(total, count)| total as f64 / count as f64)
+            .collect())
+    }
+
+    /// Embeds watermark bits into a PNG file, streaming rows from `img_in` to `img_out`.
+    ///
+    /// The output is an 8-bit RGB PNG.
+    pub fn embed_png<P: AsRef<Path>>(
+        &self,
+        img_in: P,
+        img_out: P,
+        watermark: &BitSlice<u8>,
+        config: &WatermarkConfig,
+    ) -> Result<()> {
+        let mut source = PngRows::open(img_in)?;
+        let (width, height) = source.dimensions();
+        let file = File::create(img_out.as_ref())
+            .with_context(|| format!("failed to create {}", img_out.as_ref().display()))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?.into_stream_writer()?;
+
+        self.embed(&mut source, watermark, config, |strip| {
+            writer.write_all(strip.as_raw())?;
+            Ok(())
+        })?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Extracts the fraction of `true` votes behind each watermark bit of a PNG file.
+    pub fn extract_soft_bits_png<P: AsRef<Path>>(
+        &self,
+        img_in: P,
+        wm_len: usize,
+        config: &WatermarkConfig,
+    ) -> Result<Vec<f64>> {
+        self.extract_soft_bits(&mut PngRows::open(img_in)?, wm_len, config)
+    }
+
+    /// First row and height of each strip, covering `height` rows.
+    fn strips(&self, height: u32) -> impl Iterator<Item = (u32, u32)> {
+        (0..height)
+            .step_by(self.strip_rows as usize)
+            .map(move |top| (top, self.strip_rows.min(height - top)))
+    }
+}
+
+/// Number of blocks and of blocks per row of an image, checking that the watermark fits.
+fn check_capacity(width: u32, height: u32, wm_len: usize) -> Result<(usize, usize)> {
+    if wm_len == 0 {
+        bail!("empty watermark");
+    }
+    // Padded to even dimensions, halved by the DWT, then cut into blocks
+    let blocks = |pixels: u32| (pixels.div_ceil(2) as usize) / BLOCK_SIZE;
+    let (blocks_height, blocks_width) = (blocks(height), blocks(width));
+    let nblocks = blocks_height * blocks_width;
+    if wm_len > nblocks {
+        bail!("watermark of {wm_len} bits does not fit, the image holds at most {nblocks}");
+    }
+    Ok((nblocks, blocks_width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{config_from_seed, embed_watermark_image, extract_soft_bits_image};
+    use image::RgbaImage;
+
+    /// Odd dimensions, with a partial row and column of blocks and a padded row and column
+    fn test_image() -> DynamicImage {
+        DynamicImage::from(RgbaImage::from_fn(75, 53, |x, y| {
+            image::Rgba([(x * 3 + y) as u8, (y * 4) as u8, ((x * y) % 251) as u8, 200])
+        }))
+    }
+
+    #[test]
+    fn test_strip_rows_rounding() {
+        assert_eq!(TiledEngine::new(0).strip_rows(), 8);
+        assert_eq!(TiledEngine::new(20).strip_rows(), 24);
+        assert_eq!(TiledEngine::new(256).strip_rows(), 256);
+    }
+
+    #[test]
+    fn test_matches_in_memory() {
+        let img = test_image();
+        let watermark = bits![u8, Lsb0; 1, 0, 0, 1, 1, 0, 1];
+        let config = config_from_seed(Some(9)).unwrap();
+        let expected = embed_watermark_image(&img, watermark, &config).into_rgb8();
+
+        for strip_rows in [8, 16, 48, 1024] {
+            let engine = TiledEngine::new(strip_rows);
+            let mut rows = Vec::new();
+            engine
+                .embed(&mut ImageRows::new(&img), watermark, &config, |strip| {
+                    rows.extend_from_slice(strip.as_raw());
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(rows, expected.as_raw().as_slice(), "{strip_rows} rows");
+
+            let watermarked = DynamicImage::from(expected.clone());
+            let soft_bits = engine
+                .extract_soft_bits(&mut ImageRows::new(&watermarked), watermark.len(), &config)
+                .unwrap();
+            assert_eq!(
+                soft_bits,
+                extract_soft_bits_image(&watermarked, watermark.len(), &config)
+            );
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let img = test_image();
+        let config = WatermarkConfig::default();
+        // (53 + 1) / 2 / 4 = 6 rows of (75 + 1) / 2 / 4 = 9 blocks
+        let watermark = bitvec![u8, Lsb0; 1; 6 * 9 + 1];
+        let result = TiledEngine::default().embed(
+            &mut ImageRows::new(&img),
+            &watermark,
+            &config,
+            |_| Ok(()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_empty_watermark() {
+        let img = test_image();
+        let config = WatermarkConfig::default();
+        let engine = TiledEngine::default();
+        let result = engine.embed(&mut ImageRows::new(&img), bits![u8, Lsb0;], &config, |_| {
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert!(
+            engine
+                .extract_soft_bits(&mut ImageRows::new(&img), 0, &config)
+                .is_err()
+        );
+    }
+}
@@ -1,3 +1,4 @@
+pub mod block4;
 pub mod cut;
 pub mod dct;
 pub mod dwt;
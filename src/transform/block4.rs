@@ -0,0 +1,285 @@
+//! Fixed-size 4×4 DCT and SVD used on every block.
+//!
+//! Blocks are copied into stack arrays, transformed with a DCT basis computed once, and
+//! decomposed with a one-sided Jacobi SVD, avoiding the allocations and the dispatch of the
+//! general [`faer`] routines. The loops have constant bounds, which the compiler unrolls and
+//! vectorizes.
+
+use crate::{BLOCK_SIZE, scalar::Scalar};
+
+const _: () = assert!(BLOCK_SIZE == 4, "the fixed-size path assumes 4×4 blocks");
+
+/// 4×4 matrix, row-major
+pub type Mat4<T = f32> = [[T; 4]; 4];
+
+/// Maximum number of Jacobi sweeps; 4×4 matrices converge in well under 10.
+const MAX_SWEEPS: usize = 30;
+
+/// Normalized DCT-II basis, see [`crate::transform::dct::dct2_2d`].
+///
+/// Cached per type by [`Scalar::dct4_basis`].
+pub(crate) fn dct4_basis<T: Scalar>() -> Mat4<T> {
+    let of = |x: usize| T::from_f64(x as f64);
+    std::array::from_fn(|r| {
+        let scale = if r == 0 {
+            T::from_f64(0.5)
+        } else {
+            T::FRAC_1_SQRT_2()
+        };
+        std::array::from_fn(|c| {
+            T::cos(T::PI() / of(4) * of(r) * (of(c) + T::from_f64(0.5))) * scale
+        })
+    })
+}
+
+/// Sum of `f(k)` over the four indices
+fn sum4<T: Scalar>(f: impl Fn(usize) -> T) -> T {
+    (0..4).fold(T::zero(), |acc, k| acc + f(k))
+}
+
+/// `a · b`
+fn mul<T: Scalar>(a: &Mat4<T>, b: &Mat4<T>) -> Mat4<T> {
+    std::array::from_fn(|r| std::array::from_fn(|c| sum4(|k| a[r][k] * b[k][c])))
+}
+
+/// `a · bᵀ`
+fn mul_transpose<T: Scalar>(a: &Mat4<T>, b: &Mat4<T>) -> Mat4<T> {
+    std::array::from_fn(|r| std::array::from_fn(|c| sum4(|k| a[r][k] * b[c][k])))
+}
+
+/// `aᵀ · b`
+fn transpose_mul<T: Scalar>(a: &Mat4<T>, b: &Mat4<T>) -> Mat4<T> {
+    std::array::from_fn(|r| std::array::from_fn(|c| sum4(|k| a[k][r] * b[k][c])))
+}
+
+/// 2D DCT-II of a 4×4 block.
+pub fn dct4<T: Scalar>(mat: &Mat4<T>) -> Mat4<T> {
+    let basis = T::dct4_basis();
+    mul_transpose(&mul(basis, mat), basis)
+}
+
+/// 2D DCT-III (inverse DCT) of a 4×4 block.
+pub fn idct4<T: Scalar>(mat: &Mat4<T>) -> Mat4<T> {
+    let basis = T::dct4_basis();
+    mul(&transpose_mul(basis, mat), basis)
+}
+
+/// Singular value decomposition `A = U · diag(s) · Vᵀ` of a 4×4 matrix.
+///
+/// Singular values are sorted in decreasing order. `U` and `V` are always complete
+/// orthonormal bases, so that singular values raised from zero can be reconstructed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Svd4<T: Scalar = f32> {
+    /// Left singular vectors, as columns
+    pub u: Mat4<T>,
+    /// Singular values, decreasing
+    pub s: [T; 4],
+    /// Right singular vectors, as columns
+    pub v: Mat4<T>,
+}
+
+impl<T: Scalar> Svd4<T> {
+    /// Decomposes `mat` by one-sided Jacobi rotations, computed in `f64`.
+    pub fn new(mat: &Mat4<T>) -> Self {
+        let (columns, v) = orthogonalize(mat, true);
+        let norms = columns.map(|col| norm(&col));
+        let mut order = [0, 1, 2, 3];
+        order.sort_by(|&a, &b| norms[b].total_cmp(&norms[a]));
+
+        let tolerance = norms[order[0]].max(f64::MIN_POSITIVE) * 1e-12;
+        let mut u_columns = [[0.0f64; 4]; 4];
+        let mut v_columns = [[0.0f64; 4]; 4];
+        let mut s = [T::zero(); 4];
+        for (k, &j) in order.iter().enumerate() {
+            v_columns[k] = v[j];
+            if norms[j] > tolerance {
+                u_columns[k] = columns[j].map(|x| x / norms[j]);
+                s[k] = T::from_f64(norms[j]);
+            }
+        }
+        complete_basis(&mut u_columns, s.iter().filter(|&&s| s > T::zero()).count());
+
+        Svd4 {
+            u: from_columns(&u_columns),
+            s,
+            v: from_columns(&v_columns),
+        }
+    }
+
+    /// Singular values of `mat`, decreasing, without computing the singular vectors.
+    pub fn singular_values(mat: &Mat4<T>) -> [T; 4] {
+        let (columns, _) = orthogonalize(mat, false);
+        let mut norms = columns.map(|col| norm(&col));
+        norms.sort_by(|a, b| b.total_cmp(a));
+        norms.map(T::from_f64)
+    }
+
+    /// `U · diag(s) · Vᵀ` with the given singular values.
+    pub fn reconstruct(&self, s: &[T; 4]) -> Mat4<T> {
+        let us: Mat4<T> = std::array::from_fn(|r| std::array::from_fn(|c| self.u[r][c] * s[c]));
+        mul_transpose(&us, &self.v)
+    }
+}
+
+/// Rotates the columns of `mat` until they are orthogonal.
+///
+/// Returns the rotated columns and, if `track` is set, the columns of the accumulated rotation.
+fn orthogonalize<T: Scalar>(mat: &Mat4<T>, track: bool) -> ([[f64; 4]; 4], [[f64; 4]; 4]) {
+    let mut a: [[f64; 4]; 4] = std::array::from_fn(|c| std::array::from_fn(|r| mat[r][c].as_f64()));
+    let mut v: [[f64; 4]; 4] =
+        std::array::from_fn(|c| std::array::from_fn(|r| (r == c) as u8 as f64));
+
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+        for p in 0..3 {
+            for q in p + 1..4 {
+                let alpha = dot(&a[p], &a[p]);
+                let beta = dot(&a[q], &a[q]);
+                let gamma = dot(&a[p], &a[q]);
+                if gamma == 0.0 || gamma.abs() <= f64::EPSILON * (alpha * beta).sqrt() {
+                    continue;
+                }
+                rotated = true;
+
+                let zeta = (beta - alpha) / (2.0 * gamma);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let cos = 1.0 / (1.0 + t * t).sqrt();
+                let sin = cos * t;
+                rotate(&mut a, p, q, cos, sin);
+                if track {
+                    rotate(&mut v, p, q, cos, sin);
+                }
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+    (a, v)
+}
+
+/// Applies a Givens rotation to columns `p < q`.
+fn rotate(columns: &mut [[f64; 4]; 4], p: usize, q: usize, cos: f64, sin: f64) {
+    let (left, right) = columns.split_at_mut(q);
+    for (x, y) in left[p].iter_mut().zip(right[0].iter_mut()) {
+        (*x, *y) = (cos * *x - sin * *y, sin * *x + cos * *y);
+    }
+}
+
+/// Fills the columns from `rank` on with unit vectors orthogonal to the previous ones.
+fn complete_basis(columns: &mut [[f64; 4]; 4], rank: usize) {
+    let mut candidates = 0..4;
+    for k in rank..4 {
+        loop {
+            let e = candidates.next().expect("four axes span the space");
+            let mut col: [f64; 4] = std::array::from_fn(|i| (i == e) as u8 as f64);
+            for prev in &columns[..k] {
+                let projection = dot(&col, prev);
+                col.iter_mut()
+                    .zip(prev)
+                    .for_each(|(x, p)| *x -= projection * p);
+            }
+            let n = norm(&col);
+            // Axes mostly within the span so far would lose too much precision
+            if n > 0.5 {
+                columns[k] = col.map(|x| x / n);
+                break;
+            }
+        }
+    }
+}
+
+fn dot(a: &[f64; 4], b: &[f64; 4]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn norm(a: &[f64; 4]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn from_columns<T: Scalar>(columns: &[[f64; 4]; 4]) -> Mat4<T> {
+    std::array::from_fn(|r| std::array::from_fn(|c| T::from_f64(columns[c][r])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::dct::{dct2_2d, dct3_2d};
+    use approx::assert_relative_eq;
+    use faer::prelude::*;
+
+    fn sample() -> Mat4 {
+        [
+            [0.52, 0.48, 0.61, 0.7],
+            [0.33, 0.9, 0.12, 0.41],
+            [0.05, 0.27, 0.77, 0.66],
+            [0.95, 0.14, 0.38, 0.2],
+        ]
+    }
+
+    fn to_faer(m: &Mat4) -> Mat<f32> {
+        Mat::from_fn(4, 4, |r, c| m[r][c])
+    }
+
+    fn assert_close(a: &Mat4, b: &Mat4) {
+        for r in 0..4 {
+            for c in 0..4 {
+                assert_relative_eq!(a[r][c], b[r][c], epsilon = 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dct4_matches_general_dct() {
+        let m = sample();
+        let expected = dct2_2d(to_faer(&m).as_ref());
+        let out = dct4(&m);
+        assert_close(
+            &out,
+            &std::array::from_fn(|r| std::array::from_fn(|c| expected[(r, c)])),
+        );
+        assert_close(&idct4(&out), &m);
+
+        let inverse = dct3_2d(to_faer(&m).as_ref());
+        assert_close(
+            &idct4(&m),
+            &std::array::from_fn(|r| std::array::from_fn(|c| inverse[(r, c)])),
+        );
+    }
+
+    #[test]
+    fn test_svd4_matches_faer() {
+        let m = sample();
+        let svd = Svd4::new(&m);
+        let expected = to_faer(&m).singular_values().unwrap();
+        for (s, e) in svd.s.iter().zip(&expected) {
+            assert_relative_eq!(s, e, epsilon = 1e-5);
+        }
+        assert_eq!(Svd4::singular_values(&m), svd.s);
+        assert_close(&svd.reconstruct(&svd.s), &m);
+
+        // Orthonormal factors
+        let identity: Mat4 =
+            std::array::from_fn(|r| std::array::from_fn(|c| (r == c) as u8 as f32));
+        assert_close(&transpose_mul(&svd.u, &svd.u), &identity);
+        assert_close(&transpose_mul(&svd.v, &svd.v), &identity);
+    }
+
+    #[test]
+    fn test_svd4_rank_deficient() {
+        // Rank one: raising the second singular value needs a complete basis
+        let m = [[1.0; 4]; 4];
+        let svd = Svd4::new(&m);
+        assert_relative_eq!(svd.s[0], 4.0, epsilon = 1e-6);
+        assert_eq!(&svd.s[1..], &[0.0; 3]);
+        let identity: Mat4 =
+            std::array::from_fn(|r| std::array::from_fn(|c| (r == c) as u8 as f32));
+        assert_close(&transpose_mul(&svd.u, &svd.u), &identity);
+
+        let raised = svd.reconstruct(&[4.0, 0.5, 0.0, 0.0]);
+        let s = Svd4::singular_values(&raised);
+        assert_relative_eq!(s[1], 0.5, epsilon = 1e-5);
+
+        assert_eq!(Svd4::new(&[[0.0; 4]; 4]).s, [0.0; 4]);
+    }
+}
@@ -1,8 +1,8 @@
-use crate::{BLOCK_SIZE, Block, BlockCutted, DwtedYCrBrAMat};
+use crate::{BLOCK_SIZE, Block, BlockCutted, DwtedYCrBrAMat, scalar::Scalar};
 
-impl DwtedYCrBrAMat {
+impl<T: Scalar> DwtedYCrBrAMat<T> {
     /// Cut the DWT transformed matrix into blocks
-    pub fn cut(self) -> BlockCutted {
+    pub fn cut(self) -> BlockCutted<T> {
         let mut y_ll_blocks = Vec::new();
         let mut cb_ll_blocks = Vec::new();
         let mut cr_ll_blocks = Vec::new();
@@ -1,106 +1,135 @@
-use crate::{AssembledYCrBrAMat, DwtedYCrBrAMat, PaddedYCrBrAMat};
+use crate::{AssembledYCrBrAMat, DwtedYCrBrAMat, PaddedYCrBrAMat, scalar::Scalar};
 use faer::traits::ComplexField;
-use faer::{Mat, MatRef};
+use faer::{ColMut, ColRef, Mat, MatRef};
 use num::Float;
+use rayon::prelude::*;
 
-impl AssembledYCrBrAMat {
-    pub fn idwt(self) -> PaddedYCrBrAMat {
+impl<T: Scalar> AssembledYCrBrAMat<T> {
+    pub fn idwt(self) -> PaddedYCrBrAMat<T> {
+        let (y, (cb, cr)) = rayon::join(
+            || haar_idwt_bands(&self.y),
+            || rayon::join(|| haar_idwt_bands(&self.cb), || haar_idwt_bands(&self.cr)),
+        );
         PaddedYCrBrAMat {
-            y: haar_idwt_2d(
-                self.y.0.as_ref(),
-                self.y.1.as_ref(),
-                self.y.2.as_ref(),
-                self.y.3.as_ref(),
-            ),
-            cb: haar_idwt_2d(
-                self.cb.0.as_ref(),
-                self.cb.1.as_ref(),
-                self.cb.2.as_ref(),
-                self.cb.3.as_ref(),
-            ),
-            cr: haar_idwt_2d(
-                self.cr.0.as_ref(),
-                self.cr.1.as_ref(),
-                self.cr.2.as_ref(),
-                self.cr.3.as_ref(),
-            ),
+            y,
+            cb,
+            cr,
             a: self.a,
             original_dimensions: self.original_dimensions,
         }
     }
 }
 
-impl PaddedYCrBrAMat {
-    pub fn dwt(self) -> DwtedYCrBrAMat {
+impl<T: Scalar> PaddedYCrBrAMat<T> {
+    pub fn dwt(self) -> DwtedYCrBrAMat<T> {
+        let (y, (cb, cr)) = rayon::join(
+            || haar_dwt_2d(self.y.as_ref()),
+            || {
+                rayon::join(
+                    || haar_dwt_2d(self.cb.as_ref()),
+                    || haar_dwt_2d(self.cr.as_ref()),
+                )
+            },
+        );
         DwtedYCrBrAMat {
-            y: haar_dwt_2d(self.y.as_ref()),
-            cb: haar_dwt_2d(self.cb.as_ref()),
-            cr: haar_dwt_2d(self.cr.as_ref()),
+            y,
+            cb,
+            cr,
             a: self.a,
             original_dimensions: self.original_dimensions,
         }
     }
 }
 
-impl DwtedYCrBrAMat {
-    pub fn idwt(self) -> PaddedYCrBrAMat {
+impl<T: Scalar> DwtedYCrBrAMat<T> {
+    pub fn idwt(self) -> PaddedYCrBrAMat<T> {
+        let (y, (cb, cr)) = rayon::join(
+            || haar_idwt_bands(&self.y),
+            || rayon::join(|| haar_idwt_bands(&self.cb), || haar_idwt_bands(&self.cr)),
+        );
         PaddedYCrBrAMat {
-            y: haar_idwt_2d(
-                self.y.0.as_ref(),
-                self.y.1.as_ref(),
-                self.y.2.as_ref(),
-                self.y.3.as_ref(),
-            ),
-            cb: haar_idwt_2d(
-                self.cb.0.as_ref(),
-                self.cb.1.as_ref(),
-                self.cb.2.as_ref(),
-                self.cb.3.as_ref(),
-            ),
-            cr: haar_idwt_2d(
-                self.cr.0.as_ref(),
-                self.cr.1.as_ref(),
-                self.cr.2.as_ref(),
-                self.cr.3.as_ref(),
-            ),
+            y,
+            cb,
+            cr,
             a: self.a,
             original_dimensions: self.original_dimensions,
         }
     }
 }
 
+/// Subbands (LL, HL, LH, HH) of a channel
+pub type Bands<T> = (Mat<T>, Mat<T>, Mat<T>, Mat<T>);
+
+fn haar_idwt_bands<T: Float + ComplexField + Send + Sync>(bands: &Bands<T>) -> Mat<T> {
+    haar_idwt_2d(
+        bands.0.as_ref(),
+        bands.1.as_ref(),
+        bands.2.as_ref(),
+        bands.3.as_ref(),
+    )
+}
+
 /// Single-level 2D Haar DWT: direct 2x2 block, returns (LL, HL, LH, HH)
-fn haar_dwt_2d<T: Float + ComplexField + Send + Sync>(
+fn haar_dwt_2d<T: Float + ComplexField + Send + Sync>(mat: MatRef<T>) -> Bands<T> {
+    let mut bands = (Mat::new(), Mat::new(), Mat::new(), Mat::new());
+    haar_dwt_2d_into(mat, &mut bands);
+    bands
+}
+
+/// Single-level 2D Haar DWT into reused subband buffers (LL, HL, LH, HH)
+///
+/// The buffers are resized to half the size of `mat`, reusing their allocations. Columns
+/// of the subbands are computed in parallel.
+pub fn haar_dwt_2d_into<T: Float + ComplexField + Send + Sync>(
     mat: MatRef<T>,
-) -> (Mat<T>, Mat<T>, Mat<T>, Mat<T>) {
+    (ll, hl, lh, hh): &mut Bands<T>,
+) {
     let (rows, cols) = mat.shape();
     assert!(rows % 2 == 0 && cols % 2 == 0);
 
     let h = rows / 2;
     let w = cols / 2;
+    for band in [&mut *ll, &mut *hl, &mut *lh, &mut *hh] {
+        band.resize_with(h, w, |_, _| T::zero());
+    }
 
-    let mut ll = Mat::zeros(h, w);
-    let mut hl = Mat::zeros(h, w);
-    let mut lh = Mat::zeros(h, w);
-    let mut hh = Mat::zeros(h, w);
+    // Columns must be contiguous to be read as slices
+    let owned;
+    let mat = if mat.row_stride() == 1 {
+        mat
+    } else {
+        owned = mat.to_owned();
+        owned.as_ref()
+    };
 
     let two = T::from(2.0).unwrap();
 
-    for r in 0..h {
-        for c in 0..w {
-            let a = mat[(2 * r, 2 * c)];
-            let b = mat[(2 * r, 2 * c + 1)];
-            let d = mat[(2 * r + 1, 2 * c)];
-            let e = mat[(2 * r + 1, 2 * c + 1)];
-
-            ll[(r, c)] = (a + b + d + e) / two;
-            hl[(r, c)] = (a - b + d - e) / two;
-            lh[(r, c)] = (a + b - d - e) / two;
-            hh[(r, c)] = (a - b - d + e) / two;
-        }
-    }
+    ll.par_col_iter_mut()
+        .zip(hl.par_col_iter_mut())
+        .zip(lh.par_col_iter_mut())
+        .zip(hh.par_col_iter_mut())
+        .enumerate()
+        .for_each(|(c, (((ll, hl), lh), hh))| {
+            let left = contiguous(mat.col(2 * c));
+            let right = contiguous(mat.col(2 * c + 1));
+            let outputs = contiguous_mut(ll)
+                .iter_mut()
+                .zip(contiguous_mut(hl))
+                .zip(contiguous_mut(lh))
+                .zip(contiguous_mut(hh));
 
-    (ll, hl, lh, hh)
+            for ((((ll, hl), lh), hh), (left, right)) in
+                outputs.zip(left.chunks_exact(2).zip(right.chunks_exact(2)))
+            {
+                let (a, b) = (left[0], right[0]);
+                let (d, e) = (left[1], right[1]);
+
+                *ll = (a + b + d + e) / two;
+                *hl = (a - b + d - e) / two;
+                *lh = (a + b - d - e) / two;
+                *hh = (a - b - d + e) / two;
+            }
+        });
 }
 
 /// Inverse transform: Reconstruct original matrix from (LL, HL, LH, HH)
@@ -110,33 +139,77 @@ fn haar_idwt_2d<T: Float + ComplexField + Send + Sync>(
     lh: MatRef<T>,
     hh: MatRef<T>,
 ) -> Mat<T> {
+    let mut out = Mat::new();
+    haar_idwt_2d_into(ll, hl, lh, hh, &mut out);
+    out
+}
+
+/// Inverse transform into a reused buffer, resized to twice the size of the subbands
+///
+/// Pairs of output columns are computed in parallel.
+pub fn haar_idwt_2d_into<T: Float + ComplexField + Send + Sync>(
+    ll: MatRef<T>,
+    hl: MatRef<T>,
+    lh: MatRef<T>,
+    hh: MatRef<T>,
+    out: &mut Mat<T>,
+) {
     let (h, w) = ll.shape();
-    let rows = h * 2;
-    let cols = w * 2;
+    assert!(
+        [hl.shape(), lh.shape(), hh.shape()]
+            .iter()
+            .all(|&s| s == (h, w))
+    );
+    out.resize_with(h * 2, w * 2, |_, _| T::zero());
+
+    // Columns must be contiguous to be read as slices
+    let owned = |band: MatRef<T>| (band.row_stride() != 1).then(|| band.to_owned());
+    let (ll_owned, hl_owned, lh_owned, hh_owned) = (owned(ll), owned(hl), owned(lh), owned(hh));
+    let ll = ll_owned.as_ref().map_or(ll, Mat::as_ref);
+    let hl = hl_owned.as_ref().map_or(hl, Mat::as_ref);
+    let lh = lh_owned.as_ref().map_or(lh, Mat::as_ref);
+    let hh = hh_owned.as_ref().map_or(hh, Mat::as_ref);
 
-    let mut out = Mat::zeros(rows, cols);
     let two = T::from(2.0).unwrap();
 
-    for r in 0..h {
-        for c in 0..w {
-            let llc = ll[(r, c)];
-            let hlc = hl[(r, c)];
-            let lhc = lh[(r, c)];
-            let hhc = hh[(r, c)];
+    out.par_col_chunks_mut(2).enumerate().for_each(|(c, pair)| {
+        let (left, right) = pair.split_at_col_mut(1);
+        let (left, right) = (
+            contiguous_mut(left.col_mut(0)),
+            contiguous_mut(right.col_mut(0)),
+        );
+        let bands = contiguous(ll.col(c))
+            .iter()
+            .zip(contiguous(hl.col(c)))
+            .zip(contiguous(lh.col(c)))
+            .zip(contiguous(hh.col(c)));
 
+        for ((((&llc, &hlc), &lhc), &hhc), (left, right)) in
+            bands.zip(left.chunks_exact_mut(2).zip(right.chunks_exact_mut(2)))
+        {
             let a = (llc + hlc + lhc + hhc) / two;
             let b = (llc - hlc + lhc - hhc) / two;
             let d = (llc + hlc - lhc - hhc) / two;
             let e = (llc - hlc - lhc + hhc) / two;
 
-            out[(2 * r, 2 * c)] = a;
-            out[(2 * r, 2 * c + 1)] = b;
-            out[(2 * r + 1, 2 * c)] = d;
-            out[(2 * r + 1, 2 * c + 1)] = e;
+            left[0] = a;
+            right[0] = b;
+            left[1] = d;
+            right[1] = e;
         }
-    }
+    });
+}
 
-    out
+fn contiguous<T>(col: ColRef<'_, T>) -> &[T] {
+    col.try_as_col_major()
+        .expect("column with unit row stride")
+        .as_slice()
+}
+
+fn contiguous_mut<T>(col: ColMut<'_, T>) -> &mut [T] {
+    col.try_as_col_major_mut()
+        .expect("column with unit row stride")
+        .as_slice_mut()
 }
 
 #[cfg(test)]
@@ -239,4 +312,25 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_haar_into_reuses_buffers() {
+        let data = Mat::<f32>::from_fn(6, 8, |r, c| (r * 8 + c) as f32 * 0.37 - 3.0);
+        let expected = haar_dwt_2d(data.as_ref());
+
+        // Buffers of the wrong size are resized, strided input is copied
+        let mut bands = (Mat::zeros(5, 1), Mat::new(), Mat::zeros(9, 9), Mat::new());
+        let transposed = data.transpose().to_owned();
+        haar_dwt_2d_into(transposed.transpose(), &mut bands);
+        assert_eq!(bands, expected);
+
+        let mut out = Mat::zeros(2, 2);
+        let (ll, hl, lh, hh) = &bands;
+        haar_idwt_2d_into(ll.as_ref(), hl.as_ref(), lh.as_ref(), hh.as_ref(), &mut out);
+        for r in 0..6 {
+            for c in 0..8 {
+                assert_relative_eq!(out[(r, c)], data[(r, c)], epsilon = 1e-5);
+            }
+        }
+    }
 }
@@ -1,47 +1,57 @@
 use crate::{
     Block, BlockCutted, Imbedded,
-    config::{WatermarkConfig, WatermarkMode},
-    quantization::{average_value, embed_quantization, extract_quantization},
+    config::{EmbeddingScheme, WatermarkConfig},
+    pipeline::BlockEmbedder,
+    quantization::{average_value, embed_quantization, extract_quantization, quantization_phase},
+    scalar::Scalar,
+    schemes::spread_correlation,
     strategy::Permutation,
-    transform::dct::{dct2_2d, dct3_2d},
+    transform::block4::{Mat4, Svd4, dct4, idct4},
 };
 use bitvec::prelude::*;
+use faer::Mat;
 use itertools::Itertools;
 use rayon::prelude::*;
 
-impl BlockCutted {
+/// Blocks of the Y, Cb and Cr channels
+pub(crate) type ChannelBlocks<T> = (Vec<Block<T>>, Vec<Block<T>>, Vec<Block<T>>);
+
+impl<T: Scalar> BlockCutted<T> {
     /// Embed watermark bits into blocks (Y, Cb, Cr)
     pub fn embed_watermark_bits(
         self,
         watermark_bits: &BitSlice<u8>,
         config: &WatermarkConfig,
-    ) -> Imbedded {
-        let wm_len = watermark_bits.len();
+    ) -> Imbedded<T> {
         let nblocks = self.blocks_dimensions.0 * self.blocks_dimensions.1;
 
-        assert!(nblocks >= wm_len, "not enough blocks for watermark");
+        assert!(
+            nblocks >= watermark_bits.len(),
+            "not enough blocks for watermark"
+        );
 
-        let perm = match config.mode {
-            WatermarkMode::Normal => Permutation {
-                f: (0..nblocks).collect(),
-                n: nblocks,
-            },
-            WatermarkMode::Strategy(seed) => Permutation::new(nblocks, seed),
-        };
+        let perm = Permutation::from_mode(config.mode, nblocks);
+        self.embed_watermark_bits_at(watermark_bits, config, &perm, 0)
+    }
 
-        let (y_ll_blocks, cb_ll_blocks, cr_ll_blocks) = (0..nblocks)
-            .into_par_iter()
-            .map(|i| {
-                let bit = watermark_bits[perm.corresponding_wmbits_position(i, wm_len)];
+    /// Embed watermark bits into the blocks of a strip of a larger image, whose first block
+    /// has index `first_block` in `perm`.
+    pub(crate) fn embed_watermark_bits_at(
+        self,
+        watermark_bits: &BitSlice<u8>,
+        config: &WatermarkConfig,
+        perm: &Permutation,
+        first_block: usize,
+    ) -> Imbedded<T> {
+        let (y_ll_blocks, cb_ll_blocks, cr_ll_blocks) =
+            self.imbed_blocks_at(watermark_bits, perm, first_block, |i, bit| {
+                let position = first_block + i;
                 (
-                    self.y_ll_blocks[i].imbed_bit(bit, config),
-                    self.cb_ll_blocks[i].imbed_bit(bit, config),
-                    self.cr_ll_blocks[i].imbed_bit(bit, config),
+                    self.y_ll_blocks[i].imbed_bit(bit, config, position),
+                    self.cb_ll_blocks[i].imbed_bit(bit, config, position),
+                    self.cr_ll_blocks[i].imbed_bit(bit, config, position),
                 )
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .multiunzip();
+            });
 
         Imbedded {
             y_ll_blocks,
@@ -56,104 +66,285 @@ impl BlockCutted {
         }
     }
 
+    /// Embed watermark bits into blocks (Y, Cb, Cr), leaving `self` untouched
+    ///
+    /// Same result as [`BlockCutted::embed_watermark_bits`], at the cost of copying the
+    /// subbands, so that several watermarks can be embedded into the same decomposition.
+    pub fn embed_watermark_bits_ref(
+        &self,
+        watermark_bits: &BitSlice<u8>,
+        config: &WatermarkConfig,
+    ) -> Imbedded<T> {
+        let blocks = self.imbed_blocks(watermark_bits, config, |i, bit| {
+            (
+                self.y_ll_blocks[i].imbed_bit(bit, config, i),
+                self.cb_ll_blocks[i].imbed_bit(bit, config, i),
+                self.cr_ll_blocks[i].imbed_bit(bit, config, i),
+            )
+        });
+        self.with_blocks(blocks)
+    }
+
+    /// Runs `imbed` on every block position with the watermark bit it carries.
+    pub(crate) fn imbed_blocks<F>(
+        &self,
+        watermark_bits: &BitSlice<u8>,
+        config: &WatermarkConfig,
+        imbed: F,
+    ) -> ChannelBlocks<T>
+    where
+        F: Fn(usize, bool) -> (Block<T>, Block<T>, Block<T>) + Sync,
+    {
+        let nblocks = self.blocks_dimensions.0 * self.blocks_dimensions.1;
+
+        assert!(
+            nblocks >= watermark_bits.len(),
+            "not enough blocks for watermark"
+        );
+
+        let perm = Permutation::from_mode(config.mode, nblocks);
+        self.imbed_blocks_at(watermark_bits, &perm, 0, imbed)
+    }
+
+    /// Same as [`BlockCutted::imbed_blocks`] for a strip of a larger image, whose first
+    /// block has index `first_block` in `perm`.
+    pub(crate) fn imbed_blocks_at<F>(
+        &self,
+        watermark_bits: &BitSlice<u8>,
+        perm: &Permutation,
+        first_block: usize,
+        imbed: F,
+    ) -> ChannelBlocks<T>
+    where
+        F: Fn(usize, bool) -> (Block<T>, Block<T>, Block<T>) + Sync,
+    {
+        let wm_len = watermark_bits.len();
+        let nblocks = self.blocks_dimensions.0 * self.blocks_dimensions.1;
+
+        (0..nblocks)
+            .into_par_iter()
+            .map(|i| {
+                let position = perm.corresponding_wmbits_position(first_block + i, wm_len);
+                imbed(i, watermark_bits[position])
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .multiunzip()
+    }
+
+    /// Watermarked blocks along with copies of the subbands preserved for recovery.
+    pub(crate) fn with_blocks(
+        &self,
+        (y_ll_blocks, cb_ll_blocks, cr_ll_blocks): ChannelBlocks<T>,
+    ) -> Imbedded<T> {
+        Imbedded {
+            y_ll_blocks,
+            cb_ll_blocks,
+            cr_ll_blocks,
+            y: self.y.clone(),
+            cb: self.cb.clone(),
+            cr: self.cr.clone(),
+            a: self.a.clone(),
+            original_dimensions: self.original_dimensions,
+            blocks_dimensions: self.blocks_dimensions,
+        }
+    }
+
     /// Extract watermark bits using 3-channel majority voting (parallelized and optimized)
-    pub fn extract_watermark_bits(self, wm_len: usize, config: &WatermarkConfig) -> BitVec<u8> {
+    pub fn extract_watermark_bits(&self, wm_len: usize, config: &WatermarkConfig) -> BitVec<u8> {
+        soft_to_hard_bits(&self.extract_soft_bits(wm_len, config))
+    }
+
+    /// Extract the fraction of `true` votes behind each watermark bit
+    ///
+    /// Values close to 0 or 1 indicate an unambiguous bit, values close to 0.5 an unreliable one.
+    ///
+    /// # Panics
+    ///
+    /// If `wm_len` is zero or exceeds the number of blocks, see
+    /// [`crate::utils::watermark_capacity`].
+    pub fn extract_soft_bits(&self, wm_len: usize, config: &WatermarkConfig) -> Vec<f64> {
         let nblocks = self.blocks_dimensions.0 * self.blocks_dimensions.1;
 
         assert!(wm_len > 0, "wm_len cannot be zero");
-        assert!(nblocks > 0);
+        assert!(nblocks >= wm_len, "not enough blocks for watermark");
 
-        let perm = match config.mode {
-            WatermarkMode::Normal => Permutation {
-                f: (0..nblocks).collect(),
-                n: nblocks,
-            },
-            WatermarkMode::Strategy(seed) => Permutation::new(nblocks, seed),
-        };
+        let perm = Permutation::from_mode(config.mode, nblocks);
 
         // 1. Parallel extraction of bits for Y, Cb and Cr at each block position `i`.
-        let block_bits: Vec<_> = (0..nblocks)
+        let block_votes: Vec<usize> = self
+            .extract_block_bits(config, 0)
+            .into_iter()
+            .map(|(y, cb, cr)| y as usize + cb as usize + cr as usize)
+            .collect();
+
+        // 2. Parallel voting for each watermark bit over the blocks carrying it.
+        vote(&block_votes, 3, &perm, wm_len)
+    }
+
+    /// Bits extracted from the Y, Cb and Cr block at each block position, for a strip whose
+    /// first block has index `first_block` in the image
+    pub(crate) fn extract_block_bits(
+        &self,
+        config: &WatermarkConfig,
+        first_block: usize,
+    ) -> Vec<(bool, bool, bool)> {
+        (0..self.y_ll_blocks.len())
             .into_par_iter()
             .map(|i| {
+                let position = first_block + i;
                 (
-                    self.y_ll_blocks[i].extract_bit(config),
-                    self.cb_ll_blocks[i].extract_bit(config),
-                    self.cr_ll_blocks[i].extract_bit(config),
+                    self.y_ll_blocks[i].extract_bit(config, position),
+                    self.cb_ll_blocks[i].extract_bit(config, position),
+                    self.cr_ll_blocks[i].extract_bit(config, position),
                 )
             })
-            .collect();
-
-        // 2. Parallel majority voting for deciding each watermark bit at bitvec position `i`.
-        (0..wm_len)
-            // .into_par_iter()
-            .map(|i| {
-                let corresponding_block_positions = perm.corresponding_block_positions(i, wm_len);
-
-                // Sum over the possible blocks corresponding to this watermark bit `i`
-                let total = corresponding_block_positions
-                    .iter()
-                    .map(|&j| {
-                        block_bits[j].0 as usize
-                            + block_bits[j].1 as usize
-                            + block_bits[j].2 as usize
-                    })
-                    .sum::<usize>();
-                let count = corresponding_block_positions.len() * 3;
-
-                // Majority voting: return true if most of the corresponding value is `true`, vice versa.
-                total * 2 >= count
-            })
-            .collect::<Vec<bool>>()
-            .into_iter()
-            .collect() // Convert to `BitVec
+            .collect()
     }
 }
 
-impl Block {
-    fn imbed_bit(&self, bit: bool, config: &WatermarkConfig) -> Block {
-        // Attempt SVD on the current matrix; fallback to original block if it fails
-        let Ok(svd_output) = dct2_2d(self.mat_data.as_ref()).svd() else {
-            return self.clone();
-        };
+/// Fraction of `true` votes behind each watermark bit, given the number of `true` votes
+/// out of `voters` at each block position.
+pub(crate) fn vote(
+    block_votes: &[usize],
+    voters: usize,
+    perm: &Permutation,
+    wm_len: usize,
+) -> Vec<f64> {
+    perm.block_buckets(wm_len)
+        .par_iter()
+        .map(|blocks| {
+            let total = blocks.iter().map(|&j| block_votes[j]).sum::<usize>();
+            let count = blocks.len() * voters;
+
+            total as f64 / count as f64
+        })
+        .collect()
+}
+
+/// Majority voting: a bit is `true` if most of the corresponding votes are `true`, vice versa.
+pub fn soft_to_hard_bits(soft_bits: &[f64]) -> BitVec<u8> {
+    soft_bits.iter().map(|&p| p >= 0.5).collect()
+}
+
+/// Mean agreement of the votes behind each bit, in `[0, 1]`
+///
+/// 1 means every vote agreed on every bit, 0 means every bit was a tie.
+pub fn extraction_confidence(soft_bits: &[f64]) -> f64 {
+    let total: f64 = soft_bits.iter().map(|p| (2.0 * p - 1.0).abs()).sum();
+    total / soft_bits.len().max(1) as f64
+}
+
+impl<T: Scalar> Block<T> {
+    /// Embeds a bit with the scheme of `config`; `position` is the index of the block in
+    /// the image, keying the dithered and spread schemes.
+    fn imbed_bit(&self, bit: bool, config: &WatermarkConfig, position: usize) -> Block<T> {
+        Block::from_mat4(&config.embed_bit(&self.to_mat4(), bit, position))
+    }
 
-        // Retrieve the left and right singular matrices
-        let u = svd_output.U();
-        let v = svd_output.V();
+    /// SVD of the block's DCT, which embedding only changes the singular values of
+    pub(crate) fn dct_svd(&self) -> Svd4<T> {
+        Svd4::new(&dct4(&self.to_mat4()))
+    }
 
-        // Hack: convert a read-only MatRef to an owned, mutable Mat
-        let mut s = svd_output.S() * 1.0;
+    /// Copy of the block on the stack
+    fn to_mat4(&self) -> Mat4<T> {
+        std::array::from_fn(|r| std::array::from_fn(|c| self.mat_data[(r, c)]))
+    }
 
-        // Retrieve quantization strength
-        let strength_1 = config.strength_1;
+    fn from_mat4(mat: &Mat4<T>) -> Block<T> {
+        Block {
+            mat_data: Mat::from_fn(4, 4, |r, c| mat[r][c]),
+        }
+    }
 
-        // Modify the primary singular value to embed the bit
-        s[0] = embed_quantization(s[0], bit, strength_1);
+    fn extract_bit(&self, config: &WatermarkConfig, position: usize) -> bool {
+        config.extract_bit(&self.to_mat4(), position)
+    }
 
-        if let Some(strength_2) = config.strength_2 {
-            s[1] = embed_quantization(s[1], bit, strength_2);
+    /// Quantization phases of the singular values carrying the watermark.
+    ///
+    /// Each phase lies in `[0, 1)`; embedding moves it to 1/4 for a `false` bit and 3/4
+    /// for a `true` bit. Singular values within the first quantization cell are skipped,
+    /// as are blocks without texture, whose phase is the same everywhere in flat areas.
+    /// Schemes other than [`EmbeddingScheme::SvdQuantization`] yield no phases.
+    pub(crate) fn quantization_phases(&self, config: &WatermarkConfig) -> Vec<f32> {
+        if config.scheme != EmbeddingScheme::SvdQuantization {
+            return Vec::new();
         }
+        let singular = Svd4::singular_values(&dct4(&self.to_mat4()));
+        if singular[1] * T::from_f64(255.0) < T::one() {
+            return Vec::new();
+        }
+
+        let strengths = std::iter::once(config.strength_1).chain(config.strength_2);
+        singular
+            .iter()
+            .zip(strengths)
+            .filter(|&(&value, strength)| {
+                value * T::from_f64(255.0) >= T::from_f64(strength as f64)
+            })
+            .map(|(&value, strength)| quantization_phase(value, strength).as_f32())
+            .collect()
+    }
+
+    /// Correlation of the block with the spread-spectrum pattern of `position`, and its
+    /// variance without watermark, see [`crate::schemes::SpreadSpectrum`].
+    pub(crate) fn spread_correlation(&self, key: u64, position: usize) -> (f64, f64) {
+        spread_correlation(&self.to_mat4(), key, position)
+    }
+}
+
+/// Rebuilds a block from the SVD of its DCT with the singular values quantized to `bit`.
+pub(crate) fn imbed_bit_into_svd<T: Scalar>(
+    svd: &Svd4<T>,
+    bit: bool,
+    config: &WatermarkConfig,
+) -> Block<T> {
+    Block::from_mat4(&quantize_svd(
+        svd,
+        bit,
+        config.strength_1,
+        config.strength_2,
+    ))
+}
 
-        // Reconstruct the matrix and return a new Block
+/// Rebuilds a 4×4 block from the SVD of its DCT with the singular values quantized to `bit`.
+pub(crate) fn quantize_svd<T: Scalar>(
+    svd: &Svd4<T>,
+    bit: bool,
+    strength_1: i32,
+    strength_2: Option<i32>,
+) -> Mat4<T> {
+    let mut s = svd.s;
 
-        let mat_data = dct3_2d((u * s * v.transpose()).as_ref());
-        Block { mat_data }
+    // Modify the primary singular value to embed the bit
+    s[0] = embed_quantization(s[0], bit, strength_1);
+
+    if let Some(strength_2) = strength_2 {
+        s[1] = embed_quantization(s[1], bit, strength_2);
     }
 
-    fn extract_bit(&self, config: &WatermarkConfig) -> bool {
-        // Retrieve singular values; return false if unavailable
-        let Ok(singular) = dct2_2d(self.mat_data.as_ref()).singular_values() else {
-            return false;
-        };
-        // Extract the bit from the primary singular value
-        let strength_1 = config.strength_1;
+    // Reconstruct the matrix
+    idct4(&svd.reconstruct(&s))
+}
 
-        match config.strength_2 {
-            None => extract_quantization(singular[0], strength_1),
-            Some(strength_2) => {
-                let first = extract_quantization(singular[0], strength_1);
-                let second = extract_quantization(singular[1], strength_2);
-                average_value(first, second)
-            }
+/// Reads the bit carried by the singular values of the DCT of a 4×4 block.
+pub(crate) fn extract_singular_bit<T: Scalar>(
+    mat: &Mat4<T>,
+    strength_1: i32,
+    strength_2: Option<i32>,
+) -> bool {
+    // Retrieve singular values
+    let singular = Svd4::singular_values(&dct4(mat));
+
+    // Extract the bit from the primary singular value
+    match strength_2 {
+        None => extract_quantization(singular[0], strength_1),
+        Some(strength_2) => {
+            let first = extract_quantization(singular[0], strength_1);
+            let second = extract_quantization(singular[1], strength_2);
+            average_value(first, second)
         }
     }
 }
@@ -161,8 +352,7 @@ impl Block {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{WatermarkConfig, WatermarkConfigBuilder, WatermarkMode};
-    use faer::prelude::*;
+    use crate::config::{EmbeddingScheme, WatermarkConfig, WatermarkConfigBuilder, WatermarkMode};
 
     /// Helper to create a simple test Block
     fn create_test_block() -> Block {
@@ -170,41 +360,66 @@ mod tests {
         Block { mat_data: data }
     }
 
-    fn create_test_config() -> WatermarkConfig {
-        WatermarkConfigBuilder::default()
-            .mode(WatermarkMode::Strategy(0))
-            .build()
-            .unwrap()
+    /// One configuration per embedding scheme
+    fn create_test_configs() -> Vec<WatermarkConfig> {
+        [
+            EmbeddingScheme::SvdQuantization,
+            EmbeddingScheme::SpreadSpectrum { key: 3 },
+            EmbeddingScheme::DitherModulation { key: 3 },
+            EmbeddingScheme::SpreadTransformDither { key: 3 },
+            EmbeddingScheme::RationalDither { key: 3 },
+        ]
+        .into_iter()
+        .map(|scheme| {
+            WatermarkConfigBuilder::default()
+                .mode(WatermarkMode::Strategy(0))
+                .scheme(scheme)
+                .build()
+                .unwrap()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_soft_bits() {
+        let soft = [1.0, 0.0, 0.5, 0.75];
+        assert_eq!(soft_to_hard_bits(&soft), bits![u8, Lsb0; 1, 0, 1, 1]);
+        assert!((extraction_confidence(&soft) - 0.625).abs() < 1e-12);
     }
 
     #[test]
     fn test_embed_extract_bit_true() {
         let block = create_test_block();
-        let config = create_test_config();
-
-        // Embed a true bit
-        let watermarked = block.imbed_bit(true, &config);
-
-        // Extract the bit
-        let extracted = watermarked.extract_bit(&config);
-
-        assert!(extracted, "Embedded true bit should be extracted as true");
+        for config in create_test_configs() {
+            // Embed a true bit
+            let watermarked = block.imbed_bit(true, &config, 5);
+
+            // Extract the bit
+            let extracted = watermarked.extract_bit(&config, 5);
+
+            assert!(
+                extracted,
+                "Embedded true bit should be extracted as true with {:?}",
+                config.scheme
+            );
+        }
     }
 
     #[test]
     fn test_embed_extract_bit_false() {
         let block = create_test_block();
-        let config = create_test_config();
-
-        // Embed a false bit
-        let watermarked = block.imbed_bit(false, &config);
-
-        // Extract the bit
-        let extracted = watermarked.extract_bit(&config);
-
-        assert!(
-            !extracted,
-            "Embedded false bit should be extracted as false"
-        );
+        for config in create_test_configs() {
+            // Embed a false bit
+            let watermarked = block.imbed_bit(false, &config, 5);
+
+            // Extract the bit
+            let extracted = watermarked.extract_bit(&config, 5);
+
+            assert!(
+                !extracted,
+                "Embedded false bit should be extracted as false with {:?}",
+                config.scheme
+            );
+        }
     }
 }
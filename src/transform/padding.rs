@@ -1,9 +1,9 @@
-use crate::{PaddedYCrBrAMat, YCrBrAMat};
+use crate::{PaddedYCrBrAMat, YCrBrAMat, scalar::Scalar};
 use faer::prelude::*;
 
-impl YCrBrAMat {
+impl<T: Scalar> YCrBrAMat<T> {
     /// Add padding to the matrix to make dimensions even
-    pub fn add_padding(mut self) -> PaddedYCrBrAMat {
+    pub fn add_padding(mut self) -> PaddedYCrBrAMat<T> {
         let (height, width) = self.dimensions;
 
         match (height % 2 == 1, width % 2 == 1) {
@@ -26,7 +26,7 @@ impl YCrBrAMat {
 
     fn add_zero_row_yuv(&mut self) {
         let cols = self.y.ncols();
-        let zero_row = Row::<f32>::zeros(cols);
+        let zero_row = Row::<T>::zeros(cols);
         let zero_row_view = zero_row.as_ref();
 
         self.y.push_row(zero_row_view);
@@ -36,7 +36,7 @@ impl YCrBrAMat {
 
     fn add_zero_col_yuv(&mut self) {
         let rows = self.y.nrows();
-        let zero_col = Col::<f32>::zeros(rows);
+        let zero_col = Col::<T>::zeros(rows);
         let zero_col_view = zero_col.as_ref();
 
         self.y.push_col(zero_col_view);
@@ -44,7 +44,7 @@ impl YCrBrAMat {
         self.cr.push_col(zero_col_view);
     }
 
-    fn internal_into_padded(self) -> PaddedYCrBrAMat {
+    fn internal_into_padded(self) -> PaddedYCrBrAMat<T> {
         PaddedYCrBrAMat {
             y: self.y,
             cb: self.cb,
@@ -55,9 +55,9 @@ impl YCrBrAMat {
     }
 }
 
-impl PaddedYCrBrAMat {
+impl<T: Scalar> PaddedYCrBrAMat<T> {
     /// Remove padding to restore original dimensions
-    pub fn remove_padding(self) -> YCrBrAMat {
+    pub fn remove_padding(self) -> YCrBrAMat<T> {
         YCrBrAMat {
             y: self
                 .y
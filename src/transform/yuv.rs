@@ -1,4 +1,4 @@
-use crate::YCrBrAMat;
+use crate::{YCrBrAMat, scalar::Scalar};
 use faer::prelude::*;
 use faer::traits::ComplexField;
 use image::Rgba32FImage;
@@ -12,24 +12,35 @@ pub struct YCrBrPixel<T: Float + ComplexField + Send + Sync> {
     pub cr: T,
 }
 
-impl From<Rgb<f32>> for YCrBrPixel<f32> {
-    fn from(Rgb([r, g, b]): Rgb<f32>) -> Self {
-        // BT.709 linear
-        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
-        let cb = (b - y) / 1.8556; // Blue difference
-        let cr = (r - y) / 1.5748; // Red difference
+impl<T: Scalar> YCrBrPixel<T> {
+    /// BT.709 linear
+    fn from_rgb([r, g, b]: [T; 3]) -> Self {
+        let y = T::from_f64(0.2126) * r + T::from_f64(0.7152) * g + T::from_f64(0.0722) * b;
+        let cb = (b - y) / T::from_f64(1.8556); // Blue difference
+        let cr = (r - y) / T::from_f64(1.5748); // Red difference
 
         YCrBrPixel { y, cb, cr }
     }
+
+    fn to_rgb(&self) -> [T; 3] {
+        let YCrBrPixel { y, cb, cr } = *self;
+        let r = y + T::from_f64(1.5748) * cr;
+        let g = y - T::from_f64(0.187324) * cb - T::from_f64(0.468124) * cr;
+        let b = y + T::from_f64(1.8556) * cb;
+
+        [r, g, b]
+    }
 }
 
-impl From<YCrBrPixel<f32>> for Rgb<f32> {
-    fn from(YCrBrPixel { y, cb, cr }: YCrBrPixel<f32>) -> Self {
-        let r = y + 1.5748 * cr;
-        let g = y - 0.187324 * cb - 0.468124 * cr;
-        let b = y + 1.8556 * cb;
+impl From<Rgb<f32>> for YCrBrPixel<f32> {
+    fn from(Rgb(rgb): Rgb<f32>) -> Self {
+        YCrBrPixel::from_rgb(rgb)
+    }
+}
 
-        Rgb([r, g, b])
+impl From<YCrBrPixel<f32>> for Rgb<f32> {
+    fn from(pixel: YCrBrPixel<f32>) -> Self {
+        Rgb(pixel.to_rgb())
     }
 }
 
@@ -41,40 +52,49 @@ pub struct YCrBrAPixel<T: Float + ComplexField + Send + Sync> {
     pub a: T,
 }
 
-impl From<Rgba<f32>> for YCrBrAPixel<f32> {
-    fn from(Rgba([r, g, b, a]): Rgba<f32>) -> Self {
-        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
-        let cb = (b - y) / 1.8556;
-        let cr = (r - y) / 1.5748;
-
+impl<T: Scalar> YCrBrAPixel<T> {
+    fn from_rgba([r, g, b, a]: [T; 4]) -> Self {
+        let YCrBrPixel { y, cb, cr } = YCrBrPixel::from_rgb([r, g, b]);
         YCrBrAPixel { y, cb, cr, a }
     }
+
+    fn to_rgba(&self) -> [T; 4] {
+        let [r, g, b] = YCrBrPixel {
+            y: self.y,
+            cb: self.cb,
+            cr: self.cr,
+        }
+        .to_rgb();
+        [r, g, b, self.a]
+    }
 }
 
-impl From<YCrBrAPixel<f32>> for Rgba<f32> {
-    fn from(YCrBrAPixel { y, cb, cr, a }: YCrBrAPixel<f32>) -> Self {
-        let r = y + 1.5748 * cr;
-        let g = y - 0.187324 * cb - 0.468124 * cr;
-        let b = y + 1.8556 * cb;
+impl From<Rgba<f32>> for YCrBrAPixel<f32> {
+    fn from(Rgba(rgba): Rgba<f32>) -> Self {
+        YCrBrAPixel::from_rgba(rgba)
+    }
+}
 
-        Rgba([r, g, b, a])
+impl From<YCrBrAPixel<f32>> for Rgba<f32> {
+    fn from(pixel: YCrBrAPixel<f32>) -> Self {
+        Rgba(pixel.to_rgba())
     }
 }
 
-impl From<Rgba32FImage> for YCrBrAMat {
+impl<T: Scalar> From<Rgba32FImage> for YCrBrAMat<T> {
     fn from(img: Rgba32FImage) -> Self {
         let (width, height) = img.dimensions();
         let width = width as usize;
         let height = height as usize;
-        let mut y = Mat::<f32>::zeros(height, width);
-        let mut cb = Mat::<f32>::zeros(height, width);
-        let mut cr = Mat::<f32>::zeros(height, width);
-        let mut a = Mat::<f32>::zeros(height, width);
+        let mut y = Mat::<T>::zeros(height, width);
+        let mut cb = Mat::<T>::zeros(height, width);
+        let mut cr = Mat::<T>::zeros(height, width);
+        let mut a = Mat::<T>::zeros(height, width);
 
         for i in 0..height {
             for j in 0..width {
-                let &pixel = img.get_pixel(j as u32, i as u32);
-                let ycbcra: YCrBrAPixel<f32> = pixel.into();
+                let &Rgba(pixel) = img.get_pixel(j as u32, i as u32);
+                let ycbcra = YCrBrAPixel::from_rgba(pixel.map(|x| T::from_f64(x as f64)));
                 y[(i, j)] = ycbcra.y;
                 cb[(i, j)] = ycbcra.cb;
                 cr[(i, j)] = ycbcra.cr;
@@ -91,8 +111,8 @@ impl From<Rgba32FImage> for YCrBrAMat {
     }
 }
 
-impl From<YCrBrAMat> for Rgba32FImage {
-    fn from(mat: YCrBrAMat) -> Self {
+impl<T: Scalar> From<YCrBrAMat<T>> for Rgba32FImage {
+    fn from(mat: YCrBrAMat<T>) -> Self {
         let (height, width) = mat.dimensions;
         let mut img = Rgba32FImage::new(width as u32, height as u32);
         for i in 0..height {
@@ -103,7 +123,7 @@ impl From<YCrBrAMat> for Rgba32FImage {
                     cr: mat.cr[(i, j)],
                     a: mat.a[(i, j)],
                 };
-                img.put_pixel(j as u32, i as u32, ycbcra.into());
+                img.put_pixel(j as u32, i as u32, Rgba(ycbcra.to_rgba().map(T::as_f32)));
             }
         }
         img
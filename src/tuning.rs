@@ -0,0 +1,133 @@
+use anyhow::{Result, bail};
+use bitvec::prelude::*;
+use image::{DynamicImage, Rgb32FImage};
+
+use crate::{
+    BlockCutted, YCrBrAMat,
+    config::{QualityTarget, WatermarkConfig},
+    decomposition::Decomposition,
+    metrics::{psnr_rgb, ssim_y},
+};
+
+/// Smallest `strength_1` considered by the search.
+pub const MIN_STRENGTH: i32 = 1;
+/// Largest `strength_1` considered by the search.
+pub const MAX_STRENGTH: i32 = 255;
+
+impl QualityTarget {
+    /// Measures the quality of `distorted` against `reference` with this target's metric.
+    pub fn measure(&self, reference: &Rgb32FImage, distorted: &Rgb32FImage) -> f64 {
+        match self {
+            QualityTarget::Psnr(_) => psnr_rgb(reference, distorted),
+            QualityTarget::Ssim(_) => ssim_y(reference, distorted),
+        }
+    }
+
+    /// Returns the quality floor.
+    pub fn floor(&self) -> f64 {
+        match *self {
+            QualityTarget::Psnr(floor) | QualityTarget::Ssim(floor) => floor,
+        }
+    }
+}
+
+/// Embeds a watermark using the strongest strengths that keep the image above a quality floor.
+///
+/// `strength_1` is binary-searched in `[MIN_STRENGTH, MAX_STRENGTH]`; when `config` uses a
+/// second singular value, `strength_2` keeps its ratio to `strength_1`. The mode of `config`
+/// is kept as-is. The result is checked to round-trip before being returned.
+///
+/// # Returns
+///
+/// The watermarked image and the configuration it was embedded with. The same configuration
+/// is needed for extraction, see [`crate::sidecar::write_sidecar`].
+pub fn embed_with_quality_target(
+    img: &DynamicImage,
+    watermark: &BitSlice<u8>,
+    config: &WatermarkConfig,
+    target: QualityTarget,
+) -> Result<(DynamicImage, WatermarkConfig)> {
+    let reference = DynamicImage::from(img.to_rgb8()).to_rgb32f();
+    // Every attempt reuses the same block SVDs
+    let decomposition = Decomposition::new(img);
+
+    let attempt = |strength_1: i32| {
+        let candidate = scaled_config(config, strength_1);
+        let output = decomposition.render(watermark, &candidate);
+        let quality = target.measure(&reference, &output.to_rgb32f());
+        (output, candidate, quality)
+    };
+
+    // Quality decreases as strength grows: find the largest strength meeting the floor.
+    let (mut lo, mut hi) = (MIN_STRENGTH, MAX_STRENGTH);
+    let mut best = None;
+    while lo <= hi {
+        let mid = lo + (hi - lo) / 2;
+        let (output, candidate, quality) = attempt(mid);
+        if quality >= target.floor() {
+            best = Some((output, candidate));
+            lo = mid + 1;
+        } else {
+            hi = mid - 1;
+        }
+    }
+
+    let Some((output, chosen)) = best else {
+        bail!("no strength reaches the quality target {:?}", target);
+    };
+    if !round_trips(&output, watermark, &chosen) {
+        bail!(
+            "quality target {:?} only allows strength {}, which is too weak to extract the watermark",
+            target,
+            chosen.strength_1
+        );
+    }
+    Ok((output, chosen))
+}
+
+/// Copies `config` with `strength_1` replaced, scaling `strength_2` proportionally.
+fn scaled_config(config: &WatermarkConfig, strength_1: i32) -> WatermarkConfig {
+    let strength_2 = config.strength_2.map(|strength_2| {
+        let ratio = strength_2 as f64 / config.strength_1 as f64;
+        ((strength_1 as f64 * ratio).round() as i32).max(MIN_STRENGTH)
+    });
+    WatermarkConfig {
+        strength_1,
+        strength_2,
+        ..config.clone()
+    }
+}
+
+fn round_trips(output: &DynamicImage, watermark: &BitSlice<u8>, config: &WatermarkConfig) -> bool {
+    let ycbcr: YCrBrAMat = output.to_rgba32f().into();
+    let cutted: BlockCutted = ycbcr.add_padding().dwt().cut();
+    cutted.extract_watermark_bits(watermark.len(), config) == watermark
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WatermarkConfigBuilder;
+
+    #[test]
+    fn test_scaled_config_keeps_ratio() {
+        let config = WatermarkConfigBuilder::default()
+            .strength_1(36)
+            .strength_2(20)
+            .build()
+            .unwrap();
+        let scaled = scaled_config(&config, 18);
+        assert_eq!(scaled.strength_1, 18);
+        assert_eq!(scaled.strength_2, Some(10));
+
+        let scaled = scaled_config(&config, 1);
+        assert_eq!(scaled.strength_2, Some(1));
+    }
+
+    #[test]
+    fn test_scaled_config_without_second_strength() {
+        let scaled = scaled_config(&WatermarkConfig::default(), 12);
+        assert_eq!(scaled.strength_1, 12);
+        assert_eq!(scaled.strength_2, None);
+    }
+}
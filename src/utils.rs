@@ -1,13 +1,300 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use bitvec::prelude::*;
 use image::{DynamicImage, ImageReader, Rgba32FImage};
 use std::path::Path;
 
 use crate::{
-    YCrBrAMat,
-    config::{WatermarkConfig, WatermarkConfigBuilder, WatermarkMode},
+    BLOCK_SIZE, Imbedded, YCrBrAMat,
+    config::{
+        FragileConfig, QualityTarget, WatermarkConfig, WatermarkConfigBuilder, WatermarkMode,
+    },
+    detection::Detection,
+    fragile::TamperMap,
+    report::ExtractionReport,
+    scalar::Scalar,
+    sidecar::{sidecar_path, write_sidecar},
+    tuning::embed_with_quality_target,
 };
 
+/// Builds the configuration used by the seed-based helpers.
+///
+/// Without a seed, the default configuration is used. With a seed, the random strategy
+/// is enabled and the second singular value is quantized with strength 20.
+pub fn config_from_seed(seed: Option<u64>) -> Result<WatermarkConfig> {
+    Ok(match seed {
+        None => WatermarkConfig::default(),
+        Some(seed) => WatermarkConfigBuilder::default()
+            .mode(WatermarkMode::Strategy(seed))
+            .strength_2(20)
+            .build()?,
+    })
+}
+
+/// Embeds watermark bits into a decoded image.
+///
+/// # Returns
+///
+/// The watermarked image as 8-bit RGB, which is what the file-based helpers save.
+pub fn embed_watermark_image(
+    img: &DynamicImage,
+    watermark: &BitSlice<u8>,
+    config: &WatermarkConfig,
+) -> DynamicImage {
+    DynamicImage::from(embed_watermark_image_as::<f32>(img, watermark, config))
+        .to_rgb8()
+        .into()
+}
+
+/// Embeds watermark bits into a decoded image, processing it in the scalar type `T`.
+///
+/// # Returns
+///
+/// The watermarked image before quantization, so that e.g. 16-bit sources can be saved
+/// without losing the precision gained from `f64`.
+pub fn embed_watermark_image_as<T: Scalar>(
+    img: &DynamicImage,
+    watermark: &BitSlice<u8>,
+    config: &WatermarkConfig,
+) -> Rgba32FImage {
+    let ycbcr: YCrBrAMat<T> = img.to_rgba32f().into();
+    reconstruct_imbedded(
+        ycbcr
+            .add_padding()
+            .dwt()
+            .cut()
+            .embed_watermark_bits(watermark, config),
+    )
+}
+
+/// Extracts watermark bits from a decoded image.
+pub fn extract_watermark_image(
+    img: &DynamicImage,
+    wm_len: usize,
+    config: &WatermarkConfig,
+) -> BitVec<u8> {
+    extract_watermark_image_as::<f32>(img, wm_len, config)
+}
+
+/// Extracts watermark bits from a decoded image, processing it in the scalar type `T`.
+pub fn extract_watermark_image_as<T: Scalar>(
+    img: &DynamicImage,
+    wm_len: usize,
+    config: &WatermarkConfig,
+) -> BitVec<u8> {
+    let ycbcr: YCrBrAMat<T> = img.to_rgba32f().into();
+    ycbcr
+        .add_padding()
+        .dwt()
+        .cut()
+        .extract_watermark_bits(wm_len, config)
+}
+
+/// Extracts the fraction of `true` votes behind each watermark bit from a decoded image.
+///
+/// See [`BlockCutted::extract_soft_bits`].
+pub fn extract_soft_bits_image(
+    img: &DynamicImage,
+    wm_len: usize,
+    config: &WatermarkConfig,
+) -> Vec<f64> {
+    let ycbcr: YCrBrAMat = img.to_rgba32f().into();
+    ycbcr
+        .add_padding()
+        .dwt()
+        .cut()
+        .extract_soft_bits(wm_len, config)
+}
+
+/// Extracts watermark bits from a decoded image and compares them against the expected ones.
+pub fn verify_watermark_image(
+    img: &DynamicImage,
+    expected: &BitSlice<u8>,
+    config: &WatermarkConfig,
+) -> ExtractionReport {
+    let ycbcr: YCrBrAMat = img.to_rgba32f().into();
+    ycbcr
+        .add_padding()
+        .dwt()
+        .cut()
+        .verify_watermark_bits(expected, config)
+}
+
+/// Tests whether a decoded image carries a watermark, see [`BlockCutted::detect_watermark`].
+pub fn detect_watermark_image(
+    img: &DynamicImage,
+    expected: Option<&BitSlice<u8>>,
+    config: &WatermarkConfig,
+    false_positive: f64,
+) -> Result<Detection> {
+    let ycbcr: YCrBrAMat = img.to_rgba32f().into();
+    ycbcr
+        .add_padding()
+        .dwt()
+        .cut()
+        .detect_watermark(expected, config, false_positive)
+}
+
+/// Rounds of [`embed_fragile_image`]
+pub(crate) const FRAGILE_ROUNDS: usize = 3;
+
+/// Embeds the fragile check pattern into a decoded image, see [`crate::fragile`].
+///
+/// # Returns
+///
+/// The marked image as 8-bit RGB, which must be saved in a lossless format.
+pub fn embed_fragile_image(img: &DynamicImage, config: &FragileConfig) -> DynamicImage {
+    (0..FRAGILE_ROUNDS).fold(img.clone(), |marked, _| fragile_round(&marked, config))
+}
+
+/// One round of [`embed_fragile_image`]. Each round starts from the previous 8-bit result,
+/// so that the changes lost to pixels clipped at black or white are made up by the others.
+pub(crate) fn fragile_round(img: &DynamicImage, config: &FragileConfig) -> DynamicImage {
+    let ycbcr: YCrBrAMat = img.to_rgba32f().into();
+    let mut dwted = ycbcr.add_padding().dwt();
+    dwted.embed_check_pattern(config);
+    let marked: Rgba32FImage = dwted.idwt().remove_padding().into();
+    DynamicImage::from(marked).to_rgb8().into()
+}
+
+/// Locates the regions of a decoded image edited since [`embed_fragile_image`], see
+/// [`crate::DwtedYCrBrAMat::check_pattern`].
+pub fn tamper_map_image(img: &DynamicImage, config: &FragileConfig) -> TamperMap {
+    let ycbcr: YCrBrAMat = img.to_rgba32f().into();
+    ycbcr.add_padding().dwt().check_pattern(config)
+}
+
+/// Converts watermarked blocks back to an 8-bit RGB image.
+pub(crate) fn render_imbedded<T: Scalar>(imbedded: Imbedded<T>) -> DynamicImage {
+    let output_image: DynamicImage = reconstruct_imbedded(imbedded).into();
+    output_image.to_rgb8().into()
+}
+
+/// Converts watermarked blocks back to a floating-point image.
+fn reconstruct_imbedded<T: Scalar>(imbedded: Imbedded<T>) -> Rgba32FImage {
+    imbedded.assemble().idwt().remove_padding().into()
+}
+
+/// Number of watermark bits an image of the given size holds, one per block.
+pub fn watermark_capacity(width: u32, height: u32) -> usize {
+    // Padded to even dimensions, halved by the DWT, then cut into blocks
+    let blocks = |pixels: u32| (pixels.div_ceil(2) as usize) / BLOCK_SIZE;
+    blocks(width) * blocks(height)
+}
+
+/// Fails when a watermark of `wm_len` bits is empty or does not fit in `img`.
+pub(crate) fn check_capacity(img: &DynamicImage, wm_len: usize) -> Result<()> {
+    if wm_len == 0 {
+        bail!("watermark length cannot be zero");
+    }
+    let capacity = watermark_capacity(img.width(), img.height());
+    if wm_len > capacity {
+        bail!("watermark of {wm_len} bits does not fit, the image holds at most {capacity}");
+    }
+    Ok(())
+}
+
+/// Extracts a watermark from an image using an explicit configuration.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the watermarked image.
+/// * `wm_len` - Length of the watermark in bits.
+/// * `config` - Configuration used during embedding.
+pub fn extract_watermark_bits_with_config<T: AsRef<Path>>(
+    img_in: T,
+    wm_len: usize,
+    config: &WatermarkConfig,
+) -> Result<BitVec<u8>> {
+    let img = ImageReader::open(img_in)?.decode()?;
+    check_capacity(&img, wm_len)?;
+    Ok(extract_watermark_image(&img, wm_len, config))
+}
+
+/// Extracts the fraction of `true` votes behind each watermark bit.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the watermarked image.
+/// * `wm_len` - Length of the watermark in bits.
+/// * `config` - Configuration used during embedding.
+pub fn extract_soft_bits_with_config<T: AsRef<Path>>(
+    img_in: T,
+    wm_len: usize,
+    config: &WatermarkConfig,
+) -> Result<Vec<f64>> {
+    let img = ImageReader::open(img_in)?.decode()?;
+    check_capacity(&img, wm_len)?;
+    Ok(extract_soft_bits_image(&img, wm_len, config))
+}
+
+/// Extracts a watermark and compares it against the expected one.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the watermarked image.
+/// * `expected` - The watermark bits that should have been embedded.
+/// * `config` - Configuration used during embedding.
+pub fn verify_watermark_bits_with_config<T: AsRef<Path>>(
+    img_in: T,
+    expected: &BitSlice<u8>,
+    config: &WatermarkConfig,
+) -> Result<ExtractionReport> {
+    let img = ImageReader::open(img_in)?.decode()?;
+    check_capacity(&img, expected.len())?;
+    Ok(verify_watermark_image(&img, expected, config))
+}
+
+/// Embeds a watermark into an image using an explicit configuration.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the input image.
+/// * `img_out` - Path to save the watermarked image.
+/// * `watermark` - The watermark bits to embed.
+/// * `config` - Configuration to embed with.
+pub fn embed_watermark_bits_with_config<T: AsRef<Path>>(
+    img_in: T,
+    img_out: T,
+    watermark: &BitSlice<u8>,
+    config: &WatermarkConfig,
+) -> Result<()> {
+    let img = ImageReader::open(img_in)?.decode()?;
+    check_capacity(&img, watermark.len())?;
+    embed_watermark_image(&img, watermark, config).save(img_out)?;
+    Ok(())
+}
+
+/// Embeds a watermark with the strongest strengths meeting a quality target.
+///
+/// The chosen strengths and the scheme are written to the sidecar of `img_out` (see
+/// [`crate::sidecar::sidecar_path`]) so that extraction can reproduce them.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the input image.
+/// * `img_out` - Path to save the watermarked image.
+/// * `watermark` - The watermark bits to embed.
+/// * `config` - Configuration whose strengths are tuned; the ratio between them is kept.
+/// * `target` - Minimum quality to preserve.
+///
+/// # Returns
+///
+/// The configuration the watermark was embedded with.
+pub fn embed_watermark_bits_with_target<T: AsRef<Path>>(
+    img_in: T,
+    img_out: T,
+    watermark: &BitSlice<u8>,
+    config: &WatermarkConfig,
+    target: QualityTarget,
+) -> Result<WatermarkConfig> {
+    let img = ImageReader::open(img_in)?.decode()?;
+    check_capacity(&img, watermark.len())?;
+    let (output, config) = embed_with_quality_target(&img, watermark, config, target)?;
+    output.save(&img_out)?;
+    write_sidecar(sidecar_path(&img_out), &config)?;
+    Ok(config)
+}
+
 /// Extracts a watermark from an image using the specified strategy.
 ///
 /// # Arguments
@@ -24,22 +311,57 @@ pub fn extract_watermark_bits<T: AsRef<Path>>(
     wm_len: usize,
     seed: Option<u64>,
 ) -> Result<BitVec<u8>> {
-    let img = ImageReader::open(img_in)?.decode()?.into_rgba32f();
+    extract_watermark_bits_with_config(img_in, wm_len, &config_from_seed(seed)?)
+}
 
-    let ycbcr: YCrBrAMat = img.into();
+/// Tests whether an image carries a watermark embedded with the given configuration.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the image to test.
+/// * `expected` - The embedded watermark bits, if known.
+/// * `config` - Configuration used during embedding.
+/// * `false_positive` - Probability of declaring an unmarked image as marked.
+pub fn detect_watermark_with_config<T: AsRef<Path>>(
+    img_in: T,
+    expected: Option<&BitSlice<u8>>,
+    config: &WatermarkConfig,
+    false_positive: f64,
+) -> Result<Detection> {
+    let img = ImageReader::open(img_in)?.decode()?;
+    detect_watermark_image(&img, expected, config, false_positive)
+}
 
-    let config = match seed {
-        None => WatermarkConfig::default(),
-        Some(seed) => WatermarkConfigBuilder::default()
-            .mode(WatermarkMode::Strategy(seed))
-            .strength_2(20)
-            .build()?,
-    };
-    Ok(ycbcr
-        .add_padding()
-        .dwt()
-        .cut()
-        .extract_watermark_bits(wm_len, &config))
+/// Tests whether an image carries a watermark embedded with the specified strategy.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the image to test.
+/// * `expected` - The embedded watermark bits, if known.
+/// * `seed` - Seed used for the random strategy during embedding.
+/// * `false_positive` - Probability of declaring an unmarked image as marked.
+pub fn detect_watermark<T: AsRef<Path>>(
+    img_in: T,
+    expected: Option<&BitSlice<u8>>,
+    seed: Option<u64>,
+    false_positive: f64,
+) -> Result<Detection> {
+    detect_watermark_with_config(img_in, expected, &config_from_seed(seed)?, false_positive)
+}
+
+/// Extracts a watermark using the specified strategy and compares it against the expected one.
+///
+/// # Arguments
+///
+/// * `img_in` - Path to the watermarked image.
+/// * `expected` - The watermark bits that should have been embedded.
+/// * `seed` - Seed used for the random strategy during embedding.
+pub fn verify_watermark_bits<T: AsRef<Path>>(
+    img_in: T,
+    expected: &BitSlice<u8>,
+    seed: Option<u64>,
+) -> Result<ExtractionReport> {
+    verify_watermark_bits_with_config(img_in, expected, &config_from_seed(seed)?)
 }
 
 /// Embeds a watermark into an image using the specified strategy.
@@ -56,27 +378,7 @@ pub fn embed_watermark_bits<T: AsRef<Path>>(
     watermark: &BitSlice<u8>,
     seed: Option<u64>,
 ) -> Result<()> {
-    let img = ImageReader::open(img_in)?.decode()?.into_rgba32f();
-    let ycbcr: YCrBrAMat = img.into();
-    let config = match seed {
-        None => WatermarkConfig::default(),
-        Some(seed) => WatermarkConfigBuilder::default()
-            .mode(WatermarkMode::Strategy(seed))
-            .strength_2(20)
-            .build()?,
-    };
-    let processed = ycbcr
-        .add_padding()
-        .dwt()
-        .cut()
-        .embed_watermark_bits(watermark, &config)
-        .assemble()
-        .idwt()
-        .remove_padding();
-    let processed_image: Rgba32FImage = processed.into();
-    let output_image: DynamicImage = processed_image.into();
-    output_image.to_rgb8().save(img_out)?;
-    Ok(())
+    embed_watermark_bits_with_config(img_in, img_out, watermark, &config_from_seed(seed)?)
 }
 
 /// Embeds a watermark into an image using the specified strategy.
@@ -0,0 +1,27 @@
+use bitvec::prelude::*;
+use blind_watermark::attacks::{Attack, benchmark};
+use blind_watermark::prelude::*;
+use image::ImageReader;
+
+#[test]
+fn test_benchmark_mild_attacks() {
+    let img = ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 256, 256);
+    let watermark = bits![u8, Lsb0; 0, 1, 1, 0, 1, 0, 0, 1];
+    let config = config_from_seed(Some(0)).unwrap();
+    let attacks = [Attack::Jpeg(90), Attack::Jpeg(75)];
+
+    let outcomes = benchmark(&img, watermark, &config, &attacks).unwrap();
+    assert_eq!(outcomes.len(), attacks.len());
+    for outcome in outcomes {
+        assert!(outcome.report.is_exact(), "{}", outcome.attack);
+        assert_eq!(
+            outcome.report.normalized_correlation, 1.0,
+            "{}",
+            outcome.attack
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use image::{Rgb, RgbImage};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Empty scratch directory for one test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bwm-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn gradient(size: u32) -> RgbImage {
+    RgbImage::from_fn(size, size, |x, y| {
+        Rgb([(x * 2) as u8, (y * 2) as u8, ((x + y) % 256) as u8])
+    })
+}
+
+fn blind_watermark(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_blind_watermark"))
+        .args(args)
+        // Keep a configuration file of the user out of the tests
+        .env(
+            "XDG_CONFIG_HOME",
+            std::env::temp_dir().join("bwm-no-config"),
+        )
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_batch_extract_reports_undersized_image() {
+    let dir = scratch_dir("extract");
+    gradient(16).save(dir.join("small.png")).unwrap();
+
+    let output = blind_watermark(&["extract", "-i", dir.to_str().unwrap(), "-l", "40"]);
+    assert!(!output.status.success());
+    let record: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["payload"], Value::Null);
+    assert_eq!(record["bits"], Value::Null);
+    assert!(
+        record["error"].as_str().unwrap().contains("does not fit"),
+        "{record}"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_batch_embed_reports_undersized_image() {
+    let dir = scratch_dir("embed");
+    let (input, out) = (dir.join("in"), dir.join("out"));
+    std::fs::create_dir(&input).unwrap();
+    gradient(16).save(input.join("small.png")).unwrap();
+    gradient(64).save(input.join("large.png")).unwrap();
+    let report = dir.join("failures.csv");
+
+    let output = blind_watermark(&[
+        "embed",
+        "-i",
+        input.to_str().unwrap(),
+        "--out-dir",
+        out.to_str().unwrap(),
+        "-s",
+        "hello",
+        "--seed",
+        "1",
+        "--failure-report",
+        report.to_str().unwrap(),
+    ]);
+    // The batch completes, and fails because of the undersized image only
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 succeeded, 0 skipped, 1 failed"));
+    assert!(out.join("large.png").is_file());
+    assert!(!out.join("small.png").exists());
+
+    let report = std::fs::read_to_string(report).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 2, "{report}");
+    assert!(lines[1].contains("small.png") && lines[1].contains("does not fit"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_batch_embed_never_overwrites_inputs() {
+    let dir = scratch_dir("overwrite");
+    for name in ["a.png", "b.jpg", "c.jpg", "c.jpeg"] {
+        gradient(64).save(dir.join(name)).unwrap();
+    }
+    let original = std::fs::read(dir.join("a.png")).unwrap();
+    let report = std::env::temp_dir().join(format!("bwm-{}-overwrite.csv", std::process::id()));
+
+    let output = blind_watermark(&[
+        "embed",
+        "-i",
+        dir.to_str().unwrap(),
+        "--prefix",
+        "",
+        "--convert",
+        "png",
+        "--overwrite",
+        "-s",
+        "hello",
+        "--failure-report",
+        report.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    // a.png maps to itself, c.jpg and c.jpeg to the same c.png
+    assert_eq!(std::fs::read(dir.join("a.png")).unwrap(), original);
+    assert!(dir.join("b.png").is_file());
+    assert!(!dir.join("c.png").exists());
+
+    let report_text = std::fs::read_to_string(&report).unwrap();
+    let failures: Vec<&str> = report_text.lines().skip(1).collect();
+    assert_eq!(failures.len(), 3, "{report_text}");
+    assert!(failures[0].contains("a.png") && failures[0].contains("would overwrite its input"));
+    assert!(failures[1].contains("c.jpeg") && failures[1].contains("also the output of"));
+    assert!(failures[2].contains("c.jpg") && failures[2].contains("also the output of"));
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_file(report).unwrap();
+}
+
+#[test]
+fn test_strength_2_can_be_turned_off() {
+    let dir = scratch_dir("strength-2");
+    gradient(64).save(dir.join("in.png")).unwrap();
+    let config = dir.join("config.toml");
+    std::fs::write(&config, "[profiles.print]\nstrength_2 = 30\n").unwrap();
+
+    let embed = |extra: &[&str]| -> Value {
+        let (input, output) = (dir.join("in.png"), dir.join("out.png"));
+        let mut args = vec![
+            "--output-format",
+            "json",
+            "--config",
+            config.to_str().unwrap(),
+            "--profile",
+            "print",
+            "embed",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-s",
+            "hi",
+        ];
+        args.extend(extra);
+        let output = blind_watermark(&args);
+        assert!(output.status.success(), "{output:?}");
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+    assert_eq!(embed(&[])["strength_2"], 30);
+    assert_eq!(embed(&["--no-strength-2"])["strength_2"], Value::Null);
+    assert!(
+        !blind_watermark(&["embed", "--strength-2", "20", "--no-strength-2"])
+            .status
+            .success()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_fingerprint_rejects_colliding_names() {
+    let dir = scratch_dir("fingerprint");
+    let input = dir.join("photo.png");
+    gradient(64).save(&input).unwrap();
+    let recipients = dir.join("recipients.csv");
+    std::fs::write(&recipients, "id,team\nc1,news\nc2,sport\nc3,news\n").unwrap();
+    let out = dir.join("out");
+
+    let output = blind_watermark(&[
+        "fingerprint",
+        "-i",
+        input.to_str().unwrap(),
+        "--recipients",
+        recipients.to_str().unwrap(),
+        "-t",
+        "{id}",
+        "--name",
+        "{team}",
+        "--out-dir",
+        out.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rows 1 and 3"), "{stderr}");
+    // Nothing was written
+    assert_eq!(std::fs::read_dir(&out).map_or(0, |dir| dir.count()), 0);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_fingerprint_rejects_duplicate_payloads() {
+    let dir = scratch_dir("fingerprint_payloads");
+    let input = dir.join("photo.png");
+    gradient(64).save(&input).unwrap();
+    let recipients = dir.join("recipients.csv");
+    std::fs::write(&recipients, "id,team\nc1,news\nc2,sport\nc3,news\n").unwrap();
+    let out = dir.join("out");
+
+    let output = blind_watermark(&[
+        "fingerprint",
+        "-i",
+        input.to_str().unwrap(),
+        "--recipients",
+        recipients.to_str().unwrap(),
+        "-t",
+        "{team}",
+        "--name",
+        "{id}",
+        "--out-dir",
+        out.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rows 1 and 3"), "{stderr}");
+    assert_eq!(std::fs::read_dir(&out).map_or(0, |dir| dir.count()), 0);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_scheme_selection_reaches_manifest_and_audit() {
+    let dir = scratch_dir("scheme");
+    let (input, output, manifest) = (
+        dir.join("in.png"),
+        dir.join("out.png"),
+        dir.join("manifest.json"),
+    );
+    gradient(64).save(&input).unwrap();
+
+    let embed = blind_watermark(&[
+        "embed",
+        "-i",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "hi",
+        "--scheme",
+        "spread_spectrum",
+        "--scheme-key",
+        "5",
+        "--manifest",
+        manifest.to_str().unwrap(),
+    ]);
+    assert!(embed.status.success(), "{embed:?}");
+    let recorded: Value = serde_json::from_slice(&std::fs::read(&manifest).unwrap()).unwrap();
+    assert_eq!(
+        recorded["entries"][0]["config"]["scheme"], "spread_spectrum",
+        "{recorded}"
+    );
+
+    let audit = |extra: &[&str]| {
+        let mut args = vec!["audit", manifest.to_str().unwrap()];
+        args.extend(extra);
+        blind_watermark(&args).status.success()
+    };
+    assert!(!audit(&[]));
+    assert!(audit(&["--scheme-key", "5"]));
+
+    let extract = blind_watermark(&[
+        "--output-format",
+        "json",
+        "extract",
+        "-i",
+        output.to_str().unwrap(),
+        "-l",
+        "16",
+        "--scheme",
+        "spread_spectrum",
+        "--scheme-key",
+        "5",
+    ]);
+    assert!(extract.status.success(), "{extract:?}");
+    let record: Value = serde_json::from_slice(&extract.stdout).unwrap();
+    assert_eq!(record["payload"], "hi");
+
+    // A keyed scheme without its key is refused
+    let embed = blind_watermark(&[
+        "embed",
+        "-i",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+        "-s",
+        "hi",
+        "--scheme",
+        "spread_spectrum",
+    ]);
+    assert!(!embed.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
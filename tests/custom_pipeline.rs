@@ -0,0 +1,108 @@
+use bitvec::prelude::*;
+use blind_watermark::config::WatermarkMode;
+use blind_watermark::pipeline::{BlockEmbedder, DomainTransform, HaarDwt, Pipeline};
+use blind_watermark::transform::block4::{Mat4, dct4, idct4};
+use blind_watermark::transform::dwt::{Bands, haar_dwt_2d_into, haar_idwt_2d_into};
+use faer::Mat;
+use image::{DynamicImage, RgbImage, Rgba32FImage};
+
+/// Haar DWT of the green channel only, without color conversion
+struct GreenHaar;
+
+impl DomainTransform for GreenHaar {
+    type Scalar = f32;
+    type State = (Rgba32FImage, Bands<f32>);
+
+    fn forward(&self, img: &Rgba32FImage) -> (Vec<Mat<f32>>, Self::State) {
+        let green = Mat::from_fn(img.height() as usize, img.width() as usize, |r, c| {
+            img.get_pixel(c as u32, r as u32)[1]
+        });
+        let mut bands = (Mat::new(), Mat::new(), Mat::new(), Mat::new());
+        haar_dwt_2d_into(green.as_ref(), &mut bands);
+        let ll = std::mem::replace(&mut bands.0, Mat::new());
+        (vec![ll], (img.clone(), bands))
+    }
+
+    fn inverse(&self, planes: Vec<Mat<f32>>, (mut img, bands): Self::State) -> Rgba32FImage {
+        let (_, hl, lh, hh) = bands;
+        let mut green = Mat::new();
+        haar_idwt_2d_into(
+            planes[0].as_ref(),
+            hl.as_ref(),
+            lh.as_ref(),
+            hh.as_ref(),
+            &mut green,
+        );
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            pixel[1] = green[(y as usize, x as usize)];
+        }
+        img
+    }
+}
+
+/// Quantization of the DC coefficient of the DCT, without SVD
+struct DcQuantization {
+    step: f32,
+}
+
+impl BlockEmbedder<f32> for DcQuantization {
+    fn embed_bit(&self, block: &Mat4, bit: bool, _position: usize) -> Mat4 {
+        let mut dct = dct4(block);
+        let offset = if bit { 0.75 } else { 0.25 };
+        dct[0][0] = ((dct[0][0] / self.step).floor() + offset) * self.step;
+        idct4(&dct)
+    }
+
+    fn extract_bit(&self, block: &Mat4, _position: usize) -> bool {
+        let dc = dct4(block)[0][0] / self.step;
+        dc - dc.floor() > 0.5
+    }
+}
+
+fn image() -> DynamicImage {
+    DynamicImage::from(RgbImage::from_fn(128, 96, |x, y| {
+        image::Rgb([(x * 2) as u8, (60 + y) as u8, ((x ^ y) % 256) as u8])
+    }))
+}
+
+#[test]
+fn test_custom_transform_and_embedder() {
+    let img = image();
+    let watermark = bits![u8, Lsb0; 1, 1, 0, 1, 0, 0, 0, 1, 1, 0];
+    let pipeline = Pipeline::new(
+        GreenHaar,
+        DcQuantization { step: 0.1 },
+        WatermarkMode::Strategy(5),
+    );
+
+    let watermarked = DynamicImage::from(pipeline.embed(&img, watermark).unwrap()).to_rgb8();
+    // Only the green channel carries the watermark
+    for (a, b) in img.to_rgb8().pixels().zip(watermarked.pixels()) {
+        assert_eq!((a[0], a[2]), (b[0], b[2]));
+    }
+    assert_eq!(
+        pipeline
+            .extract_watermark_bits(&watermarked.into(), watermark.len())
+            .unwrap(),
+        watermark
+    );
+}
+
+#[test]
+fn test_builtin_transform_with_custom_embedder() {
+    let img = image();
+    let watermark = bits![u8, Lsb0; 0, 1, 1, 0, 1];
+    let pipeline = Pipeline::new(
+        HaarDwt::default(),
+        DcQuantization { step: 0.1 },
+        WatermarkMode::Normal,
+    );
+
+    let watermarked = DynamicImage::from(pipeline.embed(&img, watermark).unwrap()).to_rgb8();
+    assert_eq!(
+        pipeline
+            .extract_watermark_bits(&watermarked.into(), watermark.len())
+            .unwrap(),
+        watermark
+    );
+}
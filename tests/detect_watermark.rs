@@ -0,0 +1,84 @@
+use bitvec::prelude::*;
+use blind_watermark::prelude::*;
+use image::ImageReader;
+
+#[test]
+fn test_detect_watermark() {
+    let img = ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 256, 256);
+    let watermark = bits![u8, Lsb0; 1, 1, 0, 1, 0, 0, 1, 0];
+    let config = config_from_seed(Some(0)).unwrap();
+    let watermarked = embed_watermark_image(&img, watermark, &config);
+
+    let unmarked = detect_watermark_image(&img, None, &config, 1e-6).unwrap();
+    assert!(!unmarked.present, "{unmarked:?}");
+
+    let blind = detect_watermark_image(&watermarked, None, &config, 1e-6).unwrap();
+    assert!(blind.present, "{blind:?}");
+
+    let informed = detect_watermark_image(&watermarked, Some(watermark), &config, 1e-6).unwrap();
+    assert!(informed.present, "{informed:?}");
+    assert!(informed.score > blind.score);
+}
+
+#[test]
+fn test_detect_watermark_rejects_invalid_input() {
+    let path =
+        std::env::temp_dir().join(format!("blind_watermark_detect_{}.png", std::process::id()));
+    ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 16, 16)
+        .save(&path)
+        .unwrap();
+    let config = WatermarkConfig::default();
+
+    let long = bitvec![u8, Lsb0; 1; 40];
+    assert!(detect_watermark_with_config(&path, Some(&long), &config, 1e-6).is_err());
+    assert!(detect_watermark_with_config(&path, None, &config, 0.0).is_err());
+    assert!(detect_watermark_with_config(&path, None, &config, 1.5).is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_detect_spread_spectrum() {
+    let img = ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 256, 256);
+    let watermark = b"ss".view_bits::<Lsb0>();
+    let config = WatermarkConfigBuilder::default()
+        .mode(WatermarkMode::Strategy(2))
+        .scheme(EmbeddingScheme::SpreadSpectrum { key: 5 })
+        .build()
+        .unwrap();
+    let watermarked = embed_watermark_image(&img, watermark, &config);
+
+    let marked = detect_watermark_image(&watermarked, Some(watermark), &config, 1e-6).unwrap();
+    assert!(marked.present, "{marked:?}");
+    assert_eq!(marked.samples, 3 * 32 * 32);
+
+    let unmarked = detect_watermark_image(&img, Some(watermark), &config, 1e-6).unwrap();
+    assert!(!unmarked.present, "{unmarked:?}");
+
+    let wrong_key = WatermarkConfig {
+        scheme: EmbeddingScheme::SpreadSpectrum { key: 6 },
+        ..config.clone()
+    };
+    let wrong = detect_watermark_image(&watermarked, Some(watermark), &wrong_key, 1e-6).unwrap();
+    assert!(!wrong.present, "{wrong:?}");
+
+    // Correlation needs the signs of the bits, and other schemes are not scored
+    assert!(detect_watermark_image(&watermarked, None, &config, 1e-6).is_err());
+    let dither = WatermarkConfig {
+        scheme: EmbeddingScheme::DitherModulation { key: 5 },
+        ..config
+    };
+    assert!(detect_watermark_image(&watermarked, Some(watermark), &dither, 1e-6).is_err());
+}
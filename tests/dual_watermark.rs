@@ -0,0 +1,86 @@
+use bitvec::prelude::*;
+use blind_watermark::dual::DualWatermark;
+use blind_watermark::fragile::Region;
+use blind_watermark::prelude::*;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, Rgb};
+use std::io::Cursor;
+
+fn png_round_trip(img: &DynamicImage) -> DynamicImage {
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png).unwrap();
+    image::load_from_memory(buffer.get_ref()).unwrap()
+}
+
+#[test]
+fn test_verify_reports_owner_and_tampering() {
+    let img = image::open("tests/example.jpg")
+        .unwrap()
+        .resize(480, 480, FilterType::Triangle);
+    let owner = "Newsroom";
+    let dual = DualWatermark::new(
+        WatermarkConfigBuilder::default()
+            .mode(WatermarkMode::Strategy(3))
+            .build()
+            .unwrap(),
+        FragileConfigBuilder::default().key(5).build().unwrap(),
+    );
+    let wm_len = owner.len() * 8;
+    let marked = png_round_trip(
+        &dual
+            .embed(&img, owner.as_bytes().view_bits::<Lsb0>())
+            .unwrap(),
+    );
+
+    // The fragile watermark does not disturb the robust one, and vice versa
+    let robust_only =
+        embed_watermark_image(&img, owner.as_bytes().view_bits::<Lsb0>(), &dual.robust);
+    assert_eq!(
+        extract_watermark_image(&marked, wm_len, &dual.robust),
+        extract_watermark_image(&robust_only, wm_len, &dual.robust)
+    );
+
+    let verification = dual.verify(&marked, wm_len).unwrap();
+    assert_eq!(verification.owner_string().as_deref(), Some(owner));
+    assert!(verification.is_intact());
+    assert_eq!(
+        verification.to_string(),
+        r#"owner = "Newsroom", tampered regions = none"#
+    );
+
+    // Paint over a block-aligned rectangle: the owner survives, the edit is located
+    let mut edited = marked.to_rgb8();
+    for y in 96..144 {
+        for x in 160..240 {
+            edited.put_pixel(x, y, Rgb([235, 220, 40]));
+        }
+    }
+    let verification = dual
+        .verify(&png_round_trip(&edited.into()), wm_len)
+        .unwrap();
+    assert_eq!(verification.owner_string().as_deref(), Some(owner));
+    assert_eq!(
+        verification.tampered_regions,
+        vec![Region {
+            x: 160,
+            y: 96,
+            width: 80,
+            height: 48
+        }]
+    );
+}
+
+#[test]
+fn test_verify_rejects_cropped_images() {
+    let img = image::open("tests/example.jpg").unwrap();
+    let dual = DualWatermark::new(
+        WatermarkConfig::default(),
+        FragileConfigBuilder::default().key(5).build().unwrap(),
+    );
+    let owner = b"Newsroom".view_bits::<Lsb0>();
+    let cropped = img.crop_imm(0, 0, 16, 16);
+    assert!(dual.embed(&cropped, owner).is_err());
+    assert!(dual.embed(&img, bits![u8, Lsb0;]).is_err());
+    assert!(dual.verify(&cropped, owner.len()).is_err());
+    assert!(dual.verify(&cropped, 0).is_err());
+}
@@ -0,0 +1,45 @@
+use bitvec::prelude::*;
+use blind_watermark::manifest::{Manifest, ManifestEntry, audit_entry};
+use blind_watermark::prelude::*;
+use image::ImageReader;
+
+#[test]
+fn test_manifest_audit() {
+    let dir = std::env::temp_dir().join(format!("blind_watermark_manifest_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, output, manifest) = (
+        dir.join("input.png"),
+        dir.join("output.png"),
+        dir.join("manifest.json"),
+    );
+
+    ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 256, 256)
+        .save(&input)
+        .unwrap();
+    let watermark = b"wm".view_bits::<Lsb0>();
+    let config = config_from_seed(Some(3)).unwrap();
+    embed_watermark_bits_with_config(&input, &output, watermark, &config).unwrap();
+
+    let entry = ManifestEntry::record(&input, &output, "wm", watermark, &config).unwrap();
+    Manifest::new(vec![entry]).write(&manifest).unwrap();
+    let manifest = Manifest::read(&manifest).unwrap();
+    let entry = &manifest.entries[0];
+    assert_eq!(entry.bits, watermark);
+    assert!(entry.psnr > 30.0, "{}", entry.psnr);
+
+    let outcome = audit_entry(entry, Some(3), None).unwrap();
+    assert!(outcome.hash_matches && outcome.passed(), "{outcome:?}");
+    assert!(audit_entry(entry, None, None).is_err());
+
+    // Re-saving changes the file but keeps the watermark
+    let reencoded = ImageReader::open(&output).unwrap().decode().unwrap();
+    reencoded.to_rgba8().save(&output).unwrap();
+    let outcome = audit_entry(entry, Some(3), None).unwrap();
+    assert!(!outcome.hash_matches && outcome.passed(), "{outcome:?}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,25 @@
+use bitvec::prelude::*;
+use blind_watermark::metrics::psnr_rgb;
+use blind_watermark::prelude::*;
+use blind_watermark::tuning::embed_with_quality_target;
+use image::{DynamicImage, ImageReader};
+
+#[test]
+fn test_embed_with_psnr_target() {
+    let img = ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 256, 256);
+    let watermark = bits![u8, Lsb0; 1, 0, 1, 1, 0, 0, 1, 0];
+    let config = config_from_seed(Some(0)).unwrap();
+
+    let (output, chosen) =
+        embed_with_quality_target(&img, watermark, &config, QualityTarget::Psnr(42.0)).unwrap();
+
+    let reference = DynamicImage::from(img.to_rgb8()).to_rgb32f();
+    assert!(psnr_rgb(&reference, &output.to_rgb32f()) >= 42.0);
+
+    let extracted = extract_watermark_image(&output, watermark.len(), &chosen);
+    assert_eq!(extracted, watermark);
+}
@@ -0,0 +1,129 @@
+use bitvec::prelude::*;
+use blind_watermark::attacks::Attack;
+use blind_watermark::pipeline::Pipeline;
+use blind_watermark::prelude::*;
+use blind_watermark::transform::embed::extraction_confidence;
+use image::DynamicImage;
+use image::imageops::FilterType;
+
+const SCHEMES: [EmbeddingScheme; 5] = [
+    EmbeddingScheme::SvdQuantization,
+    EmbeddingScheme::SpreadSpectrum { key: 11 },
+    EmbeddingScheme::DitherModulation { key: 11 },
+    EmbeddingScheme::SpreadTransformDither { key: 11 },
+    EmbeddingScheme::RationalDither { key: 11 },
+];
+
+#[test]
+fn test_schemes_round_trip() {
+    let img = image::open("tests/example.jpg")
+        .unwrap()
+        .resize(320, 320, FilterType::Triangle);
+    let watermark = b"scheme".view_bits::<Lsb0>();
+
+    for scheme in SCHEMES {
+        let config = WatermarkConfigBuilder::default()
+            .mode(WatermarkMode::Strategy(4))
+            .scheme(scheme)
+            .build()
+            .unwrap();
+
+        let watermarked = embed_watermark_image(&img, watermark, &config);
+        assert_eq!(
+            extract_watermark_image(&watermarked, watermark.len(), &config),
+            watermark,
+            "{scheme:?}"
+        );
+
+        // Composed pipelines use the same scheme
+        let pipeline = Pipeline::from_config(&config);
+        assert_eq!(
+            DynamicImage::from(pipeline.embed(&img, watermark).unwrap()).to_rgb8(),
+            watermarked.to_rgb8(),
+            "{scheme:?}"
+        );
+
+        // Survives moderate JPEG compression
+        let compressed = Attack::Jpeg(80).apply(&watermarked).unwrap();
+        assert_eq!(
+            extract_watermark_image(&compressed, watermark.len(), &config),
+            watermark,
+            "{scheme:?} after JPEG"
+        );
+    }
+}
+
+#[test]
+fn test_schemes_need_key() {
+    let img = image::open("tests/example.jpg")
+        .unwrap()
+        .resize(320, 320, FilterType::Triangle);
+    let watermark = b"scheme".view_bits::<Lsb0>();
+
+    for (scheme, wrong) in [
+        (
+            EmbeddingScheme::SpreadSpectrum { key: 1 },
+            EmbeddingScheme::SpreadSpectrum { key: 2 },
+        ),
+        (
+            EmbeddingScheme::DitherModulation { key: 1 },
+            EmbeddingScheme::DitherModulation { key: 2 },
+        ),
+        (
+            EmbeddingScheme::SpreadTransformDither { key: 1 },
+            EmbeddingScheme::SpreadTransformDither { key: 2 },
+        ),
+        (
+            EmbeddingScheme::RationalDither { key: 1 },
+            EmbeddingScheme::RationalDither { key: 2 },
+        ),
+    ] {
+        let config = WatermarkConfigBuilder::default()
+            .scheme(scheme)
+            .build()
+            .unwrap();
+        let watermarked = embed_watermark_image(&img, watermark, &config);
+        let soft = extract_soft_bits_image(
+            &watermarked,
+            watermark.len(),
+            &WatermarkConfig {
+                scheme: wrong,
+                ..config
+            },
+        );
+        assert!(extraction_confidence(&soft) < 0.3, "{scheme:?}");
+    }
+}
+
+#[test]
+fn test_rational_dither_survives_gain() {
+    let img = image::open("tests/example.jpg")
+        .unwrap()
+        .resize(320, 320, FilterType::Triangle);
+    let watermark = b"gain".view_bits::<Lsb0>();
+    let config = WatermarkConfigBuilder::default()
+        .mode(WatermarkMode::Strategy(4))
+        .scheme(EmbeddingScheme::RationalDither { key: 11 })
+        .build()
+        .unwrap();
+    let watermarked = embed_watermark_image(&img, watermark, &config);
+
+    for gain in [0.8, 0.9, 1.1, 1.2] {
+        let attacked = Attack::Gain(gain).apply(&watermarked).unwrap();
+        assert_eq!(
+            extract_watermark_image(&attacked, watermark.len(), &config),
+            watermark,
+            "gain {gain}"
+        );
+    }
+
+    // Whereas the singular value quantization loses the watermark
+    let svd_config = WatermarkConfig {
+        scheme: EmbeddingScheme::SvdQuantization,
+        ..config
+    };
+    let watermarked = embed_watermark_image(&img, watermark, &svd_config);
+    let attacked = Attack::Gain(0.8).apply(&watermarked).unwrap();
+    let soft = extract_soft_bits_image(&attacked, watermark.len(), &svd_config);
+    assert!(extraction_confidence(&soft) < 0.5);
+}
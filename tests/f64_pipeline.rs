@@ -0,0 +1,58 @@
+use bitvec::prelude::*;
+use blind_watermark::prelude::*;
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba32FImage};
+
+/// 16-bit gradient, with values that 8 bits cannot represent
+fn image_16bit() -> DynamicImage {
+    DynamicImage::ImageRgb16(ImageBuffer::from_fn(96, 80, |x, y| {
+        Rgb([
+            (x * 683 + y * 17) as u16,
+            (y * 811 + x * 5) as u16,
+            ((x * y * 13) % 65536) as u16,
+        ])
+    }))
+}
+
+/// Largest difference between two images, per channel
+fn max_error(a: &Rgba32FImage, b: &Rgba32FImage) -> f32 {
+    a.as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f32::max)
+}
+
+fn round_trip<T: blind_watermark::scalar::Scalar>(img: &DynamicImage) -> Rgba32FImage {
+    let ycbcr: YCrBrAMat<T> = img.to_rgba32f().into();
+    ycbcr.add_padding().dwt().idwt().remove_padding().into()
+}
+
+#[test]
+fn test_f64_reduces_round_off() {
+    let img = image_16bit();
+    let original = img.to_rgba32f();
+    let error_f32 = max_error(&original, &round_trip::<f32>(&img));
+    let error_f64 = max_error(&original, &round_trip::<f64>(&img));
+    assert!(error_f64 < error_f32, "{error_f64} >= {error_f32}");
+    // Well below the step of a 16-bit sample
+    assert!(error_f64 < 1e-6, "{error_f64}");
+}
+
+#[test]
+fn test_f64_embed_extract_16bit() {
+    let img = image_16bit();
+    let watermark = bits![u8, Lsb0; 1, 0, 1, 1, 0, 0, 1, 0, 1, 1];
+    let config = config_from_seed(Some(3)).unwrap();
+
+    let watermarked = DynamicImage::from(embed_watermark_image_as::<f64>(&img, watermark, &config));
+    let archived = DynamicImage::from(watermarked.to_rgb16());
+    assert_eq!(
+        extract_watermark_image_as::<f64>(&archived, watermark.len(), &config),
+        watermark
+    );
+    // The precision does not change the bitstream format
+    assert_eq!(
+        extract_watermark_image(&archived, watermark.len(), &config),
+        watermark
+    );
+}
@@ -0,0 +1,34 @@
+use bitvec::prelude::*;
+use blind_watermark::fingerprint::{Fingerprinter, PayloadTemplate};
+use blind_watermark::prelude::*;
+use image::ImageReader;
+use std::collections::HashMap;
+
+#[test]
+fn test_fingerprint_copies() {
+    let img = ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 256, 256);
+    let config = config_from_seed(Some(1)).unwrap();
+    let fingerprinter = Fingerprinter::new(&img, &config);
+    let template: PayloadTemplate = "id-{customer_id}".parse().unwrap();
+
+    for customer_id in ["c1", "c2"] {
+        let recipient = HashMap::from([("customer_id", customer_id)]);
+        let payload = template
+            .render(|name| recipient.get(name).copied())
+            .unwrap();
+        let watermark = payload.as_bytes().view_bits::<Lsb0>();
+
+        let copy = fingerprinter.render(watermark).unwrap();
+        // Same result as embedding from scratch
+        assert_eq!(copy, embed_watermark_image(&img, watermark, &config));
+        let extracted = extract_watermark_image(&copy, watermark.len(), &config);
+        assert_eq!(extracted, watermark);
+    }
+
+    let too_long = bitvec![u8, Lsb0; 1; fingerprinter.capacity() + 1];
+    assert!(fingerprinter.render(&too_long).is_err());
+}
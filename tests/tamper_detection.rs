@@ -0,0 +1,66 @@
+use blind_watermark::attacks::Attack;
+use blind_watermark::fragile::REGION_SIZE;
+use blind_watermark::prelude::*;
+use image::{DynamicImage, ImageFormat, Rgb};
+use std::io::Cursor;
+
+fn png_round_trip(img: &DynamicImage) -> DynamicImage {
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png).unwrap();
+    image::load_from_memory(buffer.get_ref()).unwrap()
+}
+
+#[test]
+fn test_tamper_map_locates_edits() {
+    let img = image::open("tests/example.jpg").unwrap();
+    let config = FragileConfigBuilder::default().key(21).build().unwrap();
+    let marked = png_round_trip(&embed_fragile_image(&img, &config));
+
+    let clean = tamper_map_image(&marked, &config);
+    assert!(!clean.is_tampered());
+
+    // Paint over a rectangle
+    let (left, top, width, height) = (200, 120, 64, 40);
+    let mut edited = marked.to_rgb8();
+    for y in top..top + height {
+        for x in left..left + width {
+            edited.put_pixel(x, y, Rgb([30, 140, 60]));
+        }
+    }
+    let map = tamper_map_image(&png_round_trip(&edited.into()), &config);
+    let covering = |(row, col): (usize, usize)| {
+        let (y, x) = ((row * REGION_SIZE) as u32, (col * REGION_SIZE) as u32);
+        y + REGION_SIZE as u32 > top
+            && y < top + height
+            && x + REGION_SIZE as u32 > left
+            && x < left + width
+    };
+    let tampered = map.tampered_blocks();
+    assert!(tampered.iter().all(|&block| covering(block)));
+    // Blocks painted over entirely
+    let inside = (width / 8) as usize * (height / 8) as usize;
+    assert!(
+        tampered.len() >= inside * 9 / 10,
+        "{} of {inside}",
+        tampered.len()
+    );
+
+    let mask = map.to_image();
+    assert_eq!(mask.dimensions(), marked.to_rgb8().dimensions());
+    assert_eq!(mask.get_pixel(left + 10, top + 10).0, [255]);
+    assert_eq!(mask.get_pixel(10, 10).0, [0]);
+}
+
+#[test]
+fn test_tamper_map_rejects_processing_and_wrong_key() {
+    let img = image::open("tests/example.jpg").unwrap();
+    let config = FragileConfigBuilder::default().key(21).build().unwrap();
+    let marked = embed_fragile_image(&img, &config);
+
+    let compressed = Attack::Jpeg(90).apply(&marked).unwrap();
+    assert!(tamper_map_image(&compressed, &config).tampered_fraction() > 0.9);
+
+    let wrong_key = FragileConfigBuilder::default().key(22).build().unwrap();
+    assert!(tamper_map_image(&marked, &wrong_key).tampered_fraction() > 0.9);
+    assert!(tamper_map_image(&img, &config).tampered_fraction() > 0.9);
+}
@@ -0,0 +1,44 @@
+use blind_watermark::fingerprint::Fingerprinter;
+use blind_watermark::prelude::*;
+use blind_watermark::tardos::TardosCode;
+use image::{DynamicImage, ImageReader, RgbImage};
+
+#[test]
+fn test_accuse_averaged_copies() {
+    let img = ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 256, 256);
+    let config = config_from_seed(Some(5)).unwrap();
+    let fingerprinter = Fingerprinter::new(&img, &config);
+    let code = TardosCode::generate(10, 2, 1e-3, 11);
+    assert!(code.len() <= fingerprinter.capacity());
+
+    let (a, b) = (
+        fingerprinter.render(&code.codewords[2]).unwrap().to_rgb8(),
+        fingerprinter.render(&code.codewords[7]).unwrap().to_rgb8(),
+    );
+    // Two recipients average their copies
+    let averaged = RgbImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        image::Rgb(std::array::from_fn(|c| {
+            ((pa[c] as u16 + pb[c] as u16).div_ceil(2)) as u8
+        }))
+    });
+
+    let soft_bits = extract_soft_bits_image(&DynamicImage::from(averaged), code.len(), &config);
+    let accusations = code.accuse(&soft_bits);
+    assert!(
+        [2, 7].contains(&accusations[0].recipient),
+        "{accusations:?}"
+    );
+    assert!(accusations[0].accused, "{accusations:?}");
+    assert!(
+        accusations
+            .iter()
+            .filter(|a| a.accused)
+            .all(|a| [2, 7].contains(&a.recipient)),
+        "{accusations:?}"
+    );
+}
@@ -0,0 +1,41 @@
+use bitvec::prelude::*;
+use blind_watermark::prelude::*;
+use blind_watermark::tiled::TiledEngine;
+use image::ImageReader;
+
+#[test]
+fn test_embed_png_by_strips() {
+    let dir = std::env::temp_dir().join(format!("blind_watermark_tiled_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, output) = (dir.join("input.png"), dir.join("output.png"));
+
+    let img = ImageReader::open("tests/example.jpg")
+        .unwrap()
+        .decode()
+        .unwrap()
+        .crop_imm(0, 0, 251, 203);
+    img.save(&input).unwrap();
+    let watermark = b"tile".view_bits::<Lsb0>();
+    let config = config_from_seed(Some(2)).unwrap();
+
+    let engine = TiledEngine::new(64);
+    engine
+        .embed_png(&input, &output, watermark, &config)
+        .unwrap();
+    let streamed = ImageReader::open(&output).unwrap().decode().unwrap();
+    assert_eq!(streamed, embed_watermark_image(&img, watermark, &config));
+
+    let soft_bits = engine
+        .extract_soft_bits_png(&output, watermark.len(), &config)
+        .unwrap();
+    assert_eq!(
+        soft_bits,
+        extract_soft_bits_image(&streamed, watermark.len(), &config)
+    );
+    assert_eq!(
+        blind_watermark::transform::embed::soft_to_hard_bits(&soft_bits),
+        watermark
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}